@@ -0,0 +1,185 @@
+//! Query benchmarks over a generated 10k-message in-memory index.
+//!
+//! Run with `cargo bench --bench find`. Criterion warms up, samples each
+//! case, and compares it against the previous run, so a regression shows up
+//! as a reported change. Save a named baseline with
+//! `cargo bench --bench find -- --save-baseline main` and compare a branch
+//! against it with `-- --baseline main`.
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use mmem::index::{init_schema, replace_messages_tx, upsert_session_tx};
+use mmem::model::{MessageRecord, SessionRecord};
+use mmem::query::{FindFilters, FindScope, find_messages, find_sessions};
+use rusqlite::Connection;
+use std::hint::black_box;
+
+const SESSIONS: usize = 200;
+const MESSAGES_PER_SESSION: usize = 50;
+
+const WORDS: &[&str] = &[
+    "parser",
+    "index",
+    "query",
+    "refactor",
+    "async",
+    "error",
+    "deploy",
+    "migration",
+    "cache",
+    "schema",
+    "branch",
+    "test",
+    "review",
+    "latency",
+    "token",
+    "session",
+];
+
+fn build_db() -> Connection {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let tx = conn.transaction().expect("tx");
+    for session in 0..SESSIONS {
+        let path = format!("/bench/{session:04}.jsonl");
+        let messages: Vec<MessageRecord> = (0..MESSAGES_PER_SESSION)
+            .map(|turn| MessageRecord {
+                turn_index: turn as i64,
+                role: Some(if turn % 2 == 0 { "user" } else { "assistant" }.to_string()),
+                timestamp: Some(format!(
+                    "2024-01-{:02}T00:00:{:02}Z",
+                    session % 28 + 1,
+                    turn
+                )),
+                text: (0..12)
+                    .map(|word| WORDS[(session * 7 + turn * 3 + word) % WORDS.len()])
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                tools: Vec::new(),
+                line: None,
+            })
+            .collect();
+        let content = messages
+            .iter()
+            .map(|message| message.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let record = SessionRecord {
+            path: path.clone(),
+            mtime: 0,
+            size: 0,
+            hash: None,
+            created_at: None,
+            last_message_at: Some(format!("2024-01-{:02}T00:00:00Z", session % 28 + 1)),
+            agent: Some(format!("agent-{}", session % 4)),
+            workspace: Some(format!("/ws/{}", session % 10)),
+            title: Some(format!("session {session}")),
+            message_count: MESSAGES_PER_SESSION as i64,
+            user_message_count: (MESSAGES_PER_SESSION / 2) as i64,
+            assistant_message_count: (MESSAGES_PER_SESSION / 2) as i64,
+            snippet: String::new(),
+            content,
+            repo_root: None,
+            repo_name: Some(format!("repo-{}", session % 5)),
+            branch: Some("main".to_string()),
+            system_prompt: None,
+            preview: None,
+            parent_path: None,
+            commands: None,
+        };
+        upsert_session_tx(&tx, &record).expect("session");
+        replace_messages_tx(&tx, &path, &messages).expect("messages");
+    }
+    tx.commit().expect("commit");
+    conn
+}
+
+fn filters() -> (FindFilters, FindFilters) {
+    let unfiltered = FindFilters {
+        limit: 20,
+        ..Default::default()
+    };
+    let filtered = FindFilters {
+        agent: Some("agent-1".to_string()),
+        repo: Some("repo-3".to_string()),
+        role: Some("user".to_string()),
+        after: Some("2024-01-05T00:00:00Z".to_string()),
+        limit: 20,
+        ..Default::default()
+    };
+    (unfiltered, filtered)
+}
+
+/// The unfiltered case takes the fast path without NULL-guard predicates.
+fn bench_find_messages(c: &mut Criterion) {
+    let conn = build_db();
+    let (unfiltered, filtered) = filters();
+    // Context is loaded per hit; its statement is prepared once per query.
+    let context = FindFilters {
+        around: 3,
+        limit: 100,
+        ..Default::default()
+    };
+
+    let mut group = c.benchmark_group("find_messages");
+    for (name, filters) in [
+        ("unfiltered", &unfiltered),
+        ("filtered", &filtered),
+        ("context", &context),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter(|| find_messages(&conn, black_box("parser"), filters).expect("find"))
+        });
+    }
+    group.finish();
+}
+
+/// `--after` cutoffs from selective to broad. Cutoffs keeping under a
+/// quarter of the messages (day 27 and 24 here) pre-filter message ids by
+/// timestamp; broader ones fall back to filtering FTS matches, so the
+/// timings show what the pre-filter saves on time-ranged queries.
+fn bench_find_messages_after(c: &mut Criterion) {
+    let conn = build_db();
+
+    let mut group = c.benchmark_group("find_messages/after");
+    for day in [27, 24, 15, 1] {
+        let filters = FindFilters {
+            after: Some(format!("2024-01-{day:02}T00:00:00Z")),
+            limit: 20,
+            ..Default::default()
+        };
+        group.bench_with_input(BenchmarkId::from_parameter(day), &filters, |b, filters| {
+            b.iter(|| find_messages(&conn, black_box("parser"), filters).expect("find"))
+        });
+    }
+    group.finish();
+}
+
+fn bench_find_sessions(c: &mut Criterion) {
+    let conn = build_db();
+    let (unfiltered, filtered) = filters();
+    let unfiltered = FindFilters {
+        scope: FindScope::Session,
+        ..unfiltered
+    };
+    let filtered = FindFilters {
+        scope: FindScope::Session,
+        ..filtered
+    };
+
+    let mut group = c.benchmark_group("find_sessions");
+    for (name, filters) in [("unfiltered", &unfiltered), ("filtered", &filtered)] {
+        group.bench_function(name, |b| {
+            b.iter(|| find_sessions(&conn, black_box("parser"), filters).expect("find"))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_find_messages,
+    bench_find_messages_after,
+    bench_find_sessions
+);
+criterion_main!(benches);
@@ -0,0 +1,159 @@
+//! Optional in-memory result cache for repeated searches.
+//!
+//! Enabled with the `query-cache` cargo feature. Intended for long-lived
+//! embedders (servers, TUIs) that run the same queries repeatedly against
+//! one connection; the CLI runs a single query per process and never uses it.
+//!
+//! # Invalidation
+//!
+//! Every index mutation in this process bumps
+//! [`crate::index::write_generation`]; commits from other connections,
+//! including a separate `mmem index` process, change SQLite's
+//! `PRAGMA data_version` on the session's connection. A [`QuerySession`]
+//! remembers both values its entries were computed at and drops the whole
+//! cache as soon as either moves, so results are never served across an
+//! upsert, rename, or removal.
+
+use crate::index::write_generation;
+use crate::model::{MessageHit, SessionHit};
+use crate::query::{FindFilters, QueryError, find_messages, find_sessions};
+use rusqlite::Connection;
+use std::collections::{HashMap, VecDeque};
+
+type CacheKey = (String, FindFilters);
+
+/// Least-recently-used map with a fixed entry capacity.
+#[derive(Debug)]
+struct LruCache<V> {
+    capacity: usize,
+    entries: HashMap<CacheKey, V>,
+    order: VecDeque<CacheKey>,
+}
+
+impl<V: Clone> LruCache<V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn get(&mut self, key: &CacheKey) -> Option<V> {
+        let value = self.entries.get(key)?.clone();
+        self.touch(key);
+        Some(value)
+    }
+
+    fn insert(&mut self, key: CacheKey, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.insert(key.clone(), value).is_some() {
+            self.touch(&key);
+            return;
+        }
+        self.order.push_back(key);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+
+    fn touch(&mut self, key: &CacheKey) {
+        if let Some(position) = self.order.iter().position(|entry| entry == key) {
+            if let Some(entry) = self.order.remove(position) {
+                self.order.push_back(entry);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// A connection wrapper that memoizes search results per `(query, filters)`.
+#[derive(Debug)]
+pub struct QuerySession<'conn> {
+    conn: &'conn Connection,
+    generation: u64,
+    data_version: Option<i64>,
+    sessions: LruCache<Vec<SessionHit>>,
+    messages: LruCache<Vec<MessageHit>>,
+}
+
+impl<'conn> QuerySession<'conn> {
+    /// Create a session caching up to `capacity` result sets per scope.
+    pub fn new(conn: &'conn Connection, capacity: usize) -> Self {
+        Self {
+            conn,
+            generation: write_generation(),
+            data_version: None,
+            sessions: LruCache::new(capacity),
+            messages: LruCache::new(capacity),
+        }
+    }
+
+    /// Cached equivalent of [`find_sessions`].
+    pub fn find_sessions(
+        &mut self,
+        query: &str,
+        filters: &FindFilters,
+    ) -> Result<Vec<SessionHit>, QueryError> {
+        self.invalidate_if_stale()?;
+        let key = (query.to_string(), filters.clone());
+        if let Some(hits) = self.sessions.get(&key) {
+            return Ok(hits);
+        }
+        let hits = find_sessions(self.conn, query, filters)?;
+        self.sessions.insert(key, hits.clone());
+        Ok(hits)
+    }
+
+    /// Cached equivalent of [`find_messages`].
+    pub fn find_messages(
+        &mut self,
+        query: &str,
+        filters: &FindFilters,
+    ) -> Result<Vec<MessageHit>, QueryError> {
+        self.invalidate_if_stale()?;
+        let key = (query.to_string(), filters.clone());
+        if let Some(hits) = self.messages.get(&key) {
+            return Ok(hits);
+        }
+        let hits = find_messages(self.conn, query, filters)?;
+        self.messages.insert(key, hits.clone());
+        Ok(hits)
+    }
+
+    /// Number of cached result sets across both scopes.
+    pub fn cached_entries(&self) -> usize {
+        self.sessions.len() + self.messages.len()
+    }
+
+    /// Drop every cached result.
+    pub fn clear(&mut self) {
+        self.sessions.clear();
+        self.messages.clear();
+    }
+
+    fn invalidate_if_stale(&mut self) -> Result<(), QueryError> {
+        let generation = write_generation();
+        let data_version: i64 = self
+            .conn
+            .query_row("PRAGMA data_version", [], |row| row.get(0))?;
+        if generation != self.generation || self.data_version != Some(data_version) {
+            self.clear();
+            self.generation = generation;
+            self.data_version = Some(data_version);
+        }
+        Ok(())
+    }
+}
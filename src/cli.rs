@@ -2,11 +2,15 @@
 //!
 //! Defines the CLI structure for mmem commands:
 //! - `index`: Index sessions from disk
+//! - `import`: Index a custom-format transcript via a mapping spec
 //! - `find`: Search sessions and messages
 //! - `show`: Inspect tool calls in a session
+//! - `open`: Open a session file in the default application
+//! - `export`: Write a session as Markdown
 //! - `stats`: Show index statistics
 //! - `agents`: List unique agents
 //! - `doctor`: Check index health
+//! - `values`: List distinct values of a filter field
 
 use clap::{Args, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
@@ -15,6 +19,19 @@ use std::path::PathBuf;
 #[command(name = "mmem")]
 #[command(about = "Marvin session memory search", version)]
 pub struct Cli {
+    #[arg(
+        long,
+        global = true,
+        help = "Emit compact single-line JSON instead of pretty-printed JSON"
+    )]
+    pub no_pretty: bool,
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        help = "Cap displayed text at N characters instead of 160 (0 = no cap)"
+    )]
+    pub trim: Option<usize>,
     #[command(subcommand)]
     pub command: Command,
 }
@@ -23,6 +40,16 @@ pub struct Cli {
 pub enum Command {
     #[command(about = "Index sessions from disk into SQLite")]
     Index(IndexArgs),
+    #[command(
+        about = "Index transcripts in a custom JSON format via a mapping spec",
+        after_help = r#"Spec example (map.json):
+  {"messages_path": "$.conversation", "role_field": "speaker",
+   "text_field": "body", "time_field": "at", "title_path": "$.subject"}
+
+Examples:
+  mmem import --spec map.json export.json"#
+    )]
+    Import(ImportArgs),
     #[command(
         about = "Search sessions and messages",
         long_about = "Search session content. Default search is literal (safe for dates and punctuation). Use --fts for raw FTS5 syntax.",
@@ -38,17 +65,69 @@ pub enum Command {
         long_about = "Show tool calls for a session. Accepts a JSONL path or a session id prefix (the numeric prefix of the filename). Default tool filter is read.",
         after_help = r#"Examples:
   mmem show 1766632198584
-  mmem show 1766632198584 --tool write
+  mmem show 1766632198584 --tool write --tool edit
   mmem show 1766632198584 --json
   mmem show ~/.config/marvin/sessions/path/session.jsonl --extract"#,
     )]
     Show(ShowArgs),
+    #[command(
+        about = "Open a session file in the default application",
+        after_help = r#"Examples:
+  mmem open 1766632198584
+  mmem open ~/.config/marvin/sessions/path/session.jsonl"#
+    )]
+    Open(OpenArgs),
+    #[command(
+        about = "Export a session as Markdown",
+        after_help = r#"Examples:
+  mmem export 1766632198584
+  mmem export 1766632198584 --out notes.md
+  mmem export 1766632198584 --out notes.md --force
+  mmem export 1766632198584 --role-prefix --out transcript.md"#
+    )]
+    Export(ExportArgs),
     #[command(about = "Show index statistics")]
     Stats(StatsArgs),
     #[command(about = "List unique agents in the index")]
     Agents(AgentsArgs),
     #[command(about = "Check index health and configuration")]
     Doctor(DoctorArgs),
+    #[command(
+        about = "List distinct values of a filter field in the index",
+        after_help = r#"Examples:
+  mmem values agent
+  mmem values repo --json"#
+    )]
+    Values(ValuesArgs),
+    #[command(
+        about = "Clear repeated message text within each indexed session",
+        long_about = "Find messages that repeat an earlier message in the same session (re-sent context, tool output), exactly or as a near-duplicate (at least 90% of 5-word shingles shared; texts under 64 characters are never touched), and clear their stored text and search content. Works on the existing index without re-reading session files.",
+        after_help = r#"Examples:
+  mmem dedup-content
+  mmem dedup-content --json"#
+    )]
+    DedupContent(DedupContentArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ImportArgs {
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "JSON mapping spec describing the format"
+    )]
+    pub spec: PathBuf,
+    #[arg(
+        value_name = "FILE",
+        required = true,
+        help = "Transcript files to import"
+    )]
+    pub files: Vec<PathBuf>,
+    #[arg(
+        long,
+        help = "Include the leading system/developer prompt in message search"
+    )]
+    pub index_system: bool,
 }
 
 #[derive(Debug, Args)]
@@ -57,20 +136,170 @@ pub struct AgentsArgs {
     pub json: bool,
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ValuesFieldArg {
+    Agent,
+    Workspace,
+    Repo,
+    Branch,
+}
+
+#[derive(Debug, Args)]
+pub struct ValuesArgs {
+    #[arg(value_enum, help = "Field to list values for")]
+    pub field: ValuesFieldArg,
+    #[arg(long, help = "JSON output (machine-friendly)")]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct DedupContentArgs {
+    #[arg(long, help = "JSON output (machine-friendly)")]
+    pub json: bool,
+}
+
 #[derive(Debug, Args)]
 pub struct IndexArgs {
     #[arg(long, help = "Full reindex (ignore mtime/size cache)")]
     pub full: bool,
     #[arg(long, help = "Sessions root directory")]
     pub root: Option<PathBuf>,
+    #[arg(
+        long = "timestamp-field",
+        value_name = "NAME",
+        help = "Extra message timestamp field name (repeatable)"
+    )]
+    pub timestamp_fields: Vec<String>,
+    #[arg(
+        long = "title-field",
+        value_name = "NAME",
+        help = "Metadata field holding the session title (repeatable)"
+    )]
+    pub title_fields: Vec<String>,
+    #[arg(
+        long = "agent-field",
+        value_name = "NAME",
+        help = "Extra metadata field holding the agent name (repeatable)"
+    )]
+    pub agent_fields: Vec<String>,
+    #[arg(
+        long = "role-alias",
+        value_name = "FROM=TO",
+        value_parser = parse_role_alias,
+        help = "Rename a message role, e.g. human=user (repeatable)"
+    )]
+    pub role_aliases: Vec<(String, String)>,
+    #[arg(
+        long,
+        help = "Skip malformed JSONL lines instead of failing the whole file"
+    )]
+    pub lenient: bool,
+    #[cfg(feature = "json5")]
+    #[arg(
+        long,
+        help = "Accept comments and trailing commas (JSON5 style) in JSON/JSONL files"
+    )]
+    pub json5: bool,
+    #[arg(
+        long,
+        help = "Drop a code fence (and its language hint) wrapping a whole message"
+    )]
+    pub strip_code_fences: bool,
+    #[arg(
+        long,
+        help = "Include the leading system/developer prompt in message search"
+    )]
+    pub index_system: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Store the first N characters of each session for --fields content_preview"
+    )]
+    pub preview_chars: Option<usize>,
+    #[arg(
+        long,
+        help = "Index message text in place instead of keeping a copy in the FTS table (one-way)"
+    )]
+    pub external_content: bool,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "NAME",
+        help = "Rebuild the FTS index with this tokenizer (kept for later runs)"
+    )]
+    pub tokenizer: Option<TokenizerArg>,
+    #[arg(
+        long,
+        conflicts_with_all = ["external_content", "tokenizer"],
+        help = "Show what would be indexed, skipped, or removed without writing"
+    )]
+    pub dry_run: bool,
+    #[arg(
+        long,
+        help = "Keep index entries for session files that are no longer on disk"
+    )]
+    pub no_remove: bool,
+    #[arg(
+        long,
+        help = "Hash files with unchanged mtime/size too, re-indexing any whose content changed"
+    )]
+    pub verify_hash: bool,
     #[arg(long, help = "JSON output (machine-friendly)")]
     pub json: bool,
 }
 
+fn parse_role_alias(value: &str) -> Result<(String, String), String> {
+    let (from, to) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected FROM=TO, got {value:?}"))?;
+    let (from, to) = (from.trim(), to.trim());
+    if from.is_empty() || to.is_empty() {
+        return Err(format!("expected FROM=TO, got {value:?}"));
+    }
+    Ok((from.to_lowercase(), to.to_lowercase()))
+}
+
+fn parse_markers(value: &str) -> Result<(String, String), String> {
+    let (open, close) = value
+        .split_once(',')
+        .ok_or_else(|| format!("expected OPEN,CLOSE, got {value:?}"))?;
+    Ok((open.to_string(), close.to_string()))
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum TokenizerArg {
+    /// Whole words (the default)
+    Unicode61,
+    /// Three-character substrings; finds words inside unspaced CJK text
+    Trigram,
+}
+
 #[derive(Debug, Clone, Copy, ValueEnum)]
 pub enum FindScopeArg {
     Session,
     Message,
+    /// User questions only, best match per session
+    Question,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FindSortArg {
+    /// Best match first
+    Relevance,
+    /// Most recent first
+    Newest,
+    /// Least recent first
+    Oldest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FindFormatArg {
+    Text,
+    Json,
+    Jsonl,
+    Markdown,
+    /// Aligned columns of the selected fields, sized to the terminal
+    Table,
 }
 
 #[derive(Debug, Args)]
@@ -91,26 +320,232 @@ pub struct FindArgs {
     pub repo: Option<String>,
     #[arg(long, help = "Filter by git branch")]
     pub branch: Option<String>,
-    #[arg(long, help = "Filter by message role (user/assistant)")]
+    #[arg(
+        long,
+        value_name = "SUBSTR",
+        help = "Filter to session paths containing SUBSTR"
+    )]
+    pub path_contains: Option<String>,
+    #[arg(
+        long,
+        value_name = "SUBSTR",
+        help = "Filter to sessions that ran a shell command containing SUBSTR"
+    )]
+    pub command_contains: Option<String>,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Filter to sessions with at least N user and N assistant messages"
+    )]
+    pub min_exchanges: Option<u32>,
+    #[arg(long, value_name = "AGENT", help = "Exclude sessions from this agent")]
+    pub not_agent: Option<String>,
+    #[arg(
+        long,
+        value_name = "WORKSPACE",
+        help = "Exclude sessions from this workspace"
+    )]
+    pub not_workspace: Option<String>,
+    #[arg(
+        long,
+        value_name = "REPO",
+        help = "Exclude sessions from this repo (name or root)"
+    )]
+    pub not_repo: Option<String>,
+    #[arg(
+        long,
+        help = "Filter by message role(s), comma-separated (user/assistant/system/developer/tool)"
+    )]
     pub role: Option<String>,
-    #[arg(long, help = "Include assistant messages (default: user only)")]
+    #[arg(
+        long,
+        help = "Include assistant, system, developer, and tool messages (default: user only)"
+    )]
     pub include_assistant: bool,
     #[arg(long, default_value_t = 0, help = "Context messages around match")]
     pub around: usize,
-    #[arg(long, value_enum, default_value_t = FindScopeArg::Message, help = "Search scope")]
-    pub scope: FindScopeArg,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Max characters per context message (default: 160)"
+    )]
+    pub context_chars: Option<usize>,
+    #[arg(
+        long,
+        value_name = "BOOL",
+        default_value_t = true,
+        action = clap::ArgAction::Set,
+        help = "Include the matched turn in its own --around context"
+    )]
+    pub include_context_self: bool,
+    #[arg(
+        long,
+        value_name = "QUERY",
+        help = "Only match messages after the first turn in the same session matching QUERY"
+    )]
+    pub after_match: Option<String>,
+    #[arg(
+        long,
+        value_enum,
+        help = "Search scope [default: message, or session with --any-field]"
+    )]
+    pub scope: Option<FindScopeArg>,
     #[arg(long, default_value_t = 5, help = "Max results to return")]
     pub limit: usize,
-    #[arg(long, help = "Use raw FTS5 query syntax (advanced)")]
+    #[arg(long, value_enum, default_value_t = FindSortArg::Relevance, help = "Result order")]
+    pub sort: FindSortArg,
+    #[arg(long, help = "Reverse the result order")]
+    pub reverse: bool,
+    #[arg(
+        long,
+        value_name = "AGENT",
+        help = "Rank this agent's results first among similarly relevant ones (no filtering)"
+    )]
+    pub prefer_agent: Option<String>,
+    #[arg(
+        long,
+        help = "Treat files linked by continuation metadata as one session"
+    )]
+    pub merge_continuations: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Max message hits from any one session (message scope)"
+    )]
+    pub per_session_limit: Option<usize>,
+    #[arg(
+        long,
+        help = "Use raw FTS5 query syntax (advanced; searches all roles unless --role is set)"
+    )]
     pub fts: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        num_args = 0..=1,
+        default_missing_value = "10",
+        conflicts_with = "fts",
+        help = "Match only where the query words are within N tokens of each other (default: 10)"
+    )]
+    pub near: Option<usize>,
+    #[arg(
+        long,
+        help = "Also match sessions with the query in agent, workspace, repo, branch, title or path"
+    )]
+    pub any_field: bool,
+    #[arg(
+        long,
+        help = "Keep common words like \"the\" and \"and\" in literal queries"
+    )]
+    pub no_stopwords: bool,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        conflicts_with = "no_stopwords",
+        help = "Replace the default stopword list (comma-separated)"
+    )]
+    pub stopwords: Option<Vec<String>>,
     #[arg(long, conflicts_with = "jsonl", help = "JSON array output (machine-friendly)")]
     pub json: bool,
     #[arg(long, conflicts_with = "json", help = "JSON Lines output (machine-friendly)")]
     pub jsonl: bool,
+    #[arg(
+        long,
+        help = "End JSONL output with {\"type\":\"summary\",\"count\":N,\"truncated\":BOOL}"
+    )]
+    pub summary_line: bool,
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Export every matching session as Markdown into DIR (session scope)"
+    )]
+    pub export_to: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Add an `exists` field to JSON output: whether each path is still on disk"
+    )]
+    pub verify_paths: bool,
+    #[arg(
+        long,
+        help = "Emit null for requested fields a result lacks, so all objects match"
+    )]
+    pub null_fields: bool,
+    #[arg(
+        long,
+        help = "Rewrite timestamps (epochs, offsets) as RFC 3339 UTC in the output"
+    )]
+    pub iso_timestamps: bool,
+    #[arg(
+        long,
+        help = "Add `matched_terms` to JSON output: the query terms found in each message"
+    )]
+    pub explain_matches: bool,
+    #[arg(long, help = "Leave out results whose session file no longer exists")]
+    pub drop_missing: bool,
+    #[arg(
+        long,
+        value_enum,
+        conflicts_with_all = ["json", "jsonl"],
+        help = "Output format (default: text)"
+    )]
+    pub format: Option<FindFormatArg>,
     #[arg(long, value_delimiter = ',', help = "Output fields (comma-separated)")]
     pub fields: Option<Vec<String>>,
-    #[arg(long, help = "Show text snippet in results")]
+    #[arg(
+        long,
+        help = "Show text snippet in results (message scope: an excerpt around the match)"
+    )]
     pub snippet: bool,
+    #[arg(
+        long,
+        value_name = "OPEN,CLOSE",
+        value_parser = parse_markers,
+        help = "Markers around matched terms in --snippet excerpts (default: [,]; none in JSON)"
+    )]
+    pub snippet_markers: Option<(String, String)>,
+    #[arg(long, help = "Show the bm25 score of each result in text output")]
+    pub show_score: bool,
+    #[arg(
+        long,
+        help = "Group message hits under one header per session in text output"
+    )]
+    pub group: bool,
+    #[arg(
+        long,
+        help = "Keep line breaks and indentation in text output (only the length cap applies)"
+    )]
+    pub preserve_whitespace: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct OpenArgs {
+    #[arg(
+        value_name = "PATH|SESSION_ID",
+        help = "Session file path or ID prefix"
+    )]
+    pub target: String,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    #[arg(
+        value_name = "PATH|SESSION_ID",
+        help = "Session file path or ID prefix"
+    )]
+    pub target: String,
+    #[arg(long, value_name = "FILE", help = "Write to FILE instead of stdout")]
+    pub out: Option<PathBuf>,
+    #[arg(
+        long,
+        alias = "replace-existing",
+        requires = "out",
+        help = "Overwrite FILE if it already exists"
+    )]
+    pub force: bool,
+    #[arg(
+        long,
+        help = "Write `role: text` lines that parse back into the same messages"
+    )]
+    pub role_prefix: bool,
 }
 
 #[derive(Debug, Args)]
@@ -121,10 +556,25 @@ pub struct ShowArgs {
     pub turn: Option<usize>,
     #[arg(long, conflicts_with = "turn", help = "Show specific line number")]
     pub line: Option<usize>,
-    #[arg(long, help = "Filter by tool name")]
-    pub tool: Option<String>,
+    #[arg(
+        long,
+        requires = "line",
+        help = "With --line, report only the line's own role/timestamp (no backfill)"
+    )]
+    pub raw: bool,
+    #[arg(long, help = "Filter by tool name (repeatable)")]
+    pub tool: Vec<String>,
     #[arg(long, help = "Max tool calls to show")]
     pub limit: Option<usize>,
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        help = "Skip the first N tool calls of the listing (page with --limit)"
+    )]
+    pub offset: usize,
+    #[arg(long, value_name = "N", help = "Max tool calls to show per tool name")]
+    pub per_tool_limit: Option<usize>,
     #[arg(long, help = "Extract and show file contents from read tool calls")]
     pub extract: bool,
     #[arg(long, help = "JSON output (machine-friendly)")]
@@ -133,6 +583,14 @@ pub struct ShowArgs {
 
 #[derive(Debug, Args)]
 pub struct StatsArgs {
+    #[arg(long, help = "Rank tool names by invocation count across all sessions")]
+    pub tools: bool,
+    #[arg(
+        long,
+        conflicts_with = "tools",
+        help = "Session and message counts per repository, most active first"
+    )]
+    pub by_repo: bool,
     #[arg(long, help = "JSON output (machine-friendly)")]
     pub json: bool,
 }
@@ -141,12 +599,22 @@ pub struct StatsArgs {
 pub struct DoctorArgs {
     #[arg(long, help = "JSON output (machine-friendly)")]
     pub json: bool,
+    #[arg(long, conflicts_with = "json", help = "JSON output on a single line")]
+    pub jsonl: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Explain whether FILE parses and is indexed, instead of the general checks"
+    )]
+    pub probe: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Delete orphaned messages whose session is no longer indexed"
+    )]
+    pub repair: bool,
 }
 
-pub fn default_db_path() -> PathBuf {
-    let home = std::env::var_os("HOME").unwrap_or_else(|| ".".into());
-    PathBuf::from(home).join(".config/marvin/mmem.sqlite")
-}
+pub use mmem::search::default_db_path;
 
 pub fn default_sessions_root() -> PathBuf {
     let home = std::env::var_os("HOME").unwrap_or_else(|| ".".into());
@@ -0,0 +1,136 @@
+//! Transparent decompression of archived session files.
+//!
+//! A compressed session keeps its inner extension in front of the
+//! compression suffix (`session.jsonl.zst`); the inner extension picks the
+//! parser. Each format is one [`Compression`] variant, so supporting a new
+//! one is a match arm in [`Compression::from_extension`] and
+//! [`Compression::decoder`].
+//!
+//! Every format is decoded in process (`zstd` and `flate2` crates) as a
+//! [`Read`] adapter, so no external tool is needed at run time and the
+//! decompressed session is streamed rather than held in memory.
+//!
+//! # Key Functions
+//!
+//! - [`session_format`]: Split a path into parser extension and compression
+//! - [`is_backup_name`]: Recognize editor backup and lock files
+//! - [`Compression::decoder`]: Wrap a reader of compressed bytes
+//! - [`Compression::decompress`]: Decompress file bytes in memory
+//! - [`Compression::decompress_with`]: Stream a compressed reader to a parser
+
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+/// Compression formats recognized by the scanner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Zstandard (`.zst`), enabled by the `zstd` cargo feature.
+    #[cfg(feature = "zstd")]
+    Zstd,
+    /// Gzip (`.gz`), enabled by the `gzip` cargo feature.
+    #[cfg(feature = "gzip")]
+    Gzip,
+}
+
+impl Compression {
+    /// Compression format for a (lowercase) file extension, if supported.
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext {
+            #[cfg(feature = "zstd")]
+            "zst" => Some(Self::Zstd),
+            #[cfg(feature = "gzip")]
+            "gz" => Some(Self::Gzip),
+            _ => None,
+        }
+    }
+
+    /// Wrap `reader`, which yields compressed bytes, in a reader of the
+    /// decompressed bytes.
+    #[cfg_attr(not(any(feature = "zstd", feature = "gzip")), allow(unused_variables))]
+    pub fn decoder<'a>(self, reader: impl Read + 'a) -> std::io::Result<Box<dyn Read + 'a>> {
+        match self {
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Ok(Box::new(zstd::stream::read::Decoder::new(reader)?)),
+            // Like `gzip -dc`, read every member of a concatenated archive.
+            #[cfg(feature = "gzip")]
+            Self::Gzip => Ok(Box::new(flate2::read::MultiGzDecoder::new(reader))),
+        }
+    }
+
+    /// Decompress a whole file held in memory.
+    pub fn decompress(self, bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut output = Vec::new();
+        self.decoder(bytes)?.read_to_end(&mut output)?;
+        Ok(output)
+    }
+
+    /// Decompress `reader` as a stream handed to `read`, so neither the
+    /// compressed nor the decompressed file has to fit in memory at once.
+    /// Output `read` leaves unread is drained, so a corrupt tail is an error
+    /// even if `read` succeeded.
+    pub fn decompress_with<T>(
+        self,
+        reader: impl Read,
+        read: impl FnOnce(&mut dyn BufRead) -> T,
+    ) -> std::io::Result<T> {
+        let mut reader = BufReader::new(self.decoder(reader)?);
+        let value = read(&mut reader);
+        std::io::copy(&mut reader, &mut std::io::sink())?;
+        Ok(value)
+    }
+}
+
+/// How a session file should be read: the parser extension (`jsonl`,
+/// `json`, `md`) and the compression wrapped around it, if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionFormat {
+    pub ext: String,
+    pub compression: Option<Compression>,
+}
+
+/// Classify `path` as a session file, looking through one compression
+/// suffix. Returns `None` for files the scanner should ignore, including
+/// editor backups and lock files (see [`is_backup_name`]).
+pub fn session_format(path: &Path) -> Option<SessionFormat> {
+    if is_backup_name(path.file_name()?.to_str()?) {
+        return None;
+    }
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    if is_session_extension(&ext) {
+        return Some(SessionFormat {
+            ext,
+            compression: None,
+        });
+    }
+
+    let compression = Compression::from_extension(&ext)?;
+    let inner = Path::new(path.file_stem()?)
+        .extension()?
+        .to_str()?
+        .to_ascii_lowercase();
+    if !is_session_extension(&inner) {
+        return None;
+    }
+
+    Some(SessionFormat {
+        ext: inner,
+        compression: Some(compression),
+    })
+}
+
+/// Editor and tool leftovers next to real sessions: `name~`, `name.bak`,
+/// `name.tmp`, vim swap files (`.swp`/`.swo`), and emacs autosave and lock
+/// files (`#name#`, `.#name`).
+pub fn is_backup_name(name: &str) -> bool {
+    let lower = name.to_ascii_lowercase();
+    lower.ends_with('~')
+        || [".bak", ".tmp", ".swp", ".swo"]
+            .iter()
+            .any(|suffix| lower.ends_with(suffix))
+        || (lower.starts_with('#') && lower.ends_with('#'))
+        || lower.starts_with(".#")
+}
+
+fn is_session_extension(ext: &str) -> bool {
+    matches!(ext, "jsonl" | "json" | "md")
+}
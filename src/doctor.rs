@@ -3,17 +3,27 @@
 //! The doctor command checks:
 //! - Sessions root directory exists
 //! - Database file exists and is readable
-//! - Schema is valid and queryable
+//! - Schema is valid, up to date, and queryable
 //! - FTS5 extension is available
+//! - SQLite version and optional capabilities (JSON1, trigram tokenizer)
+//! - Orphaned messages whose session row is missing
 //!
 //! # Key Functions
 //!
 //! - [`run_doctor`]: Generate a diagnostic report
+//! - [`run_doctor_with`]: Same, optionally repairing what it can
+//! - [`probe_file`]: Explain whether and why one session file is indexed
 
-use crate::index::init_schema;
+use crate::compress::session_format;
+use crate::index::{
+    IndexError, check_schema, count_orphaned_messages, init_schema, open_read_only,
+    remove_orphaned_messages,
+};
+use crate::parse::parse_file;
 use crate::stats::load_stats;
-use rusqlite::Connection;
+use rusqlite::{Connection, OptionalExtension};
 use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 
 #[derive(Debug, serde::Serialize)]
 pub struct DoctorReport {
@@ -24,11 +34,28 @@ pub struct DoctorReport {
     pub schema_ok: bool,
     pub schema_error: Option<String>,
     pub fts5_available: bool,
+    pub sqlite_version: String,
+    pub json1_available: bool,
+    pub trigram_available: bool,
     pub indexed_sessions: i64,
     pub newest_message_at: Option<String>,
+    /// Messages with no matching `sessions` row, counted before any repair.
+    pub orphaned_messages: i64,
+    /// Orphaned messages deleted by `--repair`.
+    pub repaired_messages: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DoctorOptions {
+    /// Delete orphaned messages instead of only reporting them.
+    pub repair: bool,
 }
 
 pub fn run_doctor(db_path: &Path, root: &Path) -> DoctorReport {
+    run_doctor_with(db_path, root, DoctorOptions::default())
+}
+
+pub fn run_doctor_with(db_path: &Path, root: &Path, options: DoctorOptions) -> DoctorReport {
     let root_exists = root.is_dir();
     let db_exists = db_path.exists();
 
@@ -36,22 +63,37 @@ pub fn run_doctor(db_path: &Path, root: &Path) -> DoctorReport {
         .ok()
         .and_then(|conn| init_schema(&conn).ok())
         .is_some();
+    let (json1_available, trigram_available) = Connection::open_in_memory()
+        .map(|conn| (json1_available(&conn), trigram_available(&conn)))
+        .unwrap_or((false, false));
 
     let mut schema_ok = false;
     let mut schema_error = None;
     let mut indexed_sessions = 0;
     let mut newest_message_at = None;
+    let mut orphaned_messages = 0;
+    let mut repaired_messages = 0;
 
     if db_exists {
         match Connection::open(db_path) {
-            Ok(conn) => match load_stats(&conn) {
+            Ok(mut conn) => match check_schema(&conn)
+                .map_err(|err| err.to_string())
+                .and_then(|()| load_stats(&conn).map_err(|err| err.to_string()))
+            {
                 Ok(stats) => {
                     schema_ok = true;
                     indexed_sessions = stats.session_count;
                     newest_message_at = stats.newest_message_at;
+                    match check_orphans(&mut conn, options.repair) {
+                        Ok((orphaned, repaired)) => {
+                            orphaned_messages = orphaned;
+                            repaired_messages = repaired;
+                        }
+                        Err(err) => schema_error = Some(err.to_string()),
+                    }
                 }
                 Err(err) => {
-                    schema_error = Some(err.to_string());
+                    schema_error = Some(err);
                 }
             },
             Err(err) => {
@@ -68,7 +110,116 @@ pub fn run_doctor(db_path: &Path, root: &Path) -> DoctorReport {
         schema_ok,
         schema_error,
         fts5_available,
+        sqlite_version: rusqlite::version().to_string(),
+        json1_available,
+        trigram_available,
         indexed_sessions,
         newest_message_at,
+        orphaned_messages,
+        repaired_messages,
+    }
+}
+
+fn check_orphans(conn: &mut Connection, repair: bool) -> Result<(i64, usize), IndexError> {
+    let orphaned = count_orphaned_messages(conn)?;
+    let repaired = if repair && orphaned > 0 {
+        remove_orphaned_messages(conn)?
+    } else {
+        0
+    };
+    Ok((orphaned, repaired))
+}
+
+fn json1_available(conn: &Connection) -> bool {
+    conn.query_row("SELECT json_valid('{}')", [], |row| row.get::<_, i64>(0))
+        .is_ok()
+}
+
+/// The trigram tokenizer exists since SQLite 3.34; probe it by creating a
+/// throwaway table rather than parsing the version.
+fn trigram_available(conn: &Connection) -> bool {
+    conn.execute_batch(
+        "CREATE VIRTUAL TABLE temp.trigram_probe USING fts5(x, tokenize = 'trigram')",
+    )
+    .is_ok()
+}
+
+/// `path` with symlinks resolved, or just made absolute when it does not
+/// exist (so a missing file can still be reported by name).
+fn canonical_or_absolute(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// How one session file fares against the index, for `doctor --probe`.
+#[derive(Debug, serde::Serialize)]
+pub struct ProbeReport {
+    pub path: PathBuf,
+    /// Whether the file parses as a session.
+    pub parsed: bool,
+    pub message_count: Option<usize>,
+    pub title: Option<String>,
+    pub agent: Option<String>,
+    /// Whether the index has a session row for this path.
+    pub indexed: bool,
+    /// File mtime (unix seconds) recorded when the session was indexed.
+    pub stored_mtime: Option<i64>,
+    /// File mtime (unix seconds) on disk now.
+    pub current_mtime: Option<i64>,
+    /// Why the file is not indexed, when it isn't.
+    pub reason_not_indexed: Option<String>,
+}
+
+/// Parse `path` and look it up in the index at `db_path`, explaining why a
+/// file `mmem index` should have picked up is missing from search.
+///
+/// `path` and `root` are canonicalized the way the scanner stores paths, so
+/// a file reached through a symlink is still found.
+pub fn probe_file(db_path: &Path, root: &Path, path: &Path) -> ProbeReport {
+    let path = canonical_or_absolute(path);
+    let root = &canonical_or_absolute(root);
+    let current_mtime = std::fs::metadata(&path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs() as i64);
+    let stored_mtime = open_read_only(db_path).ok().and_then(|conn| {
+        conn.query_row(
+            "SELECT mtime FROM sessions WHERE path = ?1",
+            [path.to_string_lossy()],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()
+        .ok()
+        .flatten()
+    });
+    let parsed = parse_file(&path);
+
+    let reason_not_indexed = if stored_mtime.is_some() {
+        None
+    } else if current_mtime.is_none() {
+        Some("file not found".to_string())
+    } else if session_format(&path).is_none() {
+        Some("not a session file (unsupported extension or editor backup)".to_string())
+    } else if let Err(err) = &parsed {
+        Some(format!("parse error: {err}"))
+    } else if !path.starts_with(root) {
+        Some(format!("outside the sessions root {}", root.display()))
+    } else {
+        Some("not indexed yet; run `mmem index`".to_string())
+    };
+
+    let parsed = parsed.ok();
+    ProbeReport {
+        parsed: parsed.is_some(),
+        message_count: parsed.as_ref().map(|session| session.message_count),
+        title: parsed.as_ref().and_then(|session| session.title.clone()),
+        agent: parsed.and_then(|session| session.agent),
+        indexed: stored_mtime.is_some(),
+        stored_mtime,
+        current_mtime,
+        reason_not_indexed,
+        path,
     }
 }
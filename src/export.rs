@@ -0,0 +1,154 @@
+//! Session export to Markdown.
+//!
+//! Renders a parsed session as a readable Markdown document and writes it
+//! to disk. Writing is safe by default: an existing file is never replaced
+//! unless the caller passes `force`.
+//!
+//! # Key Functions
+//!
+//! - [`session_to_markdown`]: Render a session as Markdown
+//! - [`session_to_transcript`]: Render a session as `role: text` lines that
+//!   [`parse_markdown`](crate::parse::parse_markdown) reads back
+//! - [`write_export`]: Write an export, refusing to clobber existing files
+//! - [`export_path_in`]: Pick a collision-free file name for a batch export
+
+use crate::model::ParsedSession;
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error("{path} already exists — pass --force to overwrite it")]
+    Exists { path: PathBuf },
+    #[error("failed to write {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+/// Render `session` as Markdown: the title as a heading, then one section
+/// per message headed by its role.
+pub fn session_to_markdown(session: &ParsedSession) -> String {
+    let mut out = String::new();
+    let title = session.title.as_deref().unwrap_or("(untitled)");
+    out.push_str(&format!("# {}\n", title.lines().next().unwrap_or(title)));
+
+    for message in &session.messages {
+        if message.text.trim().is_empty() {
+            continue;
+        }
+        let role = message.role.as_deref().unwrap_or("unknown");
+        out.push_str(&format!("\n## {}\n\n{}\n", role, message.text.trim()));
+    }
+
+    out
+}
+
+/// Render `session` as a role-prefixed transcript: each message starts with
+/// a `role: ` line and is separated from the next by a blank line.
+///
+/// The output parses back with [`parse_markdown`](crate::parse::parse_markdown)
+/// into the same roles, texts, and order. Two cases cannot be carried:
+/// messages without a role are written unprefixed, and a line inside a
+/// message that itself reads as `role: text` (outside a code fence) starts a
+/// new message when parsed.
+pub fn session_to_transcript(session: &ParsedSession) -> String {
+    let mut out = String::new();
+    for message in &session.messages {
+        let text = message.text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        match message.role.as_deref() {
+            Some(role) => out.push_str(&format!("{role}: {text}\n")),
+            None => out.push_str(&format!("{text}\n")),
+        }
+    }
+    out
+}
+
+/// Pick the output path in `dir` for a session export.
+///
+/// The file is named after the slugified title, or the session ID when
+/// there is no usable title. When that name was already used in this batch
+/// (`taken`) or exists on disk, the session ID is appended to a title, and
+/// then a counter (`-2`, `-3`, ...) until the name is free.
+pub fn export_path_in(
+    dir: &Path,
+    title: Option<&str>,
+    session_id: &str,
+    taken: &HashSet<PathBuf>,
+) -> PathBuf {
+    let is_free = |path: &PathBuf| !taken.contains(path) && !path.exists();
+    let slug = title.map(slugify).unwrap_or_default();
+    let stem = if slug.is_empty() {
+        session_id.to_string()
+    } else {
+        let path = dir.join(format!("{slug}.md"));
+        if is_free(&path) {
+            return path;
+        }
+        format!("{slug}-{session_id}")
+    };
+
+    let mut path = dir.join(format!("{stem}.md"));
+    let mut counter = 2;
+    while !is_free(&path) {
+        path = dir.join(format!("{stem}-{counter}.md"));
+        counter += 1;
+    }
+    path
+}
+
+/// Lowercase ASCII alphanumerics joined by single dashes, capped at 60 chars.
+fn slugify(title: &str) -> String {
+    let first_line = title.lines().next().unwrap_or_default();
+    let mut slug = String::new();
+    for ch in first_line.chars() {
+        if ch.is_ascii_alphanumeric() {
+            if slug.len() >= 60 {
+                break;
+            }
+            slug.push(ch.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Write `contents` to `path`.
+///
+/// Without `force`, fails with [`ExportError::Exists`] if `path` already
+/// exists. The check and the create are one atomic step, so a file that
+/// appears in between is not clobbered either.
+pub fn write_export(path: &Path, contents: &str, force: bool) -> Result<(), ExportError> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+
+    let io_error = |source: std::io::Error| {
+        if source.kind() == std::io::ErrorKind::AlreadyExists {
+            ExportError::Exists {
+                path: path.to_path_buf(),
+            }
+        } else {
+            ExportError::Io {
+                path: path.to_path_buf(),
+                source,
+            }
+        }
+    };
+
+    let mut file = options.open(path).map_err(io_error)?;
+    file.write_all(contents.as_bytes()).map_err(io_error)
+}
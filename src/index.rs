@@ -8,23 +8,78 @@
 //! - `sessions`: Session metadata (path, agent, workspace, timestamps, etc.)
 //! - `sessions_fts`: FTS5 index of session content
 //! - `messages`: Individual messages with turn indices
-//! - `messages_fts`: FTS5 index of message text
+//! - `messages_fts`: FTS5 index of message text (a copy of it by default;
+//!   see [External-Content Message Index](#external-content-message-index))
+//! - `message_tools`: Tool call names per message
 //!
 //! # Key Functions
 //!
-//! - [`init_schema`]: Create tables and indexes
+//! - [`init_schema`]: Create tables and indexes, migrating older databases
+//! - [`check_schema`]: Report columns an older database is still missing
+//! - [`schema_is_current`]: Cheap check whether `init_schema` can be skipped
 //! - [`configure_connection`]: Set WAL mode, busy timeout, etc.
+//! - [`open_read_only`]: Open an existing database for queries only
 //! - [`upsert_session`] / [`upsert_session_tx`]: Insert or update a session
 //! - [`replace_messages_tx`]: Replace all messages for a session
 //! - [`remove_session`] / [`remove_session_tx`]: Delete a session and its messages
+//! - [`rename_session_tx`]: Move a session to a new path in place
+//! - [`count_orphaned_messages`] / [`remove_orphaned_messages`]: Find or drop
+//!   messages whose session row is gone
+//! - [`dedup_session_content`]: Clear repeated message text in place
+//! - [`use_external_message_fts`]: Stop duplicating message text in the index
+//! - [`init_schema_with`] / [`fts_tokenizer`]: Pick or report the FTS tokenizer
 //!
 //! # Transaction Pattern
 //!
 //! Functions with `_tx` suffix operate within an existing transaction.
 //! Non-`_tx` variants create their own transaction.
+//!
+//! # External-Content Message Index
+//!
+//! By default `messages_fts` stores its own copy of every message's text.
+//! [`use_external_message_fts`] rebuilds it as an FTS5 external-content
+//! table that reads text from `messages` instead, kept in sync by triggers
+//! on `messages`; the write functions here then leave `messages_fts` to
+//! those triggers. Queries are unchanged. The conversion is one-way for a
+//! given database file.
+//!
+//! # Tokenizers
+//!
+//! Both FTS tables use SQLite's `unicode61` tokenizer unless
+//! [`init_schema_with`] asks for another [`FtsTokenizer`]. `trigram` indexes
+//! every three-character substring, so runs of CJK text without spaces (one
+//! giant token under `unicode61`) can be searched by any part of them. The
+//! database roughly doubles in size, and a query term matches as a
+//! substring rather than a whole word. Switching rebuilds both tables from
+//! their own content; later `init_schema` calls keep whichever tokenizer is
+//! in place.
 
 use crate::model::{MessageRecord, SessionRecord};
-use rusqlite::{Connection, Transaction, params};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, Transaction, params};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Stored in `PRAGMA user_version` once [`init_schema`] has brought a
+/// database fully up to date. Bump it whenever `init_schema` gains a
+/// migration, so existing databases run it again.
+pub const SCHEMA_VERSION: i64 = 7;
+
+/// Process-wide counter bumped by every index mutation.
+static WRITE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Current write generation; changes whenever sessions or messages are
+/// written, renamed, or removed in this process. Caches compare it to detect
+/// stale results, alongside `PRAGMA data_version` for other processes.
+pub fn write_generation() -> u64 {
+    WRITE_GENERATION.load(Ordering::Acquire)
+}
+
+fn bump_write_generation() {
+    WRITE_GENERATION.fetch_add(1, Ordering::AcqRel);
+}
 
 const SCHEMA: &str = r#"
 CREATE TABLE IF NOT EXISTS sessions (
@@ -38,10 +93,16 @@ CREATE TABLE IF NOT EXISTS sessions (
   workspace TEXT,
   title TEXT,
   message_count INTEGER,
+  user_message_count INTEGER NOT NULL DEFAULT 0,
+  assistant_message_count INTEGER NOT NULL DEFAULT 0,
   snippet TEXT,
   repo_root TEXT,
   repo_name TEXT,
-  branch TEXT
+  branch TEXT,
+  system_prompt TEXT,
+  preview TEXT,
+  parent_path TEXT,
+  commands TEXT
 );
 
 CREATE VIRTUAL TABLE IF NOT EXISTS sessions_fts USING fts5(
@@ -55,7 +116,9 @@ CREATE TABLE IF NOT EXISTS messages (
   turn_index INTEGER NOT NULL,
   role TEXT,
   timestamp TEXT,
-  text TEXT
+  text TEXT,
+  line INTEGER,
+  UNIQUE(session_path, turn_index)
 );
 
 CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
@@ -65,25 +128,210 @@ CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
   role UNINDEXED
 );
 
+CREATE TABLE IF NOT EXISTS message_tools (
+  session_path TEXT NOT NULL,
+  turn_index INTEGER NOT NULL,
+  name TEXT NOT NULL
+);
+
 CREATE INDEX IF NOT EXISTS idx_sessions_last_message_at ON sessions(last_message_at);
 CREATE INDEX IF NOT EXISTS idx_sessions_agent ON sessions(agent);
 CREATE INDEX IF NOT EXISTS idx_sessions_workspace ON sessions(workspace);
+CREATE INDEX IF NOT EXISTS idx_messages_session_turn ON messages(session_path, turn_index);
+CREATE INDEX IF NOT EXISTS idx_message_tools_session ON message_tools(session_path);
+CREATE INDEX IF NOT EXISTS idx_message_tools_name ON message_tools(name);
+"#;
+
+/// Indexes on migrated columns, created once `ensure_column` has run.
+const MIGRATED_INDEXES: &str = r#"
 CREATE INDEX IF NOT EXISTS idx_sessions_repo_name ON sessions(repo_name);
 CREATE INDEX IF NOT EXISTS idx_sessions_branch ON sessions(branch);
-CREATE INDEX IF NOT EXISTS idx_messages_session_turn ON messages(session_path, turn_index);
+CREATE INDEX IF NOT EXISTS idx_sessions_parent_path ON sessions(parent_path);
 "#;
 
+/// Indexes on columns an outdated table may lack, created once
+/// [`check_schema`] has confirmed they exist. The timestamp index lets
+/// `find --after` pre-filter message ids before the FTS match.
+const CHECKED_INDEXES: &str = r#"
+CREATE INDEX IF NOT EXISTS idx_messages_timestamp ON messages(timestamp);
+"#;
+
+/// Fill role counts for sessions indexed before the columns existed, from
+/// the messages already stored.
+const BACKFILL_ROLE_COUNTS: &str = r#"
+UPDATE sessions SET
+  user_message_count = (
+    SELECT COUNT(*) FROM messages m WHERE m.session_path = sessions.path AND m.role = 'user'),
+  assistant_message_count = (
+    SELECT COUNT(*) FROM messages m
+    WHERE m.session_path = sessions.path AND m.role = 'assistant');
+"#;
+
+/// `messages_fts` as an external-content table over `messages`. The view
+/// exposes `id` again as `message_id`, the column queries join on. The
+/// triggers are the only writers once this is in place. `{tokenize}` is
+/// replaced with the database's current [`FtsTokenizer`].
+const EXTERNAL_MESSAGES_FTS: &str = r#"
+DROP TABLE IF EXISTS messages_fts;
+CREATE VIEW IF NOT EXISTS messages_fts_content AS
+  SELECT id, text, id AS message_id, session_path, role FROM messages;
+CREATE VIRTUAL TABLE messages_fts USING fts5(
+  text,
+  message_id UNINDEXED,
+  session_path UNINDEXED,
+  role UNINDEXED,
+  content = 'messages_fts_content',
+  content_rowid = 'id',
+  tokenize = '{tokenize}'
+);
+CREATE TRIGGER IF NOT EXISTS messages_fts_insert AFTER INSERT ON messages BEGIN
+  INSERT INTO messages_fts (rowid, text, message_id, session_path, role)
+  VALUES (new.id, new.text, new.id, new.session_path, new.role);
+END;
+CREATE TRIGGER IF NOT EXISTS messages_fts_delete AFTER DELETE ON messages BEGIN
+  INSERT INTO messages_fts (messages_fts, rowid, text, message_id, session_path, role)
+  VALUES ('delete', old.id, old.text, old.id, old.session_path, old.role);
+END;
+CREATE TRIGGER IF NOT EXISTS messages_fts_update AFTER UPDATE ON messages BEGIN
+  INSERT INTO messages_fts (messages_fts, rowid, text, message_id, session_path, role)
+  VALUES ('delete', old.id, old.text, old.id, old.session_path, old.role);
+  INSERT INTO messages_fts (rowid, text, message_id, session_path, role)
+  VALUES (new.id, new.text, new.id, new.session_path, new.role);
+END;
+INSERT INTO messages_fts (messages_fts) VALUES ('rebuild');
+"#;
+
+/// Copy both FTS tables into new ones using tokenizer `{tokenize}`. A
+/// `messages_fts` over external content is recreated by
+/// [`EXTERNAL_MESSAGES_FTS`] instead.
+const RETOKENIZE_SESSIONS_FTS: &str = r#"
+CREATE VIRTUAL TABLE sessions_fts_retokenized USING fts5(
+  content,
+  path UNINDEXED,
+  tokenize = '{tokenize}'
+);
+INSERT INTO sessions_fts_retokenized (content, path) SELECT content, path FROM sessions_fts;
+DROP TABLE sessions_fts;
+ALTER TABLE sessions_fts_retokenized RENAME TO sessions_fts;
+"#;
+
+const RETOKENIZE_MESSAGES_FTS: &str = r#"
+CREATE VIRTUAL TABLE messages_fts_retokenized USING fts5(
+  text,
+  message_id UNINDEXED,
+  session_path UNINDEXED,
+  role UNINDEXED,
+  tokenize = '{tokenize}'
+);
+INSERT INTO messages_fts_retokenized (text, message_id, session_path, role)
+  SELECT text, message_id, session_path, role FROM messages_fts;
+DROP TABLE messages_fts;
+ALTER TABLE messages_fts_retokenized RENAME TO messages_fts;
+"#;
+
+/// Schema version that changed how Markdown sessions split into messages
+/// (unprefixed lines continue the previous message).
+const MARKDOWN_CONTINUATION_VERSION: i64 = 7;
+
+/// Make the next `mmem index` re-parse Markdown sessions indexed by an
+/// older version, so stored turn indices match what `show --turn` parses.
+/// Clearing the hash as well as the mtime defeats the content-hash skip.
+const REPARSE_MARKDOWN_SESSIONS: &str = r#"
+UPDATE sessions SET mtime = -1, hash = NULL
+WHERE path LIKE '%.md' OR path LIKE '%.md.%';
+"#;
+
+/// Give a `messages` table created before `UNIQUE(session_path, turn_index)`
+/// the same guarantee: drop duplicate turns (keeping the first inserted),
+/// then add a unique index.
+const MIGRATE_UNIQUE_TURNS: &str = r#"
+DELETE FROM messages_fts WHERE message_id IN (
+  SELECT id FROM messages
+  WHERE id NOT IN (SELECT MIN(id) FROM messages GROUP BY session_path, turn_index)
+);
+DELETE FROM messages
+WHERE id NOT IN (SELECT MIN(id) FROM messages GROUP BY session_path, turn_index);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_messages_session_turn_unique
+  ON messages(session_path, turn_index);
+"#;
+
+/// Columns the query layer relies on, checked after migrations run.
+const EXPECTED_COLUMNS: &[(&str, &[&str])] = &[
+    (
+        "sessions",
+        &[
+            "path",
+            "mtime",
+            "size",
+            "hash",
+            "created_at",
+            "last_message_at",
+            "agent",
+            "workspace",
+            "title",
+            "message_count",
+            "user_message_count",
+            "assistant_message_count",
+            "snippet",
+            "repo_root",
+            "repo_name",
+            "branch",
+            "system_prompt",
+            "preview",
+            "parent_path",
+            "commands",
+        ],
+    ),
+    (
+        "messages",
+        &[
+            "id",
+            "session_path",
+            "turn_index",
+            "role",
+            "timestamp",
+            "text",
+            "line",
+        ],
+    ),
+];
+
+/// Tokenizer used by both FTS tables; see [Tokenizers](#tokenizers).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FtsTokenizer {
+    /// Words split on whitespace and punctuation (SQLite's default).
+    #[default]
+    Unicode61,
+    /// Overlapping three-character substrings, for CJK and other unspaced text.
+    Trigram,
+}
+
+impl FtsTokenizer {
+    /// The name FTS5's `tokenize` option takes.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Unicode61 => "unicode61",
+            Self::Trigram => "trigram",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct IndexedSession {
     pub path: String,
     pub mtime: i64,
     pub size: i64,
+    pub hash: Option<String>,
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum IndexError {
     #[error("sqlite error: {source}")]
     Sqlite { source: rusqlite::Error },
+    #[error(
+        "database schema is outdated (missing {table}.{column}); remove the database and run `mmem index --full`"
+    )]
+    SchemaMismatch { table: String, column: String },
 }
 
 impl From<rusqlite::Error> for IndexError {
@@ -93,10 +341,113 @@ impl From<rusqlite::Error> for IndexError {
 }
 
 pub fn init_schema(conn: &Connection) -> Result<(), IndexError> {
+    init_schema_with(conn, None)
+}
+
+/// [`init_schema`], then rebuild the FTS tables with `tokenizer` if they use
+/// a different one. `None` keeps the current tokenizer (`unicode61` for a
+/// new database).
+pub fn init_schema_with(
+    conn: &Connection,
+    tokenizer: Option<FtsTokenizer>,
+) -> Result<(), IndexError> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
     conn.execute_batch(SCHEMA)?;
     ensure_column(conn, "sessions", "repo_root", "TEXT")?;
     ensure_column(conn, "sessions", "repo_name", "TEXT")?;
     ensure_column(conn, "sessions", "branch", "TEXT")?;
+    ensure_column(conn, "sessions", "system_prompt", "TEXT")?;
+    ensure_column(conn, "sessions", "preview", "TEXT")?;
+    ensure_column(conn, "sessions", "parent_path", "TEXT")?;
+    ensure_column(conn, "sessions", "commands", "TEXT")?;
+    ensure_column(conn, "messages", "line", "INTEGER")?;
+    let user_added = ensure_column(
+        conn,
+        "sessions",
+        "user_message_count",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    let assistant_added = ensure_column(
+        conn,
+        "sessions",
+        "assistant_message_count",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    if user_added || assistant_added {
+        conn.execute_batch(BACKFILL_ROLE_COUNTS)?;
+    }
+    conn.execute_batch(MIGRATED_INDEXES)?;
+    if !has_unique_turn_index(conn)? {
+        conn.execute_batch(MIGRATE_UNIQUE_TURNS)?;
+    }
+    if version < MARKDOWN_CONTINUATION_VERSION {
+        conn.execute_batch(REPARSE_MARKDOWN_SESSIONS)?;
+    }
+    check_schema(conn)?;
+    conn.execute_batch(CHECKED_INDEXES)?;
+    if let Some(tokenizer) = tokenizer
+        && fts_tokenizer(conn)? != tokenizer
+    {
+        retokenize_fts(conn, tokenizer)?;
+    }
+    conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    Ok(())
+}
+
+/// The tokenizer the FTS tables were created with.
+pub fn fts_tokenizer(conn: &Connection) -> Result<FtsTokenizer, IndexError> {
+    let sql: Option<String> = conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'sessions_fts'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(match sql {
+        Some(sql) if sql.contains("'trigram'") => FtsTokenizer::Trigram,
+        _ => FtsTokenizer::Unicode61,
+    })
+}
+
+fn retokenize_fts(conn: &Connection, tokenizer: FtsTokenizer) -> Result<(), IndexError> {
+    let messages_sql = if messages_fts_is_external(conn)? {
+        EXTERNAL_MESSAGES_FTS
+    } else {
+        RETOKENIZE_MESSAGES_FTS
+    };
+    let tx = conn.unchecked_transaction()?;
+    tx.execute_batch(&RETOKENIZE_SESSIONS_FTS.replace("{tokenize}", tokenizer.name()))?;
+    tx.execute_batch(&messages_sql.replace("{tokenize}", tokenizer.name()))?;
+    tx.commit()?;
+    bump_write_generation();
+    Ok(())
+}
+
+/// Whether [`init_schema`] has already run against this database at the
+/// current [`SCHEMA_VERSION`]. Reads a single pragma, so query commands can
+/// call it instead of re-running the schema batch on every invocation.
+pub fn schema_is_current(conn: &Connection) -> Result<bool, IndexError> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    Ok(version >= SCHEMA_VERSION)
+}
+
+/// Verify that every column the queries select is present.
+///
+/// `init_schema` migrates what it can; anything still missing comes from a
+/// table layout this version cannot upgrade in place, so report it clearly
+/// instead of letting a later query fail with a raw SQLite error.
+pub fn check_schema(conn: &Connection) -> Result<(), IndexError> {
+    for (table, columns) in EXPECTED_COLUMNS {
+        let existing = table_columns(conn, table)?;
+        for column in *columns {
+            if !existing.iter().any(|name| name == column) {
+                return Err(IndexError::SchemaMismatch {
+                    table: (*table).to_string(),
+                    column: (*column).to_string(),
+                });
+            }
+        }
+    }
     Ok(())
 }
 
@@ -115,13 +466,28 @@ pub fn configure_connection(conn: &Connection) -> Result<(), IndexError> {
     Ok(())
 }
 
+/// Open an existing database read-only, for commands that only query.
+///
+/// Under WAL a reader sees a consistent snapshot while `mmem index` writes,
+/// and a read-only handle can never take the write lock or mutate the
+/// index by accident.
+pub fn open_read_only(path: &Path) -> Result<Connection, IndexError> {
+    let conn = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    let _: i64 = conn.query_row("PRAGMA busy_timeout = 5000", [], |row| row.get(0))?;
+    Ok(conn)
+}
+
 pub fn load_indexed_sessions(conn: &Connection) -> Result<Vec<IndexedSession>, IndexError> {
-    let mut stmt = conn.prepare("SELECT path, mtime, size FROM sessions")?;
+    let mut stmt = conn.prepare("SELECT path, mtime, size, hash FROM sessions")?;
     let rows = stmt.query_map([], |row| {
         Ok(IndexedSession {
             path: row.get(0)?,
             mtime: row.get(1)?,
             size: row.get(2)?,
+            hash: row.get(3)?,
         })
     })?;
 
@@ -141,6 +507,7 @@ pub fn upsert_session(conn: &mut Connection, record: &SessionRecord) -> Result<(
 }
 
 pub fn upsert_session_tx(tx: &Transaction<'_>, record: &SessionRecord) -> Result<(), IndexError> {
+    bump_write_generation();
     tx.execute(
         "INSERT INTO sessions (
             path,
@@ -156,8 +523,17 @@ pub fn upsert_session_tx(tx: &Transaction<'_>, record: &SessionRecord) -> Result
             snippet,
             repo_root,
             repo_name,
-            branch
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+            branch,
+            system_prompt,
+            preview,
+            parent_path,
+            commands,
+            user_message_count,
+            assistant_message_count
+        ) VALUES (
+            ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18,
+            ?19, ?20
+        )
         ON CONFLICT(path) DO UPDATE SET
             mtime = excluded.mtime,
             size = excluded.size,
@@ -171,7 +547,13 @@ pub fn upsert_session_tx(tx: &Transaction<'_>, record: &SessionRecord) -> Result
             snippet = excluded.snippet,
             repo_root = excluded.repo_root,
             repo_name = excluded.repo_name,
-            branch = excluded.branch",
+            branch = excluded.branch,
+            system_prompt = excluded.system_prompt,
+            preview = excluded.preview,
+            parent_path = excluded.parent_path,
+            commands = excluded.commands,
+            user_message_count = excluded.user_message_count,
+            assistant_message_count = excluded.assistant_message_count",
         params![
             &record.path,
             record.mtime,
@@ -187,6 +569,12 @@ pub fn upsert_session_tx(tx: &Transaction<'_>, record: &SessionRecord) -> Result
             &record.repo_root,
             &record.repo_name,
             &record.branch,
+            &record.system_prompt,
+            &record.preview,
+            &record.parent_path,
+            &record.commands,
+            record.user_message_count,
+            record.assistant_message_count,
         ],
     )?;
 
@@ -202,28 +590,47 @@ pub fn upsert_session_tx(tx: &Transaction<'_>, record: &SessionRecord) -> Result
     Ok(())
 }
 
+/// Replace every stored message for `session_path` with `messages`.
+///
+/// Existing rows in `messages` and `messages_fts` are deleted first, so the
+/// call is idempotent when a session is re-indexed. Each FTS row records the
+/// `messages.id` it mirrors, which is what message-scope queries join on.
+/// `turn_index` must be unique within `messages`; a duplicate fails the
+/// insert (and, with the caller's transaction, the whole replacement).
 pub fn replace_messages_tx(
     tx: &Transaction<'_>,
     session_path: &str,
     messages: &[MessageRecord],
 ) -> Result<(), IndexError> {
+    bump_write_generation();
+    let copy_fts = !messages_fts_is_external(tx)?;
+    if copy_fts {
+        tx.execute(
+            "DELETE FROM messages_fts WHERE session_path = ?1",
+            params![session_path],
+        )?;
+    }
     tx.execute(
-        "DELETE FROM messages_fts WHERE session_path = ?1",
+        "DELETE FROM messages WHERE session_path = ?1",
         params![session_path],
     )?;
     tx.execute(
-        "DELETE FROM messages WHERE session_path = ?1",
+        "DELETE FROM message_tools WHERE session_path = ?1",
         params![session_path],
     )?;
 
     let mut insert_message = tx.prepare(
-        "INSERT INTO messages (session_path, turn_index, role, timestamp, text)
-         VALUES (?1, ?2, ?3, ?4, ?5)",
+        "INSERT INTO messages (session_path, turn_index, role, timestamp, text, line)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
     )?;
     let mut insert_fts = tx.prepare(
         "INSERT INTO messages_fts (text, message_id, session_path, role)
          VALUES (?1, ?2, ?3, ?4)",
     )?;
+    let mut insert_tool = tx.prepare(
+        "INSERT INTO message_tools (session_path, turn_index, name)
+         VALUES (?1, ?2, ?3)",
+    )?;
 
     for message in messages {
         insert_message.execute(params![
@@ -232,14 +639,20 @@ pub fn replace_messages_tx(
             &message.role,
             &message.timestamp,
             &message.text,
+            message.line,
         ])?;
-        let message_id = tx.last_insert_rowid();
-        insert_fts.execute(params![
-            &message.text,
-            message_id,
-            session_path,
-            &message.role,
-        ])?;
+        if copy_fts {
+            let message_id = tx.last_insert_rowid();
+            insert_fts.execute(params![
+                &message.text,
+                message_id,
+                session_path,
+                &message.role,
+            ])?;
+        }
+        for tool in &message.tools {
+            insert_tool.execute(params![session_path, message.turn_index, tool])?;
+        }
     }
 
     Ok(())
@@ -253,12 +666,19 @@ pub fn remove_session(conn: &mut Connection, path: &str) -> Result<(), IndexErro
 }
 
 pub fn remove_session_tx(tx: &Transaction<'_>, path: &str) -> Result<(), IndexError> {
+    bump_write_generation();
+    if !messages_fts_is_external(tx)? {
+        tx.execute(
+            "DELETE FROM messages_fts WHERE session_path = ?1",
+            params![path],
+        )?;
+    }
     tx.execute(
-        "DELETE FROM messages_fts WHERE session_path = ?1",
+        "DELETE FROM messages WHERE session_path = ?1",
         params![path],
     )?;
     tx.execute(
-        "DELETE FROM messages WHERE session_path = ?1",
+        "DELETE FROM message_tools WHERE session_path = ?1",
         params![path],
     )?;
     tx.execute("DELETE FROM sessions_fts WHERE path = ?1", params![path])?;
@@ -267,24 +687,361 @@ pub fn remove_session_tx(tx: &Transaction<'_>, path: &str) -> Result<(), IndexEr
     Ok(())
 }
 
+/// Record a new mtime/size for a session whose content hash is unchanged,
+/// so later scans skip it on the cheap mtime/size check again.
+pub fn touch_session_tx(
+    tx: &Transaction<'_>,
+    path: &str,
+    mtime: i64,
+    size: i64,
+) -> Result<(), IndexError> {
+    tx.execute(
+        "UPDATE sessions SET mtime = ?2, size = ?3 WHERE path = ?1",
+        params![path, mtime, size],
+    )?;
+    Ok(())
+}
+
+/// Move a session and its messages from `from` to `to` without re-parsing.
+///
+/// Used when a file was renamed: the content is unchanged, so only the path
+/// keys and the file's mtime/size need updating.
+pub fn rename_session_tx(
+    tx: &Transaction<'_>,
+    from: &str,
+    to: &str,
+    mtime: i64,
+    size: i64,
+) -> Result<(), IndexError> {
+    bump_write_generation();
+    tx.execute(
+        "UPDATE sessions SET path = ?2, mtime = ?3, size = ?4 WHERE path = ?1",
+        params![from, to, mtime, size],
+    )?;
+    tx.execute(
+        "UPDATE sessions_fts SET path = ?2 WHERE path = ?1",
+        params![from, to],
+    )?;
+    tx.execute(
+        "UPDATE messages SET session_path = ?2 WHERE session_path = ?1",
+        params![from, to],
+    )?;
+    if !messages_fts_is_external(tx)? {
+        tx.execute(
+            "UPDATE messages_fts SET session_path = ?2 WHERE session_path = ?1",
+            params![from, to],
+        )?;
+    }
+    tx.execute(
+        "UPDATE message_tools SET session_path = ?2 WHERE session_path = ?1",
+        params![from, to],
+    )?;
+
+    Ok(())
+}
+
+/// Messages whose `session_path` has no `sessions` row. Every query joins
+/// messages to sessions, so these can never be returned.
+const ORPHANED_MESSAGES: &str =
+    "SELECT id FROM messages WHERE session_path NOT IN (SELECT path FROM sessions)";
+
+/// Count messages left behind by a session that no longer exists.
+pub fn count_orphaned_messages(conn: &Connection) -> Result<i64, IndexError> {
+    let count = conn.query_row(
+        &format!("SELECT COUNT(*) FROM ({ORPHANED_MESSAGES})"),
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(count)
+}
+
+/// Delete orphaned messages (see [`count_orphaned_messages`]) along with
+/// their FTS and tool rows, returning how many messages were removed.
+pub fn remove_orphaned_messages(conn: &mut Connection) -> Result<usize, IndexError> {
+    let tx = conn.transaction()?;
+    if !messages_fts_is_external(&tx)? {
+        tx.execute(
+            &format!("DELETE FROM messages_fts WHERE message_id IN ({ORPHANED_MESSAGES})"),
+            [],
+        )?;
+    }
+    tx.execute(
+        "DELETE FROM message_tools WHERE session_path NOT IN (SELECT path FROM sessions)",
+        [],
+    )?;
+    let removed = tx.execute(
+        &format!("DELETE FROM messages WHERE id IN ({ORPHANED_MESSAGES})"),
+        [],
+    )?;
+    tx.commit()?;
+    if removed > 0 {
+        bump_write_generation();
+    }
+    Ok(removed)
+}
+
+/// Messages shorter than this (after collapsing whitespace) are never
+/// treated as duplicates; short replies like "ok" repeat legitimately.
+const MIN_DUPLICATE_CHARS: usize = 64;
+
+/// Outcome of [`dedup_session_content`].
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct DedupStats {
+    /// Sessions that had at least one duplicate message.
+    pub sessions: usize,
+    /// Messages whose text was cleared.
+    pub messages: usize,
+    /// Text removed from `messages` and `sessions_fts`, in bytes.
+    pub bytes_saved: i64,
+}
+
+/// Words per shingle when comparing messages for [`dedup_session_content`].
+const SHINGLE_WORDS: usize = 5;
+
+/// Jaccard similarity of two messages' shingle sets at or above which the
+/// later one counts as a near-duplicate of the earlier one.
+const NEAR_DUPLICATE_SIMILARITY: f64 = 0.9;
+
+/// Hashes of every run of [`SHINGLE_WORDS`] consecutive words in `text`, so
+/// whitespace differences disappear and a small edit only changes the few
+/// shingles that overlap it. `None` when the text is too short to matter.
+fn shingles(text: &str) -> Option<HashSet<u64>> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let chars: usize = words.iter().map(|word| word.chars().count() + 1).sum();
+    if chars.saturating_sub(1) < MIN_DUPLICATE_CHARS {
+        return None;
+    }
+    let shingles = words
+        .windows(SHINGLE_WORDS.min(words.len()))
+        .map(|window| {
+            let mut hasher = DefaultHasher::new();
+            window.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect();
+    Some(shingles)
+}
+
+/// Whether two shingle sets are at least [`NEAR_DUPLICATE_SIMILARITY`]
+/// similar (Jaccard index: shared shingles over all distinct shingles).
+fn is_near_duplicate(a: &HashSet<u64>, b: &HashSet<u64>) -> bool {
+    let (small, large) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    // The index can be at most small/large, so skip the set walk when even
+    // that falls short.
+    if (small.len() as f64) < NEAR_DUPLICATE_SIMILARITY * large.len() as f64 {
+        return false;
+    }
+    let shared = small
+        .iter()
+        .filter(|shingle| large.contains(shingle))
+        .count();
+    let union = small.len() + large.len() - shared;
+    shared as f64 >= NEAR_DUPLICATE_SIMILARITY * union as f64
+}
+
+/// Clear the text of messages that repeat an earlier message in the same
+/// session, exactly or with small edits, working only from what is already
+/// stored.
+///
+/// Agents often resend the same context block (file contents, tool output)
+/// many times in one session, sometimes slightly changed. A message counts
+/// as a near-duplicate when its word shingles are at least
+/// [`NEAR_DUPLICATE_SIMILARITY`] similar to those of an earlier message that
+/// was kept. The first copy is kept; later copies keep their row, role, and
+/// turn index but lose their text, including whatever small edit they
+/// carried, and their block is cut from the session's `sessions_fts`
+/// content. Reindexing a changed file
+/// restores the duplicates, and the database file only shrinks after a
+/// `VACUUM`.
+pub fn dedup_session_content(conn: &mut Connection) -> Result<DedupStats, IndexError> {
+    let tx = conn.transaction()?;
+    let mut duplicates: Vec<(i64, String, Option<String>, String)> = Vec::new();
+    {
+        let mut stmt = tx.prepare(
+            "SELECT id, session_path, role, text FROM messages
+             WHERE text <> '' ORDER BY session_path, turn_index",
+        )?;
+        let mut rows = stmt.query([])?;
+        let mut current: Option<String> = None;
+        let mut kept: Vec<HashSet<u64>> = Vec::new();
+        while let Some(row) = rows.next()? {
+            let session_path: String = row.get(1)?;
+            if current.as_deref() != Some(session_path.as_str()) {
+                kept.clear();
+                current = Some(session_path.clone());
+            }
+            let text: String = row.get(3)?;
+            let Some(shingles) = shingles(&text) else {
+                continue;
+            };
+            // Compare against kept copies only, so a chain of small edits
+            // cannot drift arbitrarily far from the text that survives.
+            if kept
+                .iter()
+                .any(|earlier| is_near_duplicate(earlier, &shingles))
+            {
+                duplicates.push((row.get(0)?, session_path, row.get(2)?, text));
+            } else {
+                kept.push(shingles);
+            }
+        }
+    }
+
+    let mut stats = DedupStats::default();
+    if duplicates.is_empty() {
+        return Ok(stats);
+    }
+    // `message_id` is UNINDEXED, so find each duplicate's FTS row in a single
+    // pass and rewrite it by rowid rather than scanning once per message.
+    let mut fts_rowids: HashMap<i64, i64> = HashMap::new();
+    if !messages_fts_is_external(&tx)? {
+        let ids: HashSet<i64> = duplicates.iter().map(|duplicate| duplicate.0).collect();
+        let mut stmt = tx.prepare("SELECT rowid, message_id FROM messages_fts")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let message_id: i64 = row.get(1)?;
+            if ids.contains(&message_id) {
+                fts_rowids.insert(message_id, row.get(0)?);
+            }
+        }
+    }
+    {
+        let mut clear_message = tx.prepare("UPDATE messages SET text = '' WHERE id = ?1")?;
+        let mut delete_fts = tx.prepare("DELETE FROM messages_fts WHERE rowid = ?1")?;
+        let mut insert_fts = tx.prepare(
+            "INSERT INTO messages_fts (rowid, text, message_id, session_path, role)
+             VALUES (?1, '', ?2, ?3, ?4)",
+        )?;
+        let mut load_content =
+            tx.prepare("SELECT rowid, content FROM sessions_fts WHERE path = ?1")?;
+        let mut store_content =
+            tx.prepare("UPDATE sessions_fts SET content = ?2 WHERE rowid = ?1")?;
+        for session in duplicates.chunk_by(|a, b| a.1 == b.1) {
+            let mut content: Option<(i64, String)> = load_content
+                .query_row(params![&session[0].1], |row| Ok((row.get(0)?, row.get(1)?)))
+                .optional()?;
+            // Later copies sit later in the content, so cut from the end.
+            for (id, session_path, role, text) in session.iter().rev() {
+                clear_message.execute(params![id])?;
+                if let Some(rowid) = fts_rowids.get(id) {
+                    delete_fts.execute(params![rowid])?;
+                    insert_fts.execute(params![rowid, id, session_path, role])?;
+                }
+                stats.bytes_saved += text.len() as i64;
+                if let Some((_, content)) = content.as_mut() {
+                    let block = crate::parse::format_message_line(role, text);
+                    stats.bytes_saved += remove_last_block(content, &block) as i64;
+                }
+            }
+            if let Some((rowid, content)) = content {
+                store_content.execute(params![rowid, content])?;
+            }
+            stats.sessions += 1;
+            stats.messages += session.len();
+        }
+    }
+    tx.commit()?;
+    bump_write_generation();
+    Ok(stats)
+}
+
+/// Remove the last line-delimited occurrence of `block` from `content`,
+/// along with one adjoining newline. Returns how many bytes were removed.
+fn remove_last_block(content: &mut String, block: &str) -> usize {
+    let found = content
+        .rmatch_indices(block)
+        .map(|(start, _)| start)
+        .find(|&start| {
+            let end = start + block.len();
+            (start == 0 || content[..start].ends_with('\n'))
+                && (end == content.len() || content[end..].starts_with('\n'))
+        });
+    let Some(start) = found else {
+        return 0;
+    };
+    let end = start + block.len();
+    let range = if start > 0 {
+        start - 1..end
+    } else if end < content.len() {
+        start..end + 1
+    } else {
+        start..end
+    };
+    let removed = range.len();
+    content.replace_range(range, "");
+    removed
+}
+
+/// Whether `messages_fts` reads its text from `messages` (see
+/// [`use_external_message_fts`]) rather than storing a copy.
+pub fn messages_fts_is_external(conn: &Connection) -> Result<bool, IndexError> {
+    let sql: Option<String> = conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'messages_fts'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(sql.is_some_and(|sql| sql.contains("content_rowid")))
+}
+
+/// Rebuild `messages_fts` as an external-content table over `messages`, so
+/// message text is stored once. Returns `false` when it already is one.
+/// Run after [`init_schema`]; the index is rebuilt from the stored messages.
+pub fn use_external_message_fts(conn: &mut Connection) -> Result<bool, IndexError> {
+    if messages_fts_is_external(conn)? {
+        return Ok(false);
+    }
+    let tokenizer = fts_tokenizer(conn)?;
+    let tx = conn.transaction()?;
+    tx.execute_batch(&EXTERNAL_MESSAGES_FTS.replace("{tokenize}", tokenizer.name()))?;
+    tx.commit()?;
+    bump_write_generation();
+    Ok(true)
+}
+
+/// Whether `messages` already has a unique index on `(session_path, turn_index)`,
+/// either from the table constraint or from an earlier migration.
+fn has_unique_turn_index(conn: &Connection) -> Result<bool, IndexError> {
+    let found: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_index_list('messages') AS il
+         WHERE il.\"unique\" = 1
+           AND (SELECT group_concat(name, ',') FROM (
+                  SELECT name FROM pragma_index_info(il.name) ORDER BY seqno
+                )) = 'session_path,turn_index'",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(found > 0)
+}
+
+/// Add `column` to `table` unless it exists; returns whether it was added.
 fn ensure_column(
     conn: &Connection,
     table: &str,
     column: &str,
     col_type: &str,
-) -> Result<(), IndexError> {
-    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
-    let mut rows = stmt.query([])?;
-    while let Some(row) = rows.next()? {
-        let name: String = row.get(1)?;
-        if name == column {
-            return Ok(());
-        }
+) -> Result<bool, IndexError> {
+    if table_columns(conn, table)?
+        .iter()
+        .any(|name| name == column)
+    {
+        return Ok(false);
     }
 
     conn.execute(
         &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, col_type),
         [],
     )?;
-    Ok(())
+    Ok(true)
+}
+
+fn table_columns(conn: &Connection, table: &str) -> Result<Vec<String>, IndexError> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let mut rows = stmt.query([])?;
+    let mut columns = Vec::new();
+    while let Some(row) = rows.next()? {
+        columns.push(row.get(1)?);
+    }
+    Ok(columns)
 }
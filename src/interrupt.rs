@@ -0,0 +1,34 @@
+//! Ctrl-C handling for long-running commands.
+//!
+//! The library never touches signals; it only polls the flag passed in
+//! [`mmem::scan::IndexOptions::interrupt`]. This module wires Ctrl-C to that
+//! flag so an interrupted `mmem index` commits its progress instead of
+//! rolling it back. A second Ctrl-C exits immediately with status 130.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+static SIGINT_FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// Install the Ctrl-C handler (once) and return the flag it sets.
+///
+/// If the handler cannot be installed, a warning is printed and Ctrl-C
+/// keeps its default behavior.
+pub fn install_sigint_flag() -> Arc<AtomicBool> {
+    SIGINT_FLAG
+        .get_or_init(|| {
+            let flag = Arc::new(AtomicBool::new(false));
+            // The handler owns its own clone, so it never observes the flag
+            // half-initialized.
+            let handler_flag = Arc::clone(&flag);
+            if let Err(err) = ctrlc::set_handler(move || {
+                if handler_flag.swap(true, Ordering::Relaxed) {
+                    std::process::exit(130);
+                }
+            }) {
+                eprintln!("warning: failed to install Ctrl-C handler: {err}");
+            }
+            flag
+        })
+        .clone()
+}
@@ -19,12 +19,17 @@
 #![warn(clippy::too_many_arguments)]
 #![warn(clippy::too_many_lines)]
 
+#[cfg(feature = "query-cache")]
+pub mod cache;
+pub mod compress;
 pub mod doctor;
+pub mod export;
 pub mod index;
 pub mod model;
 pub mod parse;
 pub mod query;
 pub mod scan;
+pub mod search;
 pub mod session;
 pub mod stats;
 pub mod util;
@@ -1,26 +1,50 @@
 mod cli;
+mod interrupt;
+mod table;
 
 use clap::Parser;
-use mmem::doctor::run_doctor;
-use mmem::index::{configure_connection, init_schema};
+use mmem::doctor::{DoctorOptions, ProbeReport, probe_file, run_doctor_with};
+use mmem::export::{
+    ExportError, export_path_in, session_to_markdown, session_to_transcript, write_export,
+};
+use mmem::index::{
+    FtsTokenizer, configure_connection, dedup_session_content, init_schema, init_schema_with,
+    open_read_only, schema_is_current, use_external_message_fts,
+};
 use mmem::model::{MessageContext, MessageHit, SessionHit};
-use mmem::query::{FindFilters, FindScope, QueryMode, find_messages, find_sessions};
-use mmem::scan::index_root;
+use mmem::parse::{ParseOptions, ParseSpec, parse_file};
+use mmem::query::{
+    ExcerptMarkers, FindFilters, FindScope, FindSort, QueryMode, TermProbe, distinct_values,
+    find_messages, find_questions, find_sessions, for_each_message, probe_query_terms,
+};
+use mmem::scan::{FileAction, IndexOptions, import_file, index_root_with};
 use mmem::session::{
-    SessionEntry, ToolCallMatch, extract_tool_calls, load_entry_by_line, load_entry_by_turn,
-    resolve_session_path, scan_tool_calls,
+    SessionEntry, ToolCallMatch, ToolScanOptions, extract_tool_calls, load_entry_by_line_with,
+    load_entry_by_turn, resolve_session_path, scan_tool_calls_with, session_id_from_path,
 };
-use mmem::stats::{load_agents, load_stats};
+use mmem::stats::{db_footprint, load_agents, load_repo_activity, load_stats, load_tool_usage};
+use mmem::util::{normalize_timestamp, parse_date_bound};
 use rusqlite::Connection;
 use serde_json::{Map, Value};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use time::format_description::well_known::Rfc3339;
 use time::{Duration, OffsetDateTime};
 
 const MAX_OUTPUT_LEN: usize = 160;
 
+/// Cap applied by [`trim_output`] and the other text-shortening paths:
+/// [`MAX_OUTPUT_LEN`] unless overridden by `--trim`. Set once in `run`
+/// before any output is produced.
+static OUTPUT_LEN: AtomicUsize = AtomicUsize::new(MAX_OUTPUT_LEN);
+
+fn output_len() -> usize {
+    OUTPUT_LEN.load(Ordering::Relaxed)
+}
+
 fn main() {
     if let Err(error) = run() {
         eprintln!("error: {error}");
@@ -28,15 +52,47 @@ fn main() {
     }
 }
 
+/// Output preferences shared by every subcommand, taken from global flags.
+#[derive(Debug, Clone, Copy)]
+struct OutputOpts {
+    pretty: bool,
+}
+
+impl OutputOpts {
+    fn from_cli(cli: &cli::Cli) -> Self {
+        Self {
+            pretty: !cli.no_pretty,
+        }
+    }
+
+    /// Serialize a JSON document (not a JSON Lines record), honoring `--no-pretty`.
+    fn to_json<T: serde::Serialize + ?Sized>(self, value: &T) -> serde_json::Result<String> {
+        if self.pretty {
+            serde_json::to_string_pretty(value)
+        } else {
+            serde_json::to_string(value)
+        }
+    }
+}
+
 fn run() -> Result<(), Box<dyn std::error::Error>> {
     let cli = cli::Cli::parse();
+    let output = OutputOpts::from_cli(&cli);
+    if let Some(trim) = cli.trim {
+        OUTPUT_LEN.store(if trim == 0 { usize::MAX } else { trim }, Ordering::Relaxed);
+    }
     match cli.command {
-        cli::Command::Index(args) => handle_index(args),
-        cli::Command::Find(args) => handle_find(*args),
-        cli::Command::Show(args) => handle_show(args),
-        cli::Command::Stats(args) => handle_stats(args),
-        cli::Command::Agents(args) => handle_agents(args),
-        cli::Command::Doctor(args) => handle_doctor(args),
+        cli::Command::Index(args) => handle_index(args, output),
+        cli::Command::Import(args) => handle_import(args),
+        cli::Command::Find(args) => handle_find(*args, output),
+        cli::Command::Show(args) => handle_show(args, output),
+        cli::Command::Open(args) => handle_open(args),
+        cli::Command::Export(args) => handle_export(args),
+        cli::Command::Stats(args) => handle_stats(args, output),
+        cli::Command::Agents(args) => handle_agents(args, output),
+        cli::Command::Doctor(args) => handle_doctor(args, output),
+        cli::Command::Values(args) => handle_values(args, output),
+        cli::Command::DedupContent(args) => handle_dedup_content(args, output),
     }
 }
 
@@ -51,45 +107,209 @@ fn open_db() -> Result<Connection, Box<dyn std::error::Error>> {
     Ok(conn)
 }
 
-fn handle_index(args: cli::IndexArgs) -> Result<(), Box<dyn std::error::Error>> {
+/// Open the database read-only for a query command.
+///
+/// The schema is created or migrated through a writable connection only
+/// when `user_version` says it is missing or outdated, so queries running
+/// alongside `mmem index` neither redo the schema batch nor take the write
+/// lock.
+fn open_db_read() -> Result<Connection, Box<dyn std::error::Error>> {
+    let db_path = cli::default_db_path();
+    if db_path.exists() {
+        let conn = open_read_only(&db_path)?;
+        if schema_is_current(&conn)? {
+            return Ok(conn);
+        }
+    }
+
+    init_schema(&open_db()?)?;
+    Ok(open_read_only(&db_path)?)
+}
+
+fn handle_index(
+    args: cli::IndexArgs,
+    output: OutputOpts,
+) -> Result<(), Box<dyn std::error::Error>> {
     let mut conn = open_db()?;
-    init_schema(&conn)?;
+    let tokenizer = args.tokenizer.map(|tokenizer| match tokenizer {
+        cli::TokenizerArg::Unicode61 => FtsTokenizer::Unicode61,
+        cli::TokenizerArg::Trigram => FtsTokenizer::Trigram,
+    });
+    init_schema_with(&conn, tokenizer)?;
+    if args.external_content && use_external_message_fts(&mut conn)? {
+        eprintln!("converted the message index to external content");
+    }
 
     let root = args.root.unwrap_or_else(cli::default_sessions_root);
-    let stats = index_root(&mut conn, &root, args.full)?;
+    let options = IndexOptions {
+        full: args.full,
+        parse: ParseOptions {
+            timestamp_fields: args.timestamp_fields,
+            title_fields: args.title_fields,
+            agent_fields: args.agent_fields,
+            role_aliases: args.role_aliases,
+            lenient: args.lenient,
+            #[cfg(feature = "json5")]
+            lenient_json: args.json5,
+            strip_code_fences: args.strip_code_fences,
+        },
+        index_system: args.index_system,
+        preview_chars: args.preview_chars,
+        interrupt: Some(interrupt::install_sigint_flag()),
+        dry_run: args.dry_run,
+        no_remove: args.no_remove,
+        verify_hash: args.verify_hash,
+    };
+    let stats = index_root_with(&mut conn, &root, &options)?;
+    if stats.interrupted {
+        eprintln!("interrupted: committed {} indexed sessions", stats.indexed);
+    }
+    if args.dry_run {
+        eprintln!("dry run: the index was not changed");
+    }
 
     if args.json {
-        println!("{}", serde_json::to_string_pretty(&stats)?);
+        println!("{}", output.to_json(&stats)?);
         return Ok(());
     }
 
+    for planned in stats.plan.iter().flatten() {
+        if planned.action != FileAction::Skip {
+            let action = planned.action.name();
+            println!("{action:<6} {} ({})", planned.path, planned.reason);
+        }
+    }
     println!("scanned: {}", stats.scanned);
     println!("indexed: {}", stats.indexed);
     println!("skipped: {}", stats.skipped);
-    println!("removed: {}", stats.removed);
+    if stats.removal_skipped {
+        println!("removed: {} (skipped)", stats.removed);
+    } else {
+        println!("removed: {}", stats.removed);
+    }
+    println!("renamed: {}", stats.renamed);
     println!("parse_errors: {}", stats.parse_errors);
+    if args.lenient {
+        println!("skipped_lines: {}", stats.skipped_lines);
+    }
 
     Ok(())
 }
 
-fn handle_find(args: cli::FindArgs) -> Result<(), Box<dyn std::error::Error>> {
-    let conn = open_db()?;
+fn handle_import(args: cli::ImportArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let spec_text = std::fs::read_to_string(&args.spec)?;
+    let spec: ParseSpec = serde_json::from_str(&spec_text)
+        .map_err(|err| format!("invalid spec {}: {}", args.spec.display(), err))?;
+
+    let mut conn = open_db()?;
     init_schema(&conn)?;
 
-    let scope = match args.scope {
+    let options = IndexOptions {
+        index_system: args.index_system,
+        ..Default::default()
+    };
+    for file in &args.files {
+        import_file(&mut conn, file, &spec, &options)?;
+        println!("imported: {}", file.display());
+    }
+
+    Ok(())
+}
+
+fn handle_find(args: cli::FindArgs, output: OutputOpts) -> Result<(), Box<dyn std::error::Error>> {
+    let conn = open_db_read()?;
+
+    let default_scope = if args.any_field {
+        cli::FindScopeArg::Session
+    } else {
+        cli::FindScopeArg::Message
+    };
+    let scope = match args.scope.unwrap_or(default_scope) {
         cli::FindScopeArg::Session => FindScope::Session,
         cli::FindScopeArg::Message => FindScope::Message,
+        cli::FindScopeArg::Question => FindScope::Question,
     };
 
-    let role = normalize_role_filter(args.role.as_deref(), args.include_assistant);
+    if args.after_match.is_some() && scope == FindScope::Session {
+        return Err("--after-match requires --scope message or question".into());
+    }
+    if args.explain_matches && scope == FindScope::Session {
+        return Err("--explain-matches requires --scope message or question".into());
+    }
+    if args.any_field && scope != FindScope::Session {
+        return Err("--any-field requires --scope session".into());
+    }
+    if args.export_to.is_some() && scope != FindScope::Session {
+        return Err("--export-to requires --scope session".into());
+    }
+
+    let format = match (args.json, args.jsonl) {
+        (true, _) => cli::FindFormatArg::Json,
+        (_, true) => cli::FindFormatArg::Jsonl,
+        _ => args.format.unwrap_or(cli::FindFormatArg::Text),
+    };
+    if args.summary_line && format != cli::FindFormatArg::Jsonl {
+        return Err("--summary-line requires JSONL output (--jsonl or --format jsonl)".into());
+    }
+    let role = match scope {
+        // Session scope has no user-only default: the role narrows which
+        // sessions match only when asked for explicitly.
+        FindScope::Session => normalize_role_filter(args.role.as_deref(), true, None),
+        FindScope::Message => {
+            let configured = std::env::var("MMEM_DEFAULT_ROLES").ok();
+            let default = message_default_roles(args.fts, configured.as_deref());
+            normalize_role_filter(
+                args.role.as_deref(),
+                args.include_assistant,
+                default.as_deref(),
+            )
+        }
+        // Question scope always searches user turns.
+        FindScope::Question => None,
+    };
     let fields_specified = args.fields.is_some();
-    let field_set = build_field_set(args.fields.as_deref(), scope);
-    let include_context = args.around > 0 && (!fields_specified || field_set.contains("context"));
-    let around = if args.json || args.jsonl {
-        if include_context { args.around } else { 0 }
+    let mut field_set = build_field_set(args.fields.as_deref(), scope);
+    if format == cli::FindFormatArg::Markdown && !fields_specified {
+        field_set.insert(match scope {
+            FindScope::Session => "snippet".to_string(),
+            FindScope::Message | FindScope::Question => "text".to_string(),
+        });
+    }
+    if args.verify_paths {
+        field_set.insert("exists".to_string());
+    }
+    if args.explain_matches {
+        field_set.insert("matched_terms".to_string());
+    }
+    let excerpt = if scope == FindScope::Session {
+        None
+    } else if args.snippet || field_set.contains("excerpt") {
+        if args.snippet && format != cli::FindFormatArg::Text {
+            field_set.insert("excerpt".to_string());
+        }
+        // Human-facing formats mark the matched terms; JSON stays plain
+        // unless markers are asked for.
+        let (open, close) = match (args.snippet_markers.clone(), format) {
+            (Some(markers), _) => markers,
+            (None, cli::FindFormatArg::Text | cli::FindFormatArg::Table) => {
+                ("[".to_string(), "]".to_string())
+            }
+            (None, cli::FindFormatArg::Markdown) => ("**".to_string(), "**".to_string()),
+            (None, cli::FindFormatArg::Json | cli::FindFormatArg::Jsonl) => {
+                (String::new(), String::new())
+            }
+        };
+        Some(ExcerptMarkers { open, close })
     } else {
+        None
+    };
+    let include_context = args.around > 0 && (!fields_specified || field_set.contains("context"));
+    let around = if format == cli::FindFormatArg::Text || include_context {
         args.around
+    } else {
+        0
     };
+    let context_chars = args.context_chars.unwrap_or_else(output_len);
 
     let mut filters = FindFilters {
         agent: args.agent.clone(),
@@ -101,8 +321,36 @@ fn handle_find(args: cli::FindArgs) -> Result<(), Box<dyn std::error::Error>> {
         before: args.before.clone(),
         limit: args.limit,
         around,
+        exclude_self_context: !args.include_context_self,
+        after_match: args.after_match.clone(),
+        path_contains: args.path_contains.clone(),
+        command_contains: args.command_contains.clone(),
+        min_exchanges: args.min_exchanges,
+        not_agent: args.not_agent.clone(),
+        not_workspace: args.not_workspace.clone(),
+        not_repo: args.not_repo.clone(),
+        per_session_limit: args.per_session_limit,
+        drop_stopwords: !args.no_stopwords,
+        stopwords: args.stopwords.clone(),
         scope,
-        query_mode: if args.fts { QueryMode::Fts } else { QueryMode::Literal },
+        query_mode: match (args.fts, args.near) {
+            (true, _) => QueryMode::Fts,
+            (false, Some(distance)) => QueryMode::Near(distance),
+            (false, None) => QueryMode::Literal,
+        },
+        sort: match args.sort {
+            cli::FindSortArg::Relevance => FindSort::Relevance,
+            cli::FindSortArg::Newest => FindSort::Newest,
+            cli::FindSortArg::Oldest => FindSort::Oldest,
+        },
+        reverse: args.reverse,
+        prefer_agent: args.prefer_agent.clone(),
+        merge_continuations: args.merge_continuations,
+        explain_matches: args.explain_matches,
+        excerpt,
+        any_field: args.any_field,
+        match_snippets: scope == FindScope::Session
+            && (args.snippet || field_set.contains("snippet")),
     };
 
     if filters.after.is_none()
@@ -111,34 +359,183 @@ fn handle_find(args: cli::FindArgs) -> Result<(), Box<dyn std::error::Error>> {
         let cutoff = OffsetDateTime::now_utc() - Duration::days(days as i64);
         filters.after = Some(cutoff.format(&Rfc3339)?);
     }
+    if let (Some(after), Some(before)) = (filters.after.as_deref(), filters.before.as_deref())
+        && let (Some(start), Some(end)) = (parse_date_bound(after), parse_date_bound(before))
+        && start > end
+    {
+        eprintln!(
+            "warning: --after {after} is later than --before {before}; \
+             no results can match this range (did you swap them?)"
+        );
+    }
 
-    match scope {
+    // `fetched` counts hits before --drop-missing removes any, so a full
+    // page still reads as truncated.
+    let (count, fetched) = match scope {
         FindScope::Session => {
-            let results = find_sessions(&conn, &args.query, &filters)?;
-            if args.json || args.jsonl {
-                emit_sessions_json(&results, &field_set, args.jsonl)?;
-            } else {
-                emit_sessions_text(&results, args.snippet);
+            let mut results = find_sessions(&conn, &args.query, &filters)?;
+            let fetched = results.len();
+            if args.drop_missing {
+                results.retain(|hit| Path::new(&hit.path).exists());
             }
+            if args.iso_timestamps {
+                for hit in &mut results {
+                    iso_timestamp(&mut hit.last_message_at);
+                }
+            }
+            if let Some(dir) = args.export_to.as_deref() {
+                let written = export_sessions(&results, dir)?;
+                println!("exported {written} sessions to {}", dir.display());
+                return Ok(());
+            }
+            match format {
+                cli::FindFormatArg::Json | cli::FindFormatArg::Jsonl => {
+                    let jsonl = format == cli::FindFormatArg::Jsonl;
+                    emit_sessions_json(&results, &field_set, jsonl, args.null_fields, output)?;
+                }
+                cli::FindFormatArg::Markdown => emit_sessions_markdown(&results, &field_set),
+                cli::FindFormatArg::Table => {
+                    let values: Vec<Value> = results
+                        .iter()
+                        .map(|hit| session_to_json(hit, &field_set, false))
+                        .collect();
+                    emit_table(
+                        &values,
+                        &table_columns(args.fields.as_deref(), &field_set, scope),
+                    );
+                }
+                cli::FindFormatArg::Text => {
+                    emit_sessions_text(
+                        &results,
+                        args.snippet,
+                        args.show_score,
+                        args.preserve_whitespace,
+                    );
+                }
+            }
+            (results.len(), fetched)
         }
-        FindScope::Message => {
-            let results = find_messages(&conn, &args.query, &filters)?;
-            if args.json || args.jsonl {
-                emit_messages_json(&results, &field_set, include_context, args.jsonl)?;
+        FindScope::Message if format == cli::FindFormatArg::Jsonl => {
+            // Stream each hit as soon as it and its context are loaded.
+            let mut emitted = 0;
+            let mut fetched = 0;
+            for_each_message(&conn, &args.query, &filters, |mut hit| {
+                fetched += 1;
+                if args.drop_missing && !Path::new(&hit.path).exists() {
+                    return Ok(());
+                }
+                if args.iso_timestamps {
+                    iso_message_timestamps(&mut hit);
+                }
+                let value = message_to_json(
+                    &hit,
+                    &field_set,
+                    include_context,
+                    context_chars,
+                    args.null_fields,
+                );
+                println!("{}", serde_json::to_string(&value)?);
+                emitted += 1;
+                Ok::<_, Box<dyn std::error::Error>>(())
+            })?;
+            (emitted, fetched)
+        }
+        FindScope::Message | FindScope::Question => {
+            let mut results = if scope == FindScope::Question {
+                find_questions(&conn, &args.query, &filters)?
             } else {
-                emit_messages_text(&results, args.snippet, around);
+                find_messages(&conn, &args.query, &filters)?
+            };
+            let fetched = results.len();
+            if args.drop_missing {
+                results.retain(|hit| Path::new(&hit.path).exists());
             }
+            if args.iso_timestamps {
+                results.iter_mut().for_each(iso_message_timestamps);
+            }
+            match format {
+                cli::FindFormatArg::Json | cli::FindFormatArg::Jsonl => {
+                    emit_messages_json(
+                        &results,
+                        &field_set,
+                        include_context,
+                        context_chars,
+                        format == cli::FindFormatArg::Jsonl,
+                        args.null_fields,
+                        output,
+                    )?;
+                }
+                cli::FindFormatArg::Markdown => {
+                    emit_messages_markdown(&results, &field_set, include_context, context_chars);
+                }
+                cli::FindFormatArg::Table => {
+                    let values: Vec<Value> = results
+                        .iter()
+                        .map(|hit| message_to_json(hit, &field_set, false, context_chars, false))
+                        .collect();
+                    emit_table(
+                        &values,
+                        &table_columns(args.fields.as_deref(), &field_set, scope),
+                    );
+                }
+                cli::FindFormatArg::Text => {
+                    let emit = if args.group {
+                        emit_messages_grouped
+                    } else {
+                        emit_messages_text
+                    };
+                    emit(
+                        &results,
+                        args.snippet,
+                        args.show_score,
+                        around,
+                        context_chars,
+                        args.preserve_whitespace,
+                    );
+                }
+            }
+            (results.len(), fetched)
         }
+    };
+
+    if format == cli::FindFormatArg::Text && count > 0 && fetched >= filters.effective_limit() {
+        println!("… (showing {count}, more may exist — increase --limit)");
+    }
+    if format == cli::FindFormatArg::Text
+        && count == 0
+        && let Some(hint) = zero_result_hint(
+            &args.query,
+            &probe_query_terms(&conn, &args.query, &filters)?,
+        )
+    {
+        println!("{hint}");
+    }
+
+    if args.summary_line {
+        let summary = serde_json::json!({
+            "type": "summary",
+            "count": count,
+            // Every query stops at the limit, so a full page means more hits
+            // are likely.
+            "truncated": fetched >= filters.effective_limit(),
+        });
+        println!("{}", serde_json::to_string(&summary)?);
     }
 
     Ok(())
 }
 
-fn handle_show(args: cli::ShowArgs) -> Result<(), Box<dyn std::error::Error>> {
-    let tool_filter = if args.turn.is_none() && args.line.is_none() && args.tool.is_none() {
-        Some("read")
+fn handle_show(args: cli::ShowArgs, output: OutputOpts) -> Result<(), Box<dyn std::error::Error>> {
+    // A per-tool cap is for sampling across tools, so it lifts the `read`
+    // default.
+    let tool_filter: Vec<&str> = if args.turn.is_none()
+        && args.line.is_none()
+        && args.tool.is_empty()
+        && args.per_tool_limit.is_none()
+    {
+        vec!["read"]
     } else {
-        args.tool.as_deref()
+        args.tool.iter().map(String::as_str).collect()
     };
     let mut out = std::io::BufWriter::new(std::io::stdout());
 
@@ -147,20 +544,39 @@ fn handle_show(args: cli::ShowArgs) -> Result<(), Box<dyn std::error::Error>> {
 
     if let Some(turn) = args.turn {
         let entry = load_entry_by_turn(&path, turn)?;
-        emit_show_entry(&mut out, &entry, tool_filter, args.extract, args.json)?;
+        emit_show_entry(
+            &mut out,
+            &entry,
+            &tool_filter,
+            args.extract,
+            args.json,
+            output,
+        )?;
         return Ok(());
     }
 
     if let Some(line) = args.line {
-        let entry = load_entry_by_line(&path, line)?;
-        emit_show_entry(&mut out, &entry, tool_filter, args.extract, args.json)?;
+        let entry = load_entry_by_line_with(&path, line, !args.raw)?;
+        emit_show_entry(
+            &mut out,
+            &entry,
+            &tool_filter,
+            args.extract,
+            args.json,
+            output,
+        )?;
         return Ok(());
     }
 
-    let matches = scan_tool_calls(&path, tool_filter, args.limit)?;
+    let options = ToolScanOptions {
+        limit: args.limit,
+        per_tool_limit: args.per_tool_limit,
+        offset: args.offset,
+    };
+    let matches = scan_tool_calls_with(&path, &tool_filter, &options)?;
     if args.json {
         let values: Vec<Value> = matches.into_iter().map(tool_match_to_json).collect();
-        let _ = writeln!(out, "{}", serde_json::to_string_pretty(&values)?);
+        let _ = writeln!(out, "{}", output.to_json(&values)?);
         return Ok(());
     }
 
@@ -176,20 +592,154 @@ fn handle_show(args: cli::ShowArgs) -> Result<(), Box<dyn std::error::Error>> {
             .unwrap_or_else(|| "turn ?".to_string());
         let _ = writeln!(out, "line {} ({}) tool={}", item.line, turn, item.tool.name);
         let _ = writeln!(out, "{}", format_tool_args(&item.tool.arguments));
+        if let Some(output) = item.output.as_deref() {
+            let _ = writeln!(out, "output: {}", trim_output(output));
+        }
         let _ = writeln!(out);
     }
 
     Ok(())
 }
 
-fn handle_stats(args: cli::StatsArgs) -> Result<(), Box<dyn std::error::Error>> {
-    let conn = open_db()?;
-    init_schema(&conn)?;
+fn handle_export(args: cli::ExportArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let root = cli::default_sessions_root();
+    let path = resolve_session_path(&args.target, &root)?;
+    let session = parse_file(&path)?;
+    let markdown = if args.role_prefix {
+        session_to_transcript(&session)
+    } else {
+        session_to_markdown(&session)
+    };
+
+    match args.out {
+        Some(out) => {
+            write_export(&out, &markdown, args.force)?;
+            println!("exported: {}", out.display());
+        }
+        None => print!("{markdown}"),
+    }
+
+    Ok(())
+}
+
+/// `--iso-timestamps`: rewrite a stored timestamp as RFC 3339 UTC, keeping
+/// values that are not recognizably a time as they are.
+fn iso_timestamp(value: &mut Option<String>) {
+    if let Some(iso) = value.as_deref().and_then(normalize_timestamp) {
+        *value = Some(iso);
+    }
+}
 
-    let stats = load_stats(&conn)?;
+fn iso_message_timestamps(hit: &mut MessageHit) {
+    iso_timestamp(&mut hit.timestamp);
+    for message in hit.context.iter_mut().flatten() {
+        iso_timestamp(&mut message.timestamp);
+    }
+}
+
+/// Write each session in `results` as Markdown into `dir`, returning how
+/// many files were written. A session that cannot be read or written is
+/// reported and skipped rather than failing the batch.
+fn export_sessions(
+    results: &[SessionHit],
+    dir: &Path,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(dir)?;
+    let mut taken = HashSet::new();
+    let mut written = 0;
+    for hit in results {
+        let session = match parse_file(Path::new(&hit.path)) {
+            Ok(session) => session,
+            Err(err) => {
+                eprintln!("warning: skipped {}: {err}", hit.path);
+                continue;
+            }
+        };
+        let session_id = session_id_from_path(&hit.path).unwrap_or_else(|| "session".to_string());
+        let out = export_path_in(dir, hit.title.as_deref(), &session_id, &taken);
+        taken.insert(out.clone());
+        match write_export(&out, &session_to_markdown(&session), false) {
+            Ok(()) => written += 1,
+            // `find` has no --force, so name the clash without suggesting one.
+            Err(ExportError::Exists { path }) => {
+                eprintln!(
+                    "warning: skipped {}: {} already exists",
+                    hit.path,
+                    path.display()
+                );
+            }
+            Err(err) => eprintln!("warning: skipped {}: {err}", hit.path),
+        }
+    }
+    Ok(written)
+}
+
+fn handle_open(args: cli::OpenArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let root = cli::default_sessions_root();
+    let path = resolve_session_path(&args.target, &root)?;
+
+    let (program, prefix_args) = platform_opener();
+    let status = match std::process::Command::new(program)
+        .args(prefix_args)
+        .arg(&path)
+        .status()
+    {
+        Ok(status) => status,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Err(format!(
+                "no file opener available (`{}` not found); session file: {}",
+                program,
+                path.display()
+            )
+            .into());
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    if !status.success() {
+        return Err(format!(
+            "`{}` failed ({}) opening {}",
+            program,
+            status,
+            path.display()
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Command (and leading arguments) that opens a file with the user's
+/// default application on this platform.
+fn platform_opener() -> (&'static str, &'static [&'static str]) {
+    if cfg!(target_os = "macos") {
+        ("open", &[])
+    } else if cfg!(windows) {
+        // `start` is a cmd builtin; the empty string is the window title.
+        ("cmd", &["/C", "start", ""])
+    } else {
+        ("xdg-open", &[])
+    }
+}
+
+fn handle_stats(
+    args: cli::StatsArgs,
+    output: OutputOpts,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let conn = open_db_read()?;
+
+    if args.tools {
+        return emit_tool_usage(&conn, args.json, output);
+    }
+    if args.by_repo {
+        return emit_repo_activity(&conn, args.json, output);
+    }
+
+    let mut stats = load_stats(&conn)?;
+    stats.footprint = Some(db_footprint(&cli::default_db_path())?);
 
     if args.json {
-        println!("{}", serde_json::to_string_pretty(&stats)?);
+        println!("{}", output.to_json(&stats)?);
         return Ok(());
     }
 
@@ -210,18 +760,99 @@ fn handle_stats(args: cli::StatsArgs) -> Result<(), Box<dyn std::error::Error>>
         Some(count) => println!("parse_failures: {}", count),
         None => println!("parse_failures: unknown"),
     }
+    if let Some(footprint) = stats.footprint {
+        println!("db_path: {}", footprint.db_path.display());
+        println!("db_size_bytes: {}", footprint.db_size_bytes);
+        if let Some(wal) = footprint.wal_size_bytes {
+            println!("wal_size_bytes: {}", wal);
+        }
+    }
 
     Ok(())
 }
 
-fn handle_agents(args: cli::AgentsArgs) -> Result<(), Box<dyn std::error::Error>> {
-    let conn = open_db()?;
-    init_schema(&conn)?;
+fn emit_tool_usage(
+    conn: &Connection,
+    json: bool,
+    output: OutputOpts,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tools = load_tool_usage(conn)?;
+
+    if json {
+        println!("{}", output.to_json(&tools)?);
+        return Ok(());
+    }
+
+    if tools.is_empty() {
+        println!("no tool calls indexed (run `mmem index --full` to populate)");
+        return Ok(());
+    }
+
+    let width = tools
+        .iter()
+        .map(|usage| usage.tool.len())
+        .max()
+        .unwrap_or(0);
+    for usage in tools {
+        println!("{:<width$}  {}", usage.tool, usage.count);
+    }
+
+    Ok(())
+}
+
+fn emit_repo_activity(
+    conn: &Connection,
+    json: bool,
+    output: OutputOpts,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repos = load_repo_activity(conn)?;
+
+    if json {
+        println!("{}", output.to_json(&repos)?);
+        return Ok(());
+    }
+
+    if repos.is_empty() {
+        println!("no sessions indexed");
+        return Ok(());
+    }
+
+    let width = repos
+        .iter()
+        .map(|repo| repo.repo.chars().count())
+        .max()
+        .unwrap_or(0);
+    let digits = |count: i64| count.to_string().len();
+    let sessions_width = repos
+        .iter()
+        .map(|repo| digits(repo.sessions))
+        .max()
+        .unwrap_or(0);
+    let messages_width = repos
+        .iter()
+        .map(|repo| digits(repo.messages))
+        .max()
+        .unwrap_or(0);
+    for repo in repos {
+        println!(
+            "{:<width$}  {:>sessions_width$} sessions  {:>messages_width$} messages",
+            repo.repo, repo.sessions, repo.messages
+        );
+    }
+
+    Ok(())
+}
+
+fn handle_agents(
+    args: cli::AgentsArgs,
+    output: OutputOpts,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let conn = open_db_read()?;
 
     let agents = load_agents(&conn)?;
 
     if args.json {
-        println!("{}", serde_json::to_string_pretty(&agents)?);
+        println!("{}", output.to_json(&agents)?);
         return Ok(());
     }
 
@@ -237,14 +868,36 @@ fn handle_agents(args: cli::AgentsArgs) -> Result<(), Box<dyn std::error::Error>
     Ok(())
 }
 
-fn handle_doctor(args: cli::DoctorArgs) -> Result<(), Box<dyn std::error::Error>> {
+fn handle_doctor(
+    args: cli::DoctorArgs,
+    output: OutputOpts,
+) -> Result<(), Box<dyn std::error::Error>> {
     let db_path = cli::default_db_path();
     let root = cli::default_sessions_root();
 
-    let report = run_doctor(&db_path, &root);
+    let output = if args.jsonl {
+        OutputOpts { pretty: false }
+    } else {
+        output
+    };
 
-    if args.json {
-        println!("{}", serde_json::to_string_pretty(&report)?);
+    if let Some(path) = args.probe.as_deref() {
+        let report = probe_file(&db_path, &root, path);
+        if args.json || args.jsonl {
+            println!("{}", output.to_json(&report)?);
+        } else {
+            emit_probe_text(&report);
+        }
+        return Ok(());
+    }
+
+    let options = DoctorOptions {
+        repair: args.repair,
+    };
+    let report = run_doctor_with(&db_path, &root, options);
+
+    if args.json || args.jsonl {
+        println!("{}", output.to_json(&report)?);
         return Ok(());
     }
 
@@ -257,6 +910,9 @@ fn handle_doctor(args: cli::DoctorArgs) -> Result<(), Box<dyn std::error::Error>
         println!("schema_error: {}", error);
     }
     println!("fts5_available: {}", report.fts5_available);
+    println!("sqlite_version: {}", report.sqlite_version);
+    println!("json1_available: {}", report.json1_available);
+    println!("trigram_available: {}", report.trigram_available);
     println!("indexed_sessions: {}", report.indexed_sessions);
     println!(
         "newest_message_at: {}",
@@ -264,42 +920,215 @@ fn handle_doctor(args: cli::DoctorArgs) -> Result<(), Box<dyn std::error::Error>
             .newest_message_at
             .unwrap_or_else(|| "(unknown)".to_string())
     );
+    println!("orphaned_messages: {}", report.orphaned_messages);
+    if args.repair {
+        println!("repaired_messages: {}", report.repaired_messages);
+    } else if report.orphaned_messages > 0 {
+        println!("hint: run `mmem doctor --repair` to delete orphaned messages");
+    }
 
     Ok(())
 }
 
-fn normalize_role_filter(role: Option<&str>, include_assistant: bool) -> Option<String> {
-    if include_assistant {
-        return role
-            .map(|value| value.trim().to_lowercase())
-            .filter(|v| !v.is_empty());
+/// Explain an empty literal search from per-term probes, e.g. `no results
+/// for 'foo bar baz'; 'foo' and 'baz' match but 'bar' does not`.
+fn zero_result_hint(query: &str, probes: &[TermProbe]) -> Option<String> {
+    if probes.len() < 2 {
+        return None;
     }
+    let (matched, missing): (Vec<&TermProbe>, Vec<&TermProbe>) =
+        probes.iter().partition(|probe| probe.matches);
+    let list = |probes: &[&TermProbe]| {
+        let quoted: Vec<String> = probes
+            .iter()
+            .map(|probe| format!("'{}'", probe.term))
+            .collect();
+        match quoted.split_last() {
+            Some((last, rest)) if !rest.is_empty() => format!("{} and {last}", rest.join(", ")),
+            _ => quoted.concat(),
+        }
+    };
+    let detail = match (matched.is_empty(), missing.is_empty()) {
+        (true, _) => "no term matches on its own".to_string(),
+        (false, true) => "every term matches on its own, but not together".to_string(),
+        (false, false) => format!(
+            "{} {} but {} {}",
+            list(&matched),
+            if matched.len() == 1 {
+                "matches"
+            } else {
+                "match"
+            },
+            list(&missing),
+            if missing.len() == 1 {
+                "does not"
+            } else {
+                "do not"
+            },
+        ),
+    };
+    Some(format!("no results for '{}'; {detail}", query.trim()))
+}
 
-    let value = role
-        .map(|value| value.trim().to_lowercase())
-        .filter(|value| !value.is_empty())
-        .unwrap_or_else(|| "user".to_string());
-    Some(value)
+fn emit_probe_text(report: &ProbeReport) {
+    let or_unknown = |value: Option<String>| value.unwrap_or_else(|| "(unknown)".to_string());
+    println!("path: {}", report.path.display());
+    println!("parsed: {}", report.parsed);
+    println!(
+        "message_count: {}",
+        or_unknown(report.message_count.map(|count| count.to_string()))
+    );
+    println!("title: {}", or_unknown(report.title.clone()));
+    println!("agent: {}", or_unknown(report.agent.clone()));
+    println!("indexed: {}", report.indexed);
+    println!(
+        "stored_mtime: {}",
+        or_unknown(report.stored_mtime.map(|mtime| mtime.to_string()))
+    );
+    println!(
+        "current_mtime: {}",
+        or_unknown(report.current_mtime.map(|mtime| mtime.to_string()))
+    );
+    if let Some(reason) = report.reason_not_indexed.as_deref() {
+        println!("reason_not_indexed: {}", reason);
+    }
 }
 
-fn build_field_set(fields: Option<&[String]>, scope: FindScope) -> HashSet<String> {
-    let defaults: &[&str] = match scope {
-        FindScope::Session => &["path", "title", "last_message_at", "score"],
-        FindScope::Message => &["path", "title", "timestamp", "role", "turn_index", "score"],
+fn handle_values(
+    args: cli::ValuesArgs,
+    output: OutputOpts,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let conn = open_db_read()?;
+
+    let column = match args.field {
+        cli::ValuesFieldArg::Agent => "agent",
+        cli::ValuesFieldArg::Workspace => "workspace",
+        cli::ValuesFieldArg::Repo => "repo_name",
+        cli::ValuesFieldArg::Branch => "branch",
     };
+    let values = distinct_values(&conn, column)?;
+
+    if args.json {
+        println!("{}", output.to_json(&values)?);
+        return Ok(());
+    }
+
+    for value in values {
+        println!("{}", value);
+    }
+
+    Ok(())
+}
+
+fn handle_dedup_content(
+    args: cli::DedupContentArgs,
+    output: OutputOpts,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut conn = open_db()?;
+    init_schema(&conn)?;
+    let stats = dedup_session_content(&mut conn)?;
+
+    if args.json {
+        println!("{}", output.to_json(&stats)?);
+        return Ok(());
+    }
+
+    println!("sessions: {}", stats.sessions);
+    println!("messages: {}", stats.messages);
+    println!("bytes_saved: {}", stats.bytes_saved);
+    if stats.bytes_saved > 0 {
+        println!(
+            "hint: run `sqlite3 {} VACUUM` to shrink the database file",
+            cli::default_db_path().display()
+        );
+    }
 
+    Ok(())
+}
+
+/// Resolve the message role filter for `find`.
+///
+/// An explicit `--role` always wins, so `system`, `developer`, and `tool`
+/// messages are reachable by name. Without one, `default` applies (`None`
+/// matches every role); `--include-assistant` lifts the filter entirely so
+/// every role matches. Roles may be comma-separated lists.
+fn normalize_role_filter(
+    role: Option<&str>,
+    include_assistant: bool,
+    default: Option<&str>,
+) -> Option<String> {
+    let role = role.and_then(normalize_role_list);
+    if include_assistant || role.is_some() {
+        return role;
+    }
+
+    default.and_then(normalize_role_list)
+}
+
+/// The role scope `find` uses when `--role` is not given, from
+/// `MMEM_DEFAULT_ROLES`: unset means `user`, `all` means every role, and
+/// anything else is a role or comma-separated list of roles.
+fn default_roles(value: Option<&str>) -> Option<String> {
+    match value.map(str::trim) {
+        None | Some("") => Some("user".to_string()),
+        Some(value) if value.eq_ignore_ascii_case("all") => None,
+        Some(value) => normalize_role_list(value),
+    }
+}
+
+/// The default role scope at message scope. Raw `--fts` queries get none:
+/// they express their own intent, so only an explicit `--role` narrows them.
+fn message_default_roles(fts: bool, configured: Option<&str>) -> Option<String> {
+    if fts { None } else { default_roles(configured) }
+}
+
+/// Lowercase and tidy a `user, Assistant` style role list.
+fn normalize_role_list(value: &str) -> Option<String> {
+    let roles: Vec<String> = value
+        .split(',')
+        .map(|role| role.trim().to_lowercase())
+        .filter(|role| !role.is_empty())
+        .collect();
+    if roles.is_empty() {
+        None
+    } else {
+        Some(roles.join(","))
+    }
+}
+
+fn default_fields(scope: FindScope) -> &'static [&'static str] {
+    match scope {
+        FindScope::Session => &["path", "session_id", "title", "last_message_at", "score"],
+        FindScope::Message | FindScope::Question => &[
+            "path",
+            "session_id",
+            "title",
+            "timestamp",
+            "role",
+            "turn_index",
+            "line",
+            "score",
+        ],
+    }
+}
+
+fn normalize_field(field: &str) -> String {
+    field.trim().to_lowercase().replace('-', "_")
+}
+
+fn build_field_set(fields: Option<&[String]>, scope: FindScope) -> HashSet<String> {
     let mut set = HashSet::new();
     match fields {
         Some(fields) => {
             for field in fields {
-                let field = field.trim().to_lowercase();
+                let field = normalize_field(field);
                 if !field.is_empty() {
                     set.insert(field);
                 }
             }
         }
         None => {
-            for field in defaults {
+            for field in default_fields(scope) {
                 set.insert((*field).to_string());
             }
         }
@@ -308,7 +1137,70 @@ fn build_field_set(fields: Option<&[String]>, scope: FindScope) -> HashSet<Strin
     set
 }
 
-fn emit_sessions_text(results: &[SessionHit], show_snippet: bool) {
+/// Table columns in `--fields` order (or the default order), followed by any
+/// fields switched on by other flags. Nested `context` has no cell form.
+fn table_columns(
+    fields: Option<&[String]>,
+    field_set: &HashSet<String>,
+    scope: FindScope,
+) -> Vec<String> {
+    let mut columns: Vec<String> = match fields {
+        Some(fields) => fields.iter().map(|field| normalize_field(field)).collect(),
+        None => default_fields(scope)
+            .iter()
+            .map(|field| field.to_string())
+            .collect(),
+    };
+    let mut extra: Vec<&String> = field_set
+        .iter()
+        .filter(|field| !columns.contains(field))
+        .collect();
+    extra.sort();
+    columns.extend(extra.into_iter().cloned());
+    let mut seen = HashSet::new();
+    columns.retain(|field| !field.is_empty() && field != "context" && seen.insert(field.clone()));
+    columns
+}
+
+/// Render already-built JSON objects as a [`table`] with one column per field.
+fn emit_table(values: &[Value], columns: &[String]) {
+    let headers: Vec<String> = columns.iter().map(|column| column.to_uppercase()).collect();
+    let rows: Vec<Vec<String>> = values
+        .iter()
+        .map(|value| {
+            columns
+                .iter()
+                .map(|column| table_cell(value.get(column.as_str())))
+                .collect()
+        })
+        .collect();
+    for line in table::render_table(&headers, &rows, table::terminal_width()) {
+        println!("{line}");
+    }
+}
+
+fn table_cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(text)) => collapse_whitespace(text),
+        Some(Value::Number(number)) if number.is_f64() => {
+            format!("{:.2}", number.as_f64().unwrap_or_default())
+        }
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| table_cell(Some(item)))
+            .collect::<Vec<_>>()
+            .join(", "),
+        Some(other) => other.to_string(),
+    }
+}
+
+fn emit_sessions_text(
+    results: &[SessionHit],
+    show_snippet: bool,
+    show_score: bool,
+    preserve_whitespace: bool,
+) {
     for hit in results {
         let title = hit
             .title
@@ -318,10 +1210,15 @@ fn emit_sessions_text(results: &[SessionHit], show_snippet: bool) {
             .last_message_at
             .clone()
             .unwrap_or_else(|| "(unknown)".to_string());
-        println!("{} | {}", when, title);
+        println!(
+            "{} | {}{}",
+            when,
+            title,
+            score_suffix(hit.score, show_score)
+        );
         println!("{}", hit.path);
         if show_snippet && let Some(snippet) = hit.snippet.as_deref() {
-            let snippet = trim_output(snippet);
+            let snippet = text_output(snippet, output_len(), preserve_whitespace);
             if !snippet.is_empty() {
                 println!("{}", snippet);
             }
@@ -330,7 +1227,24 @@ fn emit_sessions_text(results: &[SessionHit], show_snippet: bool) {
     }
 }
 
-fn emit_messages_text(results: &[MessageHit], show_snippet: bool, around: usize) {
+/// Header suffix for `--show-score`: the raw bm25 score, where lower (more
+/// negative) means more relevant.
+fn score_suffix(score: f64, show_score: bool) -> String {
+    if show_score {
+        format!(" [score {score:.2}]")
+    } else {
+        String::new()
+    }
+}
+
+fn emit_messages_text(
+    results: &[MessageHit],
+    show_snippet: bool,
+    show_score: bool,
+    around: usize,
+    context_chars: usize,
+    preserve_whitespace: bool,
+) {
     for hit in results {
         let title = hit
             .title
@@ -340,10 +1254,16 @@ fn emit_messages_text(results: &[MessageHit], show_snippet: bool, around: usize)
             .timestamp
             .clone()
             .unwrap_or_else(|| "(unknown)".to_string());
-        println!("{} | {}", when, title);
+        println!(
+            "{} | {}{}",
+            when,
+            title,
+            score_suffix(hit.score, show_score)
+        );
         println!("{}#{}", hit.path, hit.turn_index);
         if show_snippet {
-            let snippet = trim_output(&hit.text);
+            let text = hit.excerpt.as_deref().unwrap_or(&hit.text);
+            let snippet = text_output(text, output_len(), preserve_whitespace);
             if !snippet.is_empty() {
                 println!("{}", snippet);
             }
@@ -351,48 +1271,225 @@ fn emit_messages_text(results: &[MessageHit], show_snippet: bool, around: usize)
         if around > 0
             && let Some(context) = hit.context.as_deref()
         {
-            emit_context_lines(context);
+            emit_context_lines(context, context_chars, preserve_whitespace, "  ");
+        }
+        println!();
+    }
+}
+
+/// [`emit_messages_text`] with hits gathered under a header for their
+/// session. Sessions appear in the order of their first hit, i.e. by best
+/// score under the default sort.
+fn emit_messages_grouped(
+    results: &[MessageHit],
+    show_snippet: bool,
+    show_score: bool,
+    around: usize,
+    context_chars: usize,
+    preserve_whitespace: bool,
+) {
+    for (path, hits) in group_by_session(results) {
+        let title = hits[0].title.as_deref().unwrap_or("(untitled)");
+        let count = match hits.len() {
+            1 => "1 hit".to_string(),
+            n => format!("{n} hits"),
+        };
+        println!("{} ({})", title, count);
+        println!("{}", path);
+        for hit in hits {
+            let when = hit.timestamp.as_deref().unwrap_or("(unknown)");
+            let role = hit.role.as_deref().unwrap_or("unknown");
+            let score = score_suffix(hit.score, show_score);
+            println!("  #{} {} {}{}", hit.turn_index, role, when, score);
+            if show_snippet {
+                let text = hit.excerpt.as_deref().unwrap_or(&hit.text);
+                let snippet = text_output(text, output_len(), preserve_whitespace);
+                if !snippet.is_empty() {
+                    println!("    {}", snippet);
+                }
+            }
+            if around > 0
+                && let Some(context) = hit.context.as_deref()
+            {
+                emit_context_lines(context, context_chars, preserve_whitespace, "    ");
+            }
         }
         println!();
     }
 }
 
+/// `results` split per session path, sessions and hits in result order.
+fn group_by_session(results: &[MessageHit]) -> Vec<(&str, Vec<&MessageHit>)> {
+    let mut groups: Vec<(&str, Vec<&MessageHit>)> = Vec::new();
+    let mut positions: HashMap<&str, usize> = HashMap::new();
+    for hit in results {
+        let position = *positions.entry(hit.path.as_str()).or_insert_with(|| {
+            groups.push((hit.path.as_str(), Vec::new()));
+            groups.len() - 1
+        });
+        groups[position].1.push(hit);
+    }
+    groups
+}
+
+fn emit_sessions_markdown(results: &[SessionHit], fields: &HashSet<String>) {
+    for hit in results {
+        let mut header = Vec::new();
+        if fields.contains("title") {
+            header.push(markdown_title(hit.title.as_deref()));
+        }
+        if fields.contains("last_message_at")
+            && let Some(when) = hit.last_message_at.as_deref()
+        {
+            header.push(format!("*{}*", when));
+        }
+        if fields.contains("path") {
+            header.push(markdown_link(&hit.path, &hit.path));
+        }
+
+        let quote = hit
+            .snippet
+            .as_deref()
+            .filter(|_| fields.contains("snippet"))
+            .map(trim_output);
+        emit_markdown_item(&header, quote.as_deref(), &[]);
+    }
+}
+
+fn emit_messages_markdown(
+    results: &[MessageHit],
+    fields: &HashSet<String>,
+    include_context: bool,
+    context_chars: usize,
+) {
+    for hit in results {
+        let mut header = Vec::new();
+        if fields.contains("title") {
+            header.push(markdown_title(hit.title.as_deref()));
+        }
+        if fields.contains("timestamp")
+            && let Some(when) = hit.timestamp.as_deref()
+        {
+            header.push(format!("*{}*", when));
+        }
+        let role = hit.role.as_deref().filter(|_| fields.contains("role"));
+        match (fields.contains("turn_index"), role) {
+            (true, Some(role)) => header.push(format!("turn {} ({})", hit.turn_index, role)),
+            (true, None) => header.push(format!("turn {}", hit.turn_index)),
+            (false, Some(role)) => header.push(role.to_string()),
+            (false, None) => {}
+        }
+        if fields.contains("path") {
+            let label = format!("{}#{}", hit.path, hit.turn_index);
+            header.push(markdown_link(&label, &hit.path));
+        }
+
+        let quote = if fields.contains("excerpt")
+            && let Some(excerpt) = hit.excerpt.as_deref()
+        {
+            Some(trim_output(excerpt))
+        } else {
+            fields.contains("text").then(|| trim_output(&hit.text))
+        };
+        let context: Vec<String> = match hit.context.as_deref() {
+            Some(context) if include_context => context
+                .iter()
+                .map(|message| {
+                    format!(
+                        "turn {} ({}): {}",
+                        message.turn_index,
+                        message.role.as_deref().unwrap_or("unknown"),
+                        escape_markdown(&trim_to(&message.text, context_chars))
+                    )
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        emit_markdown_item(&header, quote.as_deref(), &context);
+    }
+}
+
+/// Print one markdown list item: a header line, an optional blockquoted
+/// snippet, and optional nested context items.
+fn emit_markdown_item(header: &[String], quote: Option<&str>, context: &[String]) {
+    if header.is_empty() {
+        println!("- (no fields selected)");
+    } else {
+        println!("- {}", header.join(" — "));
+    }
+    if let Some(quote) = quote.filter(|quote| !quote.is_empty()) {
+        println!();
+        println!("  > {}", escape_markdown(quote));
+    }
+    if !context.is_empty() {
+        println!();
+        for line in context {
+            println!("  - {}", line);
+        }
+    }
+    println!();
+}
+
+fn markdown_title(title: Option<&str>) -> String {
+    format!("**{}**", escape_markdown(title.unwrap_or("(untitled)")))
+}
+
+/// Link with angle-bracket destination so paths with spaces stay intact.
+fn markdown_link(label: &str, path: &str) -> String {
+    format!("[{}](<{}>)", escape_markdown(label), path)
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if matches!(ch, '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#') {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
 fn emit_show_entry(
     out: &mut dyn Write,
     entry: &SessionEntry,
-    tool_filter: Option<&str>,
+    tool_filter: &[&str],
     extract: bool,
     json: bool,
+    output: OutputOpts,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut tools = extract_tool_calls(&entry.value);
-    if let Some(filter) = tool_filter {
-        tools.retain(|tool| tool.name.eq_ignore_ascii_case(filter));
+    if !tool_filter.is_empty() {
+        tools.retain(|tool| {
+            tool_filter
+                .iter()
+                .any(|filter| tool.name.eq_ignore_ascii_case(filter))
+        });
     }
 
     if extract {
-        let mut extracted = false;
-        for tool in tools {
-            if !tool.name.eq_ignore_ascii_case("read") {
-                continue;
-            }
-            if let Some(read_args) = parse_read_args(&tool.arguments) {
-                emit_read_extract(out, &read_args)?;
-                extracted = true;
-            }
-        }
-
-        if !extracted {
+        let extracts: Vec<ReadExtract> = tools
+            .iter()
+            .filter(|tool| tool.name.eq_ignore_ascii_case("read"))
+            .filter_map(|tool| parse_read_args(&tool.arguments))
+            .map(|read_args| load_read_extract(&read_args))
+            .collect();
+
+        if json {
+            let values: Vec<Value> = extracts.iter().map(read_extract_to_json).collect();
+            let _ = writeln!(out, "{}", output.to_json(&values)?);
+        } else if extracts.is_empty() {
             let _ = writeln!(out, "no readable tool calls found");
+        } else {
+            for extract in &extracts {
+                emit_read_extract(out, extract);
+            }
         }
         return Ok(());
     }
 
     if json {
-        let _ = writeln!(
-            out,
-            "{}",
-            serde_json::to_string_pretty(&entry_to_json(entry, &tools))?
-        );
+        let _ = writeln!(out, "{}", output.to_json(&entry_to_json(entry, &tools))?);
         return Ok(());
     }
 
@@ -449,6 +1546,9 @@ fn tool_match_to_json(item: ToolCallMatch) -> Value {
         map.insert("turn".to_string(), Value::from(turn as i64));
     }
     map.insert("tool".to_string(), tool_to_json(&item.tool));
+    if let Some(output) = item.output {
+        map.insert("output".to_string(), Value::String(output));
+    }
     Value::Object(map)
 }
 
@@ -475,43 +1575,79 @@ fn format_tool_args(arguments: &Value) -> String {
     }
 }
 
-fn emit_read_extract(
-    out: &mut dyn Write,
-    read_args: &ReadArgs,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let resolved = mmem::util::expand_home(&read_args.path);
-    let content = match std::fs::read_to_string(&resolved) {
-        Ok(content) => content,
-        Err(err) => {
-            let _ = writeln!(
-                out,
-                ">>> {}:{} (limit {})",
-                resolved.display(),
-                read_args.offset,
-                read_args.limit
-            );
-            let _ = writeln!(out, "read failed: {}", err);
-            let _ = writeln!(out);
-            return Ok(());
-        }
-    };
-    let lines: Vec<&str> = content.lines().collect();
-    let start = read_args.offset.saturating_sub(1);
-    let end = std::cmp::min(lines.len(), start.saturating_add(read_args.limit));
+/// File slice named by a read tool call, as loaded for `show --extract`.
+struct ReadExtract {
+    path: std::path::PathBuf,
+    offset: usize,
+    limit: usize,
+    /// Numbered lines, or the error that prevented reading the file.
+    lines: Result<Vec<(usize, String)>, String>,
+}
+
+fn load_read_extract(read_args: &ReadArgs) -> ReadExtract {
+    let path = mmem::util::expand_home(&read_args.path);
+    let lines = std::fs::read_to_string(&path)
+        .map(|content| {
+            let lines: Vec<&str> = content.lines().collect();
+            let start = std::cmp::min(lines.len(), read_args.offset.saturating_sub(1));
+            let end = std::cmp::min(lines.len(), start.saturating_add(read_args.limit));
+            lines[start..end]
+                .iter()
+                .enumerate()
+                .map(|(idx, line)| (read_args.offset + idx, line.to_string()))
+                .collect()
+        })
+        .map_err(|err| err.to_string());
+    ReadExtract {
+        path,
+        offset: read_args.offset,
+        limit: read_args.limit,
+        lines,
+    }
+}
 
+fn emit_read_extract(out: &mut dyn Write, extract: &ReadExtract) {
     let _ = writeln!(
         out,
         ">>> {}:{} (limit {})",
-        resolved.display(),
-        read_args.offset,
-        read_args.limit
+        extract.path.display(),
+        extract.offset,
+        extract.limit
     );
-    for (idx, line) in lines[start..end].iter().enumerate() {
-        let line_no = read_args.offset + idx;
-        let _ = writeln!(out, "{:>4} {}", line_no, line);
+    match &extract.lines {
+        Ok(lines) => {
+            for (line_no, line) in lines {
+                let _ = writeln!(out, "{:>4} {}", line_no, line);
+            }
+        }
+        Err(err) => {
+            let _ = writeln!(out, "read failed: {}", err);
+        }
     }
     let _ = writeln!(out);
-    Ok(())
+}
+
+fn read_extract_to_json(extract: &ReadExtract) -> Value {
+    let mut map = Map::new();
+    map.insert(
+        "path".to_string(),
+        Value::String(extract.path.display().to_string()),
+    );
+    map.insert("offset".to_string(), Value::from(extract.offset));
+    map.insert("limit".to_string(), Value::from(extract.limit));
+    let lines = match &extract.lines {
+        Ok(lines) => lines.as_slice(),
+        Err(err) => {
+            map.insert("error".to_string(), Value::String(err.clone()));
+            &[]
+        }
+    };
+    let values: Vec<Value> = lines
+        .iter()
+        .map(|(number, text)| serde_json::json!({ "number": number, "text": text }))
+        .collect();
+    map.insert("lines".to_string(), Value::Array(values));
+    Value::Object(map)
 }
 
 #[derive(Debug)]
@@ -542,14 +1678,29 @@ fn normalize_arguments(arguments: &Value) -> Option<Value> {
     serde_json::from_str(raw).ok()
 }
 
-fn emit_context_lines(context: &[MessageContext]) {
+fn emit_context_lines(
+    context: &[MessageContext],
+    max_len: usize,
+    preserve_whitespace: bool,
+    indent: &str,
+) {
     for message in context {
         let role = message.role.as_deref().unwrap_or("unknown");
-        let text = trim_output(&message.text);
+        let text = text_output(&message.text, max_len, preserve_whitespace);
         if text.is_empty() {
             continue;
         }
-        println!("  {}:{} {}", message.turn_index, role, text);
+        match message.path.as_deref() {
+            // Context pulled in from another file of a continuation chain.
+            Some(path) => {
+                let name = Path::new(path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy())
+                    .unwrap_or_default();
+                println!("{indent}{}#{}:{} {}", name, message.turn_index, role, text);
+            }
+            None => println!("{indent}{}:{} {}", message.turn_index, role, text),
+        }
     }
 }
 
@@ -557,10 +1708,12 @@ fn emit_sessions_json(
     results: &[SessionHit],
     fields: &HashSet<String>,
     jsonl: bool,
+    null_fields: bool,
+    output: OutputOpts,
 ) -> Result<(), serde_json::Error> {
     if jsonl {
         for hit in results {
-            let value = session_to_json(hit, fields);
+            let value = session_to_json(hit, fields, null_fields);
             println!("{}", serde_json::to_string(&value)?);
         }
         return Ok(());
@@ -568,9 +1721,9 @@ fn emit_sessions_json(
 
     let values: Vec<Value> = results
         .iter()
-        .map(|hit| session_to_json(hit, fields))
+        .map(|hit| session_to_json(hit, fields, null_fields))
         .collect();
-    println!("{}", serde_json::to_string_pretty(&values)?);
+    println!("{}", output.to_json(&values)?);
     Ok(())
 }
 
@@ -578,11 +1731,14 @@ fn emit_messages_json(
     results: &[MessageHit],
     fields: &HashSet<String>,
     include_context: bool,
+    context_chars: usize,
     jsonl: bool,
+    null_fields: bool,
+    output: OutputOpts,
 ) -> Result<(), serde_json::Error> {
     if jsonl {
         for hit in results {
-            let value = message_to_json(hit, fields, include_context);
+            let value = message_to_json(hit, fields, include_context, context_chars, null_fields);
             println!("{}", serde_json::to_string(&value)?);
         }
         return Ok(());
@@ -590,15 +1746,75 @@ fn emit_messages_json(
 
     let values: Vec<Value> = results
         .iter()
-        .map(|hit| message_to_json(hit, fields, include_context))
+        .map(|hit| message_to_json(hit, fields, include_context, context_chars, null_fields))
         .collect();
-    println!("{}", serde_json::to_string_pretty(&values)?);
+    println!("{}", output.to_json(&values)?);
     Ok(())
 }
 
-fn session_to_json(hit: &SessionHit, fields: &HashSet<String>) -> Value {
+/// Every field a session JSON object can carry, in README order.
+const SESSION_JSON_FIELDS: &[&str] = &[
+    "path",
+    "session_id",
+    "title",
+    "agent",
+    "workspace",
+    "repo_root",
+    "repo_name",
+    "branch",
+    "last_message_at",
+    "snippet",
+    "content_preview",
+    "score",
+    "exists",
+];
+
+/// Every field a message JSON object can carry.
+const MESSAGE_JSON_FIELDS: &[&str] = &[
+    "path",
+    "session_id",
+    "title",
+    "agent",
+    "workspace",
+    "repo_root",
+    "repo_name",
+    "branch",
+    "turn_index",
+    "line",
+    "role",
+    "timestamp",
+    "text",
+    "score",
+    "matched_terms",
+    "excerpt",
+    "context",
+    "exists",
+];
+
+/// For `--null-fields`: give every requested field a key, `null` when the
+/// hit has no value, so all objects share one shape.
+fn fill_null_fields(map: &mut Map<String, Value>, fields: &HashSet<String>, known: &[&str]) {
+    for field in known {
+        if fields.contains(*field) {
+            map.entry(field.to_string()).or_insert(Value::Null);
+        }
+    }
+}
+
+fn session_to_json(hit: &SessionHit, fields: &HashSet<String>, null_fields: bool) -> Value {
     let mut map = Map::new();
     insert_field(&mut map, "path", &hit.path, fields);
+    if fields.contains("session_id")
+        && let Some(session_id) = session_id_from_path(&hit.path)
+    {
+        map.insert("session_id".to_string(), Value::String(session_id));
+    }
+    if fields.contains("exists") {
+        map.insert(
+            "exists".to_string(),
+            Value::Bool(Path::new(&hit.path).exists()),
+        );
+    }
     insert_opt_field(&mut map, "title", hit.title.as_deref(), fields);
     insert_opt_field(&mut map, "agent", hit.agent.as_deref(), fields);
     insert_opt_field(&mut map, "workspace", hit.workspace.as_deref(), fields);
@@ -616,15 +1832,45 @@ fn session_to_json(hit: &SessionHit, fields: &HashSet<String>) -> Value {
     {
         map.insert("snippet".to_string(), Value::String(trim_output(snippet)));
     }
+    if fields.contains("content_preview") {
+        // Fall back to the snippet for sessions indexed without --preview-chars.
+        let preview = hit.preview.as_deref().or(hit.snippet.as_deref());
+        if let Some(preview) = preview {
+            map.insert(
+                "content_preview".to_string(),
+                Value::String(preview.to_string()),
+            );
+        }
+    }
     if fields.contains("score") {
         map.insert("score".to_string(), Value::from(hit.score));
     }
+    if null_fields {
+        fill_null_fields(&mut map, fields, SESSION_JSON_FIELDS);
+    }
     Value::Object(map)
 }
 
-fn message_to_json(hit: &MessageHit, fields: &HashSet<String>, include_context: bool) -> Value {
+fn message_to_json(
+    hit: &MessageHit,
+    fields: &HashSet<String>,
+    include_context: bool,
+    context_chars: usize,
+    null_fields: bool,
+) -> Value {
     let mut map = Map::new();
     insert_field(&mut map, "path", &hit.path, fields);
+    if fields.contains("session_id")
+        && let Some(session_id) = session_id_from_path(&hit.path)
+    {
+        map.insert("session_id".to_string(), Value::String(session_id));
+    }
+    if fields.contains("exists") {
+        map.insert(
+            "exists".to_string(),
+            Value::Bool(Path::new(&hit.path).exists()),
+        );
+    }
     insert_opt_field(&mut map, "title", hit.title.as_deref(), fields);
     insert_opt_field(&mut map, "agent", hit.agent.as_deref(), fields);
     insert_opt_field(&mut map, "workspace", hit.workspace.as_deref(), fields);
@@ -634,6 +1880,11 @@ fn message_to_json(hit: &MessageHit, fields: &HashSet<String>, include_context:
     if fields.contains("turn_index") {
         map.insert("turn_index".to_string(), Value::from(hit.turn_index));
     }
+    if fields.contains("line")
+        && let Some(line) = hit.line
+    {
+        map.insert("line".to_string(), Value::from(line));
+    }
     if fields.contains("role") {
         insert_opt_field(&mut map, "role", hit.role.as_deref(), fields);
     }
@@ -644,19 +1895,35 @@ fn message_to_json(hit: &MessageHit, fields: &HashSet<String>, include_context:
     if fields.contains("score") {
         map.insert("score".to_string(), Value::from(hit.score));
     }
+    if fields.contains("matched_terms")
+        && let Some(terms) = hit.matched_terms.as_ref()
+    {
+        map.insert("matched_terms".to_string(), Value::from(terms.clone()));
+    }
+    insert_opt_field(&mut map, "excerpt", hit.excerpt.as_deref(), fields);
     if include_context
         && fields.contains("context")
         && let Some(context) = hit.context.as_deref()
     {
-        let values: Vec<Value> = context.iter().map(message_context_to_json).collect();
+        let values: Vec<Value> = context
+            .iter()
+            .map(|message| message_context_to_json(message, context_chars))
+            .collect();
         map.insert("context".to_string(), Value::Array(values));
     }
 
+    if null_fields {
+        fill_null_fields(&mut map, fields, MESSAGE_JSON_FIELDS);
+    }
+
     Value::Object(map)
 }
 
-fn message_context_to_json(context: &MessageContext) -> Value {
+fn message_context_to_json(context: &MessageContext, max_len: usize) -> Value {
     let mut map = Map::new();
+    if let Some(path) = context.path.as_deref() {
+        map.insert("path".to_string(), Value::String(path.to_string()));
+    }
     map.insert("turn_index".to_string(), Value::from(context.turn_index));
     if let Some(role) = context.role.as_deref() {
         map.insert("role".to_string(), Value::String(role.to_string()));
@@ -669,7 +1936,7 @@ fn message_context_to_json(context: &MessageContext) -> Value {
     }
     map.insert(
         "text".to_string(),
-        Value::String(trim_output(&context.text)),
+        Value::String(trim_to(&context.text, max_len)),
     );
     Value::Object(map)
 }
@@ -694,11 +1961,29 @@ fn insert_opt_field(
 }
 
 fn trim_output(text: &str) -> String {
-    let compacted = text.split_whitespace().collect::<Vec<_>>().join(" ");
-    if compacted.chars().count() <= MAX_OUTPUT_LEN {
-        return compacted;
+    trim_to(text, output_len())
+}
+
+fn trim_to(text: &str, max_len: usize) -> String {
+    cap_len(&collapse_whitespace(text), max_len)
+}
+
+/// Text-mode rendering: [`trim_to`], or with `preserve_whitespace` only the
+/// length cap, so code keeps its indentation and line breaks.
+fn text_output(text: &str, max_len: usize, preserve_whitespace: bool) -> String {
+    if preserve_whitespace {
+        cap_len(text.trim_matches('\n').trim_end(), max_len)
+    } else {
+        trim_to(text, max_len)
     }
-    compacted.chars().take(MAX_OUTPUT_LEN).collect()
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn cap_len(text: &str, max_len: usize) -> String {
+    text.chars().take(max_len).collect()
 }
 
 #[cfg(test)]
@@ -710,33 +1995,234 @@ mod tests {
 
         #[test]
         fn defaults_to_user_without_include_assistant() {
-            let result = normalize_role_filter(None, false);
+            let result = normalize_role_filter(None, false, Some("user"));
             assert_eq!(result, Some("user".to_string()));
         }
 
         #[test]
         fn returns_none_with_include_assistant_and_no_role() {
-            let result = normalize_role_filter(None, true);
+            let result = normalize_role_filter(None, true, Some("user"));
             assert_eq!(result, None);
         }
 
         #[test]
         fn respects_explicit_role() {
-            let result = normalize_role_filter(Some("assistant"), false);
+            let result = normalize_role_filter(Some("assistant"), false, Some("user"));
             assert_eq!(result, Some("assistant".to_string()));
         }
 
         #[test]
         fn normalizes_role_to_lowercase() {
-            let result = normalize_role_filter(Some("USER"), false);
+            let result = normalize_role_filter(Some("USER"), false, Some("user"));
             assert_eq!(result, Some("user".to_string()));
         }
 
         #[test]
         fn trims_whitespace() {
-            let result = normalize_role_filter(Some("  user  "), false);
+            let result = normalize_role_filter(Some("  user  "), false, Some("user"));
             assert_eq!(result, Some("user".to_string()));
         }
+
+        #[test]
+        fn reaches_developer_and_system_roles() {
+            assert_eq!(
+                normalize_role_filter(Some("Developer"), false, Some("user")),
+                Some("developer".to_string())
+            );
+            assert_eq!(
+                normalize_role_filter(Some("system"), true, Some("user")),
+                Some("system".to_string())
+            );
+        }
+
+        #[test]
+        fn applies_configured_default_roles() {
+            assert_eq!(default_roles(None), Some("user".to_string()));
+            assert_eq!(default_roles(Some("ALL")), None);
+            assert_eq!(
+                default_roles(Some("User, assistant")),
+                Some("user,assistant".to_string())
+            );
+            let default = default_roles(Some("user,assistant"));
+            assert_eq!(
+                normalize_role_filter(None, false, default.as_deref()),
+                Some("user,assistant".to_string())
+            );
+            assert_eq!(normalize_role_filter(None, false, None), None);
+        }
+
+        #[test]
+        fn fts_queries_skip_the_default_role() {
+            assert_eq!(message_default_roles(false, None), Some("user".to_string()));
+            assert_eq!(message_default_roles(true, None), None);
+            assert_eq!(message_default_roles(true, Some("user,assistant")), None);
+            let default = message_default_roles(true, None);
+            assert_eq!(normalize_role_filter(None, false, default.as_deref()), None);
+            assert_eq!(
+                normalize_role_filter(Some("user"), false, default.as_deref()),
+                Some("user".to_string())
+            );
+        }
+    }
+
+    mod table_columns_tests {
+        use super::*;
+
+        #[test]
+        fn keeps_requested_order_and_appends_flag_fields() {
+            let fields = vec![
+                "Title".to_string(),
+                "path".to_string(),
+                "context".to_string(),
+            ];
+            let mut set = build_field_set(Some(&fields), FindScope::Message);
+            set.insert("matched_terms".to_string());
+            let columns = table_columns(Some(&fields), &set, FindScope::Message);
+            assert_eq!(columns, vec!["title", "path", "matched_terms"]);
+        }
+
+        #[test]
+        fn formats_cells() {
+            assert_eq!(table_cell(None), "");
+            assert_eq!(table_cell(Some(&Value::from(-2.3125))), "-2.31");
+            assert_eq!(table_cell(Some(&Value::from(7))), "7");
+            assert_eq!(table_cell(Some(&serde_json::json!(["a", "b"]))), "a, b");
+            assert_eq!(table_cell(Some(&Value::from("two\nlines"))), "two lines");
+        }
+    }
+
+    mod zero_result_hint_tests {
+        use super::*;
+
+        fn probes(terms: &[(&str, bool)]) -> Vec<TermProbe> {
+            terms
+                .iter()
+                .map(|(term, matches)| TermProbe {
+                    term: term.to_string(),
+                    matches: *matches,
+                })
+                .collect()
+        }
+
+        #[test]
+        fn names_matching_and_missing_terms() {
+            let hint = zero_result_hint(
+                "foo bar baz",
+                &probes(&[("foo", true), ("bar", false), ("baz", true)]),
+            );
+            assert_eq!(
+                hint.as_deref(),
+                Some("no results for 'foo bar baz'; 'foo' and 'baz' match but 'bar' does not")
+            );
+        }
+
+        #[test]
+        fn covers_all_or_nothing_and_single_terms() {
+            let none = zero_result_hint("a b", &probes(&[("a", false), ("b", false)]));
+            assert_eq!(
+                none.as_deref(),
+                Some("no results for 'a b'; no term matches on its own")
+            );
+            let all = zero_result_hint("a b", &probes(&[("a", true), ("b", true)]));
+            let all = all.expect("hint");
+            assert!(all.ends_with("every term matches on its own, but not together"));
+            assert_eq!(zero_result_hint("a", &probes(&[("a", false)])), None);
+        }
+    }
+
+    mod score_suffix_tests {
+        use super::*;
+
+        #[test]
+        fn hidden_by_default() {
+            assert_eq!(score_suffix(-2.3125, false), "");
+        }
+
+        #[test]
+        fn rounds_to_two_decimals() {
+            assert_eq!(score_suffix(-2.3125, true), " [score -2.31]");
+        }
+    }
+
+    mod null_fields_tests {
+        use super::*;
+
+        fn hit() -> SessionHit {
+            SessionHit {
+                path: "/s/1_a.jsonl".to_string(),
+                title: None,
+                agent: Some("gpt-4".to_string()),
+                workspace: None,
+                repo_root: None,
+                repo_name: None,
+                branch: None,
+                last_message_at: None,
+                snippet: None,
+                preview: None,
+                score: -1.0,
+            }
+        }
+
+        #[test]
+        fn sparse_by_default_and_null_filled_on_request() {
+            let fields: HashSet<String> = ["agent", "branch", "bogus"]
+                .iter()
+                .map(|field| field.to_string())
+                .collect();
+
+            let sparse = session_to_json(&hit(), &fields, false);
+            assert_eq!(sparse, serde_json::json!({ "agent": "gpt-4" }));
+
+            let full = session_to_json(&hit(), &fields, true);
+            assert_eq!(
+                full,
+                serde_json::json!({ "agent": "gpt-4", "branch": null })
+            );
+        }
+    }
+
+    mod group_by_session_tests {
+        use super::*;
+
+        fn hit(path: &str, turn_index: i64) -> MessageHit {
+            MessageHit {
+                path: path.to_string(),
+                title: None,
+                agent: None,
+                workspace: None,
+                repo_root: None,
+                repo_name: None,
+                branch: None,
+                turn_index,
+                role: None,
+                timestamp: None,
+                text: String::new(),
+                score: -1.0,
+                context: None,
+                matched_terms: None,
+                excerpt: None,
+                line: None,
+            }
+        }
+
+        #[test]
+        fn keeps_sessions_in_order_of_first_hit() {
+            let results = [
+                hit("/b", 3),
+                hit("/a", 1),
+                hit("/b", 7),
+                hit("/a", 2),
+                hit("/c", 0),
+            ];
+            let groups: Vec<(&str, Vec<i64>)> = group_by_session(&results)
+                .into_iter()
+                .map(|(path, hits)| (path, hits.iter().map(|hit| hit.turn_index).collect()))
+                .collect();
+            assert_eq!(
+                groups,
+                [("/b", vec![3, 7]), ("/a", vec![1, 2]), ("/c", vec![0])]
+            );
+        }
     }
 
     mod trim_output_tests {
@@ -755,10 +2241,24 @@ mod tests {
             assert_eq!(result.len(), MAX_OUTPUT_LEN);
         }
 
+        #[test]
+        fn trims_to_custom_length() {
+            let result = trim_to("hello world", 5);
+            assert_eq!(result, "hello");
+        }
+
         #[test]
         fn collapses_whitespace() {
             let result = trim_output("hello   world\n\ntest");
             assert_eq!(result, "hello world test");
         }
+
+        #[test]
+        fn preserves_whitespace_when_asked() {
+            let code = "fn main() {\n    run();\n}\n";
+            assert_eq!(text_output(code, 100, true), "fn main() {\n    run();\n}");
+            assert_eq!(text_output(code, 13, true), "fn main() {\n ");
+            assert_eq!(text_output(code, 100, false), "fn main() { run(); }");
+        }
     }
 }
@@ -16,13 +16,20 @@
 //! - [`MessageHit`]: Search result for message-scope queries
 //! - [`MessageContext`]: Surrounding messages for context display
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ParsedMessage {
     pub role: Option<String>,
     pub text: String,
     pub timestamp: Option<String>,
+    /// Names of tool calls made in this message, in order.
+    pub tools: Vec<String>,
+    /// Shell commands run by this message's tool calls, in order.
+    pub commands: Vec<String>,
+    /// 1-based line of the source file the message starts on. Set for JSONL
+    /// and Markdown; `None` for single JSON documents.
+    pub line: Option<usize>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -33,9 +40,18 @@ pub struct ParsedSession {
     pub workspace: Option<String>,
     pub title: Option<String>,
     pub message_count: usize,
+    /// Messages with role `user`.
+    pub user_message_count: usize,
+    /// Messages with role `assistant`.
+    pub assistant_message_count: usize,
     pub snippet: String,
     pub content: String,
     pub messages: Vec<ParsedMessage>,
+    /// Malformed JSONL lines skipped in lenient mode.
+    pub skipped_lines: usize,
+    /// The session this file continues, as written in its metadata
+    /// (`continues_from` or `parent_id`): a file name, path, or session ID.
+    pub continues_from: Option<String>,
 }
 
 impl ParsedSession {
@@ -47,9 +63,13 @@ impl ParsedSession {
             workspace: None,
             title: None,
             message_count: 0,
+            user_message_count: 0,
+            assistant_message_count: 0,
             snippet: String::new(),
             content: String::new(),
             messages: Vec::new(),
+            skipped_lines: 0,
+            continues_from: None,
         }
     }
 
@@ -71,11 +91,17 @@ impl ParsedSession {
             workspace: self.workspace,
             title: self.title,
             message_count: self.message_count as i64,
+            user_message_count: self.user_message_count as i64,
+            assistant_message_count: self.assistant_message_count as i64,
             snippet: self.snippet,
             content: self.content,
             repo_root: None,
             repo_name: None,
             branch: None,
+            system_prompt: None,
+            preview: None,
+            parent_path: None,
+            commands: None,
         };
 
         (record, self.messages)
@@ -94,11 +120,24 @@ pub struct SessionRecord {
     pub workspace: Option<String>,
     pub title: Option<String>,
     pub message_count: i64,
+    pub user_message_count: i64,
+    pub assistant_message_count: i64,
     pub snippet: String,
     pub content: String,
     pub repo_root: Option<String>,
     pub repo_name: Option<String>,
     pub branch: Option<String>,
+    /// Leading system/developer prompt, kept out of message search unless
+    /// indexing with `--index-system`.
+    pub system_prompt: Option<String>,
+    /// Longer lead-in of the session content, stored only when indexing with
+    /// `--preview-chars`.
+    pub preview: Option<String>,
+    /// Indexed path of the session this file continues, resolved from
+    /// [`ParsedSession::continues_from`].
+    pub parent_path: Option<String>,
+    /// Shell commands run during the session, one per line.
+    pub commands: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -107,8 +146,12 @@ pub struct MessageRecord {
     pub role: Option<String>,
     pub timestamp: Option<String>,
     pub text: String,
+    pub tools: Vec<String>,
+    /// See [`ParsedMessage::line`].
+    pub line: Option<i64>,
 }
 
+/// Session-scope search result; column order mirrors `FIND_SESSIONS_SQL`.
 #[derive(Debug, Clone, Serialize)]
 pub struct SessionHit {
     pub path: String,
@@ -120,18 +163,25 @@ pub struct SessionHit {
     pub branch: Option<String>,
     pub last_message_at: Option<String>,
     pub snippet: Option<String>,
+    pub preview: Option<String>,
     pub score: f64,
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// A neighbouring message loaded for `--around` context.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MessageContext {
+    /// Set only when the message comes from another file of the same
+    /// continuation chain (see `FindFilters::merge_continuations`).
+    #[serde(default)]
+    pub path: Option<String>,
     pub turn_index: i64,
     pub role: Option<String>,
     pub timestamp: Option<String>,
     pub text: String,
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// Message-scope search result, optionally carrying surrounding context.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MessageHit {
     pub path: String,
     pub title: Option<String>,
@@ -146,4 +196,19 @@ pub struct MessageHit {
     pub text: String,
     pub score: f64,
     pub context: Option<Vec<MessageContext>>,
+    /// Distinct query terms found in `text`, lowercased, in order of first
+    /// appearance. Only filled in with [`FindFilters::explain_matches`].
+    ///
+    /// [`FindFilters::explain_matches`]: crate::query::FindFilters::explain_matches
+    #[serde(default)]
+    pub matched_terms: Option<Vec<String>>,
+    /// FTS5 `snippet()` window of `text` around the match, terms wrapped in
+    /// the requested markers. Only filled in with [`FindFilters::excerpt`].
+    ///
+    /// [`FindFilters::excerpt`]: crate::query::FindFilters::excerpt
+    #[serde(default)]
+    pub excerpt: Option<String>,
+    /// 1-based source line of the message, when the index recorded one.
+    #[serde(default)]
+    pub line: Option<i64>,
 }
@@ -5,10 +5,18 @@
 //!
 //! # Key Functions
 //!
+//! - [`parse_file`]: Read a session file and parse it by extension
 //! - [`parse_jsonl`]: Parse newline-delimited JSON session files
+//! - [`parse_jsonl_reader_with`]: Same, streaming lines from a reader
 //! - [`parse_json`]: Parse single JSON session files
 //! - [`parse_markdown`]: Parse markdown conversation logs
 //! - [`extract_message`]: Extract a single message from a JSON value
+//! - [`tool_output`]: Read a standalone tool result entry
+//! - [`parse_with_spec`]: Parse any JSON transcript described by a [`ParseSpec`]
+//!
+//! The `_with` variants take a [`ParseOptions`] for schema overrides: extra
+//! timestamp/title/agent field names and role aliases. The plain variants use
+//! the defaults.
 //!
 //! # Turn Index Semantics
 //!
@@ -18,9 +26,18 @@
 //! - `mmem show --turn` command
 //!
 //! A toolCall-only message will have `text: ""` but still count as a turn.
+//!
+//! # Tool Results
+//!
+//! Tool results stored as separate `function_call_output` entries (bare or
+//! as a `response_item` payload) become messages with the `tool` role, so
+//! command output and search results are searchable. See [`tool_output`].
 
+use crate::compress::{SessionFormat, session_format};
 use crate::model::{ParsedMessage, ParsedSession};
 use serde_json::Value;
+use std::io::{BufRead, Read};
+use std::path::{Path, PathBuf};
 
 const MAX_SNIPPET_LEN: usize = 240;
 
@@ -30,7 +47,10 @@ mod json_types {
     pub const RESPONSE_ITEM: &str = "response_item";
     pub const MESSAGE: &str = "message";
     pub const INPUT_TEXT: &str = "input_text";
+    pub const OUTPUT_TEXT: &str = "output_text";
+    pub const TEXT: &str = "text";
     pub const TOOL_CALL: &str = "toolCall";
+    pub const FUNCTION_CALL_OUTPUT: &str = "function_call_output";
 }
 
 /// Check if a JSON value has a specific "type" field value.
@@ -50,18 +70,82 @@ pub enum ParseError {
         line: usize,
         source: serde_json::Error,
     },
+    #[error("spec path {path:?} does not point at an array of messages")]
+    SpecPathNotFound { path: String },
+    #[error("unsupported session format: {path}")]
+    UnsupportedFormat { path: PathBuf },
+    #[error("failed to read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to decompress session: {source}")]
+    Decompress { source: std::io::Error },
+    #[error("session is not valid UTF-8: {source}")]
+    InvalidUtf8 { source: std::string::FromUtf8Error },
+    #[error("failed to read session: {source}")]
+    Read { source: std::io::Error },
+}
+
+/// Tunable parsing behavior for nonstandard transcript schemas.
+///
+/// The zero-argument `parse_*` functions use [`ParseOptions::default`].
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Extra field names to read message timestamps from, tried in order
+    /// after the built-in `created_at`/`timestamp`/`time`/`ts`.
+    pub timestamp_fields: Vec<String>,
+    /// Metadata field names holding the session title. When one is present
+    /// it wins over the first user message.
+    pub title_fields: Vec<String>,
+    /// Extra metadata field names holding the agent, tried after `agent`.
+    pub agent_fields: Vec<String>,
+    /// Role renames applied to every message, as `(from, to)` pairs
+    /// (e.g. `("human", "user")`). Matching is case-insensitive.
+    pub role_aliases: Vec<(String, String)>,
+    /// Skip malformed JSONL lines (counted in
+    /// [`ParsedSession::skipped_lines`]) instead of failing the whole file.
+    pub lenient: bool,
+    /// Accept `//` and `/* */` comments and trailing commas in JSON and
+    /// JSONL input (the JSON5 extensions hand-edited files tend to use).
+    #[cfg(feature = "json5")]
+    pub lenient_json: bool,
+    /// Drop a code fence wrapping a message's whole text: the opening fence
+    /// line with its language hint and the closing fence. The code itself
+    /// is kept, as are fences that open or close mid-message.
+    pub strip_code_fences: bool,
+}
+
+impl ParseOptions {
+    fn resolve_role(&self, role: String) -> String {
+        self.role_aliases
+            .iter()
+            .find(|(from, _)| from.eq_ignore_ascii_case(&role))
+            .map(|(_, to)| normalize_role(to))
+            .unwrap_or(role)
+    }
+
+    fn is_role_alias(&self, role: &str) -> bool {
+        self.role_aliases
+            .iter()
+            .any(|(from, _)| from.eq_ignore_ascii_case(role.trim()))
+    }
 }
 
 #[derive(Debug, Default)]
 struct Meta {
+    title: Option<String>,
     created_at: Option<String>,
     last_message_at: Option<String>,
     agent: Option<String>,
     workspace: Option<String>,
+    continues_from: Option<String>,
 }
 
 pub fn extract_message(value: &Value) -> Option<ParsedMessage> {
-    if let Some(message) = format_session_entry(value) {
+    if let Some(mut message) = format_session_entry(value) {
+        message.tools = tool_call_names(value);
+        message.commands = tool_call_commands(value);
         return Some(message);
     }
 
@@ -70,12 +154,90 @@ pub fn extract_message(value: &Value) -> Option<ParsedMessage> {
             role: extract_role(value),
             text: String::new(),
             timestamp: extract_timestamp(value),
+            tools: tool_call_names(value),
+            commands: tool_call_commands(value),
+            line: None,
         });
     }
 
     None
 }
 
+/// [`extract_message`] plus the schema overrides in `options`.
+pub fn extract_message_with(value: &Value, options: &ParseOptions) -> Option<ParsedMessage> {
+    let mut message = extract_message(value)?;
+    if message.timestamp.is_none() {
+        message.timestamp = extract_custom_field(value, &options.timestamp_fields);
+    }
+    message.role = message.role.map(|role| options.resolve_role(role));
+    Some(message)
+}
+
+/// Look up the first present custom field on the entry or its nested
+/// `message`/`payload` object.
+fn extract_custom_field(value: &Value, fields: &[String]) -> Option<String> {
+    let scopes = [Some(value), value.get("message"), value.get("payload")];
+    fields.iter().find_map(|field| {
+        scopes
+            .iter()
+            .flatten()
+            .find_map(|scope| extract_string_field(scope, field))
+    })
+}
+
+fn tool_call_names(value: &Value) -> Vec<String> {
+    let Some(content) = extract_content_array(value) else {
+        return Vec::new();
+    };
+
+    content
+        .iter()
+        .filter(|item| type_is(item, json_types::TOOL_CALL))
+        .map(|item| {
+            item.get("name")
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown")
+                .to_string()
+        })
+        .collect()
+}
+
+/// Shell commands run by an entry's tool calls: the `command` or `cmd`
+/// argument of any tool call, whatever the tool is named. Arguments may be
+/// an object or a JSON string; an argv array is joined with spaces.
+fn tool_call_commands(value: &Value) -> Vec<String> {
+    let Some(content) = extract_content_array(value) else {
+        return Vec::new();
+    };
+
+    content
+        .iter()
+        .filter(|item| type_is(item, json_types::TOOL_CALL))
+        .filter_map(|item| {
+            let arguments = item.get("arguments")?;
+            let decoded;
+            let arguments = match arguments.as_str() {
+                Some(raw) => {
+                    decoded = serde_json::from_str::<Value>(raw).ok()?;
+                    &decoded
+                }
+                None => arguments,
+            };
+            let command = match arguments.get("command").or_else(|| arguments.get("cmd"))? {
+                Value::String(command) => command.clone(),
+                Value::Array(argv) => argv
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                _ => return None,
+            };
+            let command = command.trim();
+            (!command.is_empty()).then(|| command.to_string())
+        })
+        .collect()
+}
+
 fn has_tool_call(value: &Value) -> bool {
     let Some(content) = extract_content_array(value) else {
         return false;
@@ -92,9 +254,12 @@ fn has_tool_call(value: &Value) -> bool {
 /// - `{ "message": { "content": [...] } }` - Claude/Marvin format
 /// - `{ "type": json_types::RESPONSE_ITEM, "payload": { "type": json_types::MESSAGE, "content": [...] } }` - OpenAI format
 /// - `{ "content": [...] }` - Generic format
-pub(crate) fn extract_content_array(value: &Value) -> Option<&Vec<Value>> {
+///
+/// A `content` that is a single block object instead of an array is
+/// returned as a one-element slice.
+pub(crate) fn extract_content_array(value: &Value) -> Option<&[Value]> {
     if let Some(message) = value.get("message")
-        && let Some(content) = message.get("content").and_then(|v| v.as_array())
+        && let Some(content) = message.get("content").and_then(content_blocks)
     {
         return Some(content);
     }
@@ -102,12 +267,20 @@ pub(crate) fn extract_content_array(value: &Value) -> Option<&Vec<Value>> {
     if type_is(value, json_types::RESPONSE_ITEM)
         && let Some(payload) = value.get("payload")
         && type_is(payload, json_types::MESSAGE)
-        && let Some(content) = payload.get("content").and_then(|v| v.as_array())
+        && let Some(content) = payload.get("content").and_then(content_blocks)
     {
         return Some(content);
     }
 
-    value.get("content").and_then(|v| v.as_array())
+    value.get("content").and_then(content_blocks)
+}
+
+fn content_blocks(content: &Value) -> Option<&[Value]> {
+    match content {
+        Value::Array(blocks) => Some(blocks),
+        Value::Object(_) => Some(std::slice::from_ref(content)),
+        _ => None,
+    }
 }
 
 fn extract_role(value: &Value) -> Option<String> {
@@ -125,36 +298,241 @@ fn extract_role(value: &Value) -> Option<String> {
         .map(normalize_role)
 }
 
+/// Parse JSON text, retrying with comments and trailing commas removed when
+/// [`ParseOptions::lenient_json`] is set. Strict input never pays for the
+/// rewrite.
+#[cfg(feature = "json5")]
+fn from_json_str(input: &str, options: &ParseOptions) -> serde_json::Result<Value> {
+    match serde_json::from_str(input) {
+        Err(_) if options.lenient_json => serde_json::from_str(&relax_json(input)),
+        result => result,
+    }
+}
+
+#[cfg(not(feature = "json5"))]
+fn from_json_str(input: &str, _options: &ParseOptions) -> serde_json::Result<Value> {
+    serde_json::from_str(input)
+}
+
+/// Rewrite JSON5-style text into strict JSON: drop `//` line comments,
+/// `/* */` block comments, and commas directly before `]` or `}`. String
+/// contents are copied verbatim.
+#[cfg(feature = "json5")]
+fn relax_json(input: &str) -> String {
+    let mut uncommented = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    let mut in_string = false;
+    while let Some(ch) = chars.next() {
+        if in_string {
+            uncommented.push(ch);
+            match ch {
+                '\\' => uncommented.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (ch, chars.peek()) {
+            ('/', Some('/')) => while chars.next_if(|next| *next != '\n').is_some() {},
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = ' ';
+                for next in chars.by_ref() {
+                    if prev == '*' && next == '/' {
+                        break;
+                    }
+                    prev = next;
+                }
+                uncommented.push(' ');
+            }
+            _ => {
+                in_string = ch == '"';
+                uncommented.push(ch);
+            }
+        }
+    }
+
+    let mut relaxed = String::with_capacity(uncommented.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for (idx, ch) in uncommented.char_indices() {
+        if in_string {
+            match ch {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if ch == '"' {
+            in_string = true;
+        } else if ch == ','
+            && matches!(
+                uncommented[idx + 1..].trim_start().chars().next(),
+                Some(']' | '}')
+            )
+        {
+            continue;
+        }
+        relaxed.push(ch);
+    }
+
+    relaxed
+}
+
+/// Strip a leading UTF-8 byte order mark, which some exporters prepend.
+pub(crate) fn strip_bom(input: &str) -> &str {
+    input.strip_prefix('\u{feff}').unwrap_or(input)
+}
+
+/// Read and parse a session file, choosing the parser by extension
+/// (`.jsonl`, `.json`, `.md`, optionally behind a supported compression
+/// suffix such as `.zst`).
+///
+/// ```
+/// use mmem::parse::parse_file;
+///
+/// let path = std::env::temp_dir().join("mmem-doctest-parse-file.md");
+/// std::fs::write(&path, "User: hello\nAssistant: hi there\n")?;
+///
+/// let session = parse_file(&path)?;
+/// assert_eq!(session.message_count, 2);
+/// assert_eq!(session.title.as_deref(), Some("hello"));
+/// # std::fs::remove_file(&path)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn parse_file(path: &Path) -> Result<ParsedSession, ParseError> {
+    parse_file_with(path, &ParseOptions::default())
+}
+
+pub fn parse_file_with(path: &Path, options: &ParseOptions) -> Result<ParsedSession, ParseError> {
+    let format = session_format(path).ok_or_else(|| ParseError::UnsupportedFormat {
+        path: path.to_path_buf(),
+    })?;
+    let file = std::fs::File::open(path).map_err(|source| ParseError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse_reader(file, &format, options)
+}
+
+/// Decompress (if needed) and parse a session of a known format from
+/// `reader`. Compressed `.jsonl` is streamed line by line from the
+/// decompressor; other formats are read whole.
+pub(crate) fn parse_reader(
+    mut reader: impl Read,
+    format: &SessionFormat,
+    options: &ParseOptions,
+) -> Result<ParsedSession, ParseError> {
+    let mut bytes = Vec::new();
+    match format.compression {
+        Some(compression) if format.ext == "jsonl" => {
+            return compression
+                .decompress_with(reader, |reader| parse_jsonl_reader_with(reader, options))
+                .map_err(|source| ParseError::Decompress { source })?;
+        }
+        Some(compression) => compression
+            .decoder(reader)
+            .and_then(|mut decoder| decoder.read_to_end(&mut bytes))
+            .map_err(|source| ParseError::Decompress { source })?,
+        None => reader
+            .read_to_end(&mut bytes)
+            .map_err(|source| ParseError::Read { source })?,
+    };
+    let contents = String::from_utf8(bytes).map_err(|source| ParseError::InvalidUtf8 { source })?;
+
+    match format.ext.as_str() {
+        "jsonl" => parse_jsonl_with(&contents, options),
+        "json" => parse_json_with(&contents, options),
+        "md" => Ok(parse_markdown_with(&contents, options)),
+        _ => Ok(ParsedSession::empty()),
+    }
+}
+
 pub fn parse_jsonl(input: &str) -> Result<ParsedSession, ParseError> {
+    parse_jsonl_with(input, &ParseOptions::default())
+}
+
+pub fn parse_jsonl_with(input: &str, options: &ParseOptions) -> Result<ParsedSession, ParseError> {
+    parse_jsonl_lines(strip_bom(input).lines().map(Ok), options)
+}
+
+/// [`parse_jsonl_with`] over a reader, one line at a time, so only the
+/// parsed messages are held in memory rather than the whole file. Compressed
+/// `.jsonl` sessions are parsed this way straight from the decompressor.
+pub fn parse_jsonl_reader_with(
+    mut reader: impl BufRead,
+    options: &ParseOptions,
+) -> Result<ParsedSession, ParseError> {
+    let mut first = true;
+    let lines = std::iter::from_fn(move || {
+        let mut line = Vec::new();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) => None,
+            Ok(_) => {
+                let line =
+                    String::from_utf8(line).map_err(|source| ParseError::InvalidUtf8 { source });
+                if std::mem::take(&mut first) {
+                    return Some(line.map(|line| strip_bom(&line).to_string()));
+                }
+                Some(line)
+            }
+            Err(source) => Some(Err(ParseError::Read { source })),
+        }
+    });
+    parse_jsonl_lines(lines, options)
+}
+
+fn parse_jsonl_lines<L: AsRef<str>>(
+    lines: impl Iterator<Item = Result<L, ParseError>>,
+    options: &ParseOptions,
+) -> Result<ParsedSession, ParseError> {
     let mut meta = Meta::default();
     let mut messages = Vec::new();
+    let mut skipped_lines = 0;
 
-    for (idx, line) in input.lines().enumerate() {
-        let line = line.trim();
+    for (idx, line) in lines.enumerate() {
+        let line = line?;
+        let line = line.as_ref().trim();
         if line.is_empty() {
             continue;
         }
 
-        let value: Value = serde_json::from_str(line).map_err(|e| ParseError::InvalidJsonl {
-            line: idx + 1,
-            source: e,
-        })?;
+        let value: Value = match from_json_str(line, options) {
+            Ok(value) => value,
+            Err(_) if options.lenient => {
+                skipped_lines += 1;
+                continue;
+            }
+            Err(source) => {
+                return Err(ParseError::InvalidJsonl {
+                    line: idx + 1,
+                    source,
+                });
+            }
+        };
 
-        update_meta_from_value(&mut meta, &value);
-        if let Some(message) = extract_message(&value) {
+        update_meta_from_value(&mut meta, &value, options);
+        if let Some(mut message) = extract_message_with(&value, options) {
+            message.line = Some(idx + 1);
             messages.push(message);
         }
     }
 
-    Ok(build_parsed_session(messages, meta))
+    let mut parsed = build_parsed_session(messages, meta, options);
+    parsed.skipped_lines = skipped_lines;
+    Ok(parsed)
 }
 
 pub fn parse_json(input: &str) -> Result<ParsedSession, ParseError> {
-    let root: Value =
-        serde_json::from_str(input).map_err(|e| ParseError::InvalidJson { source: e })?;
+    parse_json_with(input, &ParseOptions::default())
+}
+
+pub fn parse_json_with(input: &str, options: &ParseOptions) -> Result<ParsedSession, ParseError> {
+    let root: Value = from_json_str(strip_bom(input), options)
+        .map_err(|e| ParseError::InvalidJson { source: e })?;
 
     let mut meta = Meta::default();
-    update_meta_from_value(&mut meta, &root);
+    update_meta_from_value(&mut meta, &root, options);
 
     let entries: Vec<&Value> = match &root {
         Value::Array(items) => items.iter().collect(),
@@ -163,6 +541,9 @@ pub fn parse_json(input: &str) -> Result<ParsedSession, ParseError> {
                 messages.iter().collect()
             } else if let Some(Value::Array(events)) = map.get("events") {
                 events.iter().collect()
+            } else if let Some(Value::Array(contents)) = map.get("contents") {
+                // Gemini `generateContent` request/export shape.
+                contents.iter().collect()
             } else {
                 vec![&root]
             }
@@ -172,44 +553,214 @@ pub fn parse_json(input: &str) -> Result<ParsedSession, ParseError> {
 
     let mut messages = Vec::new();
     for entry in entries {
-        update_meta_from_value(&mut meta, entry);
-        if let Some(message) = extract_message(entry) {
+        update_meta_from_value(&mut meta, entry, options);
+        if let Some(message) = extract_message_with(entry, options) {
             messages.push(message);
         }
     }
 
-    Ok(build_parsed_session(messages, meta))
+    Ok(build_parsed_session(messages, meta, options))
+}
+
+/// Field mapping for a JSON transcript format mmem does not parse natively.
+///
+/// Paths use a minimal accessor syntax: dot-separated keys with optional
+/// `[N]` array indexes, optionally starting at `$` (e.g.
+/// `$.data.conversation[0].turns`). `messages_path` and `title_path` are
+/// resolved from the document root; the other fields from each message.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParseSpec {
+    pub messages_path: String,
+    pub text_field: String,
+    #[serde(default)]
+    pub role_field: Option<String>,
+    #[serde(default)]
+    pub time_field: Option<String>,
+    #[serde(default)]
+    pub title_path: Option<String>,
+}
+
+/// Parse a single JSON document using a [`ParseSpec`] mapping.
+pub fn parse_with_spec(input: &str, spec: &ParseSpec) -> Result<ParsedSession, ParseError> {
+    let root: Value = serde_json::from_str(strip_bom(input))
+        .map_err(|e| ParseError::InvalidJson { source: e })?;
+
+    let Some(entries) = select_path(&root, &spec.messages_path).and_then(|v| v.as_array()) else {
+        return Err(ParseError::SpecPathNotFound {
+            path: spec.messages_path.clone(),
+        });
+    };
+
+    let messages = entries
+        .iter()
+        .filter_map(|entry| {
+            let text = select_path(entry, &spec.text_field).and_then(coerce_content)?;
+            let role = spec
+                .role_field
+                .as_deref()
+                .and_then(|field| select_path(entry, field))
+                .and_then(|v| v.as_str())
+                .map(normalize_role);
+            let timestamp = spec
+                .time_field
+                .as_deref()
+                .and_then(|field| select_path(entry, field))
+                .and_then(scalar_to_string);
+            Some(ParsedMessage {
+                role,
+                text,
+                timestamp,
+                tools: Vec::new(),
+                commands: Vec::new(),
+                line: None,
+            })
+        })
+        .collect();
+
+    let meta = Meta {
+        title: spec
+            .title_path
+            .as_deref()
+            .and_then(|path| select_path(&root, path))
+            .and_then(scalar_to_string),
+        ..Meta::default()
+    };
+
+    Ok(build_parsed_session(
+        messages,
+        meta,
+        &ParseOptions::default(),
+    ))
+}
+
+/// Resolve a `$.a.b[0].c` style path against `value`.
+fn select_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    let path = path.trim();
+    let path = path.strip_prefix('$').unwrap_or(path);
+
+    let mut current = value;
+    for segment in path.split('.').filter(|segment| !segment.is_empty()) {
+        let (key, indexes) = match segment.find('[') {
+            Some(pos) => segment.split_at(pos),
+            None => (segment, ""),
+        };
+        if !key.is_empty() {
+            current = current.get(key)?;
+        }
+        for index in indexes.split('[').filter(|index| !index.is_empty()) {
+            let index: usize = index.strip_suffix(']')?.trim().parse().ok()?;
+            current = current.get(index)?;
+        }
+    }
+
+    Some(current)
+}
+
+fn scalar_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.trim().to_string()).filter(|text| !text.is_empty()),
+        Value::Number(num) => Some(num.to_string()),
+        _ => None,
+    }
 }
 
 pub fn parse_markdown(input: &str) -> ParsedSession {
-    let mut messages = Vec::new();
+    parse_markdown_with(input, &ParseOptions::default())
+}
 
-    for line in input.lines() {
-        let line = line.trim();
-        if line.is_empty() {
+pub fn parse_markdown_with(input: &str, options: &ParseOptions) -> ParsedSession {
+    let mut messages: Vec<ParsedMessage> = Vec::new();
+    let mut in_fence = false;
+    let mut blank_lines = 0;
+
+    for (idx, raw) in strip_bom(input).lines().enumerate() {
+        let line = raw.trim();
+
+        // Fenced code is kept verbatim in the current message: its lines are
+        // never split into messages or matched against role prefixes.
+        let is_fence = toggles_fence(line);
+        if in_fence || is_fence {
+            if is_fence {
+                in_fence = !in_fence;
+            }
+            let blanks = std::mem::take(&mut blank_lines);
+            match messages.last_mut() {
+                Some(message) => {
+                    message.text.push_str(&"\n".repeat(blanks + 1));
+                    message.text.push_str(raw);
+                }
+                None => messages.push(ParsedMessage {
+                    role: None,
+                    text: raw.to_string(),
+                    timestamp: None,
+                    tools: Vec::new(),
+                    commands: Vec::new(),
+                    line: Some(idx + 1),
+                }),
+            }
             continue;
         }
 
-        let (role, text) = match split_role_prefix(line) {
-            Some((role, text)) => (Some(role), text),
-            None => (None, line.to_string()),
+        if line.is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+        let blanks = std::mem::take(&mut blank_lines);
+
+        let (role, text) = match split_role_prefix(line, options) {
+            Some((role, text)) => (Some(options.resolve_role(role)), text),
+            None => {
+                // An unprefixed line continues the current role's message,
+                // keeping the blank lines that separated it.
+                if let Some(message) = messages.last_mut().filter(|m| m.role.is_some()) {
+                    message.text.push_str(&"\n".repeat(blanks + 1));
+                    message.text.push_str(raw.trim_end());
+                    continue;
+                }
+                (None, line.to_string())
+            }
         };
 
         if text.trim().is_empty() {
             continue;
         }
+        // `assistant: ```rust` opens a fence just like a bare fence line.
+        if role.is_some() && toggles_fence(&text) {
+            in_fence = true;
+        }
 
         messages.push(ParsedMessage {
             role,
             text,
             timestamp: None,
+            tools: Vec::new(),
+            commands: Vec::new(),
+            line: Some(idx + 1),
         });
     }
 
-    build_parsed_session(messages, Meta::default())
+    build_parsed_session(messages, Meta::default(), options)
 }
 
-fn build_parsed_session(messages: Vec<ParsedMessage>, mut meta: Meta) -> ParsedSession {
+/// Whether a line starting with a fence marker leaves it open: an inline
+/// span like ```` ```ls``` please ```` opens and closes on the same line.
+fn toggles_fence(text: &str) -> bool {
+    text.starts_with("```") && text.matches("```").count() % 2 == 1
+}
+
+fn build_parsed_session(
+    mut messages: Vec<ParsedMessage>,
+    mut meta: Meta,
+    options: &ParseOptions,
+) -> ParsedSession {
+    if options.strip_code_fences {
+        for message in &mut messages {
+            if let Some(code) = unwrap_code_fence(&message.text) {
+                message.text = code;
+            }
+        }
+    }
     if meta.created_at.is_none() {
         meta.created_at = messages.first().and_then(|m| m.timestamp.clone());
     }
@@ -223,8 +774,17 @@ fn build_parsed_session(messages: Vec<ParsedMessage>, mut meta: Meta) -> ParsedS
         .collect();
     let content = content_lines.join("\n");
 
-    let title =
-        first_user_title(&messages).or_else(|| messages.first().map(|m| m.text.trim().to_string()));
+    let title = meta
+        .title
+        .or_else(|| first_user_title(&messages))
+        .or_else(|| messages.first().map(|m| m.text.trim().to_string()));
+
+    let role_count = |role: &str| {
+        messages
+            .iter()
+            .filter(|message| message.role.as_deref() == Some(role))
+            .count()
+    };
 
     ParsedSession {
         created_at: meta.created_at,
@@ -233,10 +793,40 @@ fn build_parsed_session(messages: Vec<ParsedMessage>, mut meta: Meta) -> ParsedS
         workspace: meta.workspace,
         title,
         message_count: messages.len(),
-        snippet: make_snippet(&content),
+        user_message_count: role_count("user"),
+        assistant_message_count: role_count("assistant"),
+        snippet: make_snippet(&messages, &content),
         content,
         messages,
+        skipped_lines: 0,
+        continues_from: meta.continues_from,
+    }
+}
+
+/// The code inside a fence that spans all of `text`, or `None` when `text`
+/// is not one fenced block (no fence, prose around it, or several blocks).
+fn unwrap_code_fence(text: &str) -> Option<String> {
+    let text = text.trim();
+    let (opening, body) = text.split_once('\n')?;
+    let fence = fence_marker(opening)?;
+    let (code, closing) = body.rsplit_once('\n').unwrap_or(("", body));
+    // The closing fence is the same character, at least as long, alone.
+    let closes = fence_marker(closing)
+        .is_some_and(|close| close.starts_with(fence) && close.len() == closing.trim().len());
+    if !closes || code.lines().any(|line| fence_marker(line).is_some()) {
+        return None;
     }
+    let code = code.trim_matches('\n');
+    (!code.trim().is_empty()).then(|| code.to_string())
+}
+
+/// The backtick or tilde run opening a fence line (at least three), if
+/// `line` is one.
+fn fence_marker(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    let ch = line.chars().next().filter(|ch| *ch == '`' || *ch == '~')?;
+    let len = line.len() - line.trim_start_matches(ch).len();
+    (len >= 3).then(|| &line[..len])
 }
 
 fn format_session_entry(value: &Value) -> Option<ParsedMessage> {
@@ -244,6 +834,17 @@ fn format_session_entry(value: &Value) -> Option<ParsedMessage> {
         return None;
     }
 
+    if let Some(output) = tool_output(value) {
+        return Some(ParsedMessage {
+            role: Some("tool".to_string()),
+            text: output.text,
+            timestamp: extract_timestamp(value),
+            tools: Vec::new(),
+            commands: Vec::new(),
+            line: None,
+        });
+    }
+
     if type_is(value, json_types::RESPONSE_ITEM)
         && let Some(payload) = value.get("payload")
         && let Some(mut message) = message_from_object(payload)
@@ -276,6 +877,9 @@ fn format_session_entry(value: &Value) -> Option<ParsedMessage> {
                     .map(normalize_role),
                 text: text.trim().to_string(),
                 timestamp: extract_timestamp(value),
+                tools: Vec::new(),
+                commands: Vec::new(),
+                line: None,
             });
         }
     }
@@ -300,6 +904,7 @@ fn message_from_object(value: &Value) -> Option<ParsedMessage> {
         .get("content")
         .and_then(coerce_content)
         .or_else(|| value.get("text").and_then(coerce_content))
+        .or_else(|| value.get("parts").and_then(coerce_content))
         .or_else(|| value.get("message").and_then(coerce_content));
 
     let text = content?.trim().to_string();
@@ -311,6 +916,52 @@ fn message_from_object(value: &Value) -> Option<ParsedMessage> {
         role,
         text,
         timestamp: extract_timestamp(value),
+        tools: Vec::new(),
+        commands: Vec::new(),
+        line: None,
+    })
+}
+
+/// The result of a tool call, recorded as an entry of its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolOutput {
+    /// The `call_id` linking the result to its tool call's `id`.
+    pub call_id: Option<String>,
+    pub text: String,
+}
+
+/// Read a `{"type": "function_call_output", "call_id": …, "output": …}`
+/// entry, bare or as a `response_item` payload. The text comes from
+/// `output` (or `result`); an output that is itself JSON carrying an
+/// `output` string (as Codex writes them) is unwrapped.
+pub fn tool_output(value: &Value) -> Option<ToolOutput> {
+    let entry = if type_is(value, json_types::RESPONSE_ITEM) {
+        value.get("payload")?
+    } else {
+        value
+    };
+    if !type_is(entry, json_types::FUNCTION_CALL_OUTPUT) {
+        return None;
+    }
+
+    let output = entry.get("output").or_else(|| entry.get("result"))?;
+    let wrapped = match output {
+        Value::String(raw) => serde_json::from_str::<Value>(raw).ok(),
+        _ => None,
+    };
+    let inner = wrapped
+        .as_ref()
+        .unwrap_or(output)
+        .get("output")
+        .filter(|inner| inner.is_string());
+    let text = coerce_content(inner.unwrap_or(output))?;
+
+    Some(ToolOutput {
+        call_id: entry
+            .get("call_id")
+            .and_then(|v| v.as_str())
+            .map(str::to_string),
+        text,
     })
 }
 
@@ -333,15 +984,12 @@ fn coerce_content(value: &Value) -> Option<String> {
         };
     }
 
-    if type_is(value, json_types::INPUT_TEXT)
-        && let Some(text) = value.get("text").and_then(|v| v.as_str())
+    // A text block, bare or inside an array. Its `text` is a string, or an
+    // object holding it under `value` (OpenAI Assistants).
+    if is_text_block(value)
+        && let Some(text) = value.get("text")
     {
-        let trimmed = text.trim();
-        return if trimmed.is_empty() {
-            None
-        } else {
-            Some(trimmed.to_string())
-        };
+        return coerce_content(text.get("value").unwrap_or(text));
     }
 
     if let Some(content) = value.get("content") {
@@ -352,9 +1000,20 @@ fn coerce_content(value: &Value) -> Option<String> {
         return coerce_content(text);
     }
 
+    // Gemini: `{"parts": [{"text": "..."}]}`
+    if let Some(parts) = value.get("parts") {
+        return coerce_content(parts);
+    }
+
     None
 }
 
+fn is_text_block(value: &Value) -> bool {
+    type_is(value, json_types::TEXT)
+        || type_is(value, json_types::INPUT_TEXT)
+        || type_is(value, json_types::OUTPUT_TEXT)
+}
+
 fn extract_timestamp(value: &Value) -> Option<String> {
     extract_string_field(value, "created_at")
         .or_else(|| extract_string_field(value, "timestamp"))
@@ -384,16 +1043,23 @@ fn extract_string_field(value: &Value, key: &str) -> Option<String> {
     None
 }
 
-fn update_meta_from_value(meta: &mut Meta, value: &Value) {
+fn update_meta_from_value(meta: &mut Meta, value: &Value, options: &ParseOptions) {
     let Some(object) = value.as_object() else {
         return;
     };
 
+    if meta.title.is_none() {
+        meta.title = options
+            .title_fields
+            .iter()
+            .find_map(|field| extract_string_field(value, field));
+    }
+
     maybe_set(
         &mut meta.agent,
-        object
-            .get("agent")
-            .and_then(|v| v.as_str())
+        std::iter::once("agent")
+            .chain(options.agent_fields.iter().map(String::as_str))
+            .find_map(|field| object.get(field).and_then(|v| v.as_str()))
             .map(|v| v.to_string()),
     );
     maybe_set(
@@ -404,6 +1070,14 @@ fn update_meta_from_value(meta: &mut Meta, value: &Value) {
             .map(|v| v.to_string()),
     );
 
+    maybe_set(
+        &mut meta.continues_from,
+        ["continues_from", "parent_id"]
+            .into_iter()
+            .find_map(|field| object.get(field).and_then(|v| v.as_str()))
+            .map(|v| v.to_string()),
+    );
+
     if meta.created_at.is_none() {
         meta.created_at = extract_string_field(value, "created_at");
     }
@@ -421,7 +1095,7 @@ fn maybe_set(target: &mut Option<String>, value: Option<String>) {
     }
 }
 
-fn format_message_line(role: &Option<String>, text: &str) -> String {
+pub(crate) fn format_message_line(role: &Option<String>, text: &str) -> String {
     match role {
         Some(role) => format!("[{}] {}", role, text),
         None => text.to_string(),
@@ -429,7 +1103,12 @@ fn format_message_line(role: &Option<String>, text: &str) -> String {
 }
 
 fn normalize_role(role: &str) -> String {
-    role.trim().to_lowercase()
+    let role = role.trim().to_lowercase();
+    // Gemini calls the assistant `model`.
+    if role == "model" {
+        return "assistant".to_string();
+    }
+    role
 }
 
 fn first_user_title(messages: &[ParsedMessage]) -> Option<String> {
@@ -447,8 +1126,16 @@ fn first_user_title(messages: &[ParsedMessage]) -> Option<String> {
     })
 }
 
-fn make_snippet(content: &str) -> String {
-    let trimmed = content.trim();
+/// Session snippet: the lead of the first user message, falling back to the
+/// whole transcript. Sessions often open with the same system/template text,
+/// so the user's own words tell conversations apart far better.
+fn make_snippet(messages: &[ParsedMessage], content: &str) -> String {
+    let user_text = messages
+        .iter()
+        .filter(|message| message.role.as_deref() == Some("user"))
+        .map(|message| message.text.trim())
+        .find(|text| !text.is_empty());
+    let trimmed = user_text.unwrap_or_else(|| content.trim());
     if trimmed.is_empty() {
         return String::new();
     }
@@ -457,7 +1144,7 @@ fn make_snippet(content: &str) -> String {
     snippet
 }
 
-fn split_role_prefix(line: &str) -> Option<(String, String)> {
+fn split_role_prefix(line: &str, options: &ParseOptions) -> Option<(String, String)> {
     let (role, text) = line.split_once(':')?;
     let role = role.trim();
     let text = text.trim();
@@ -465,7 +1152,7 @@ fn split_role_prefix(line: &str) -> Option<(String, String)> {
         return None;
     }
 
-    if matches_role(role) {
+    if matches_role(role) || options.is_role_alias(role) {
         Some((normalize_role(role), text.to_string()))
     } else {
         None
@@ -6,22 +6,38 @@
 //! # Query Modes
 //!
 //! - **Literal** (default): Each word is quoted for exact matching. Safe for
-//!   dates and punctuation like `"2025-01-28"`.
+//!   dates and punctuation like `"2025-01-28"`. With
+//!   [`FindFilters::drop_stopwords`], common words ([`DEFAULT_STOPWORDS`])
+//!   are dropped first unless the whole query is in double quotes.
 //! - **FTS**: Raw FTS5 syntax for advanced queries like `title:rust AND async`.
+//! - **Near**: The literal words wrapped in `NEAR("a" "b", N)`, so they must
+//!   appear within N tokens of each other.
+//!
+//! Under the `trigram` tokenizer (see [`crate::index::FtsTokenizer`]) a
+//! literal word shorter than three characters has no trigram of its own, so
+//! it is matched through the indexed trigrams that contain it instead.
 //!
 //! # Key Functions
 //!
 //! - [`find_sessions`]: Search session-level content
 //! - [`find_messages`]: Search individual messages with optional context
+//! - [`for_each_message`]: Same, handing each hit over as soon as it is built
+//! - [`find_questions`]: Find the user question that best matches, per session
+//! - [`probe_query_terms`]: Check which words of a fruitless query match alone
+//! - [`distinct_values`]: List existing values of a filterable metadata column
 //!
 //! # Error Handling
 //!
 //! FTS5 syntax errors (in `--fts` mode) produce [`QueryError::InvalidFtsSyntax`]
 //! with the original query for debugging.
 
+use crate::index::{FtsTokenizer, IndexError, fts_tokenizer};
 use crate::model::{MessageContext, MessageHit, SessionHit};
-use rusqlite::{Connection, params};
+use rusqlite::{Connection, OptionalExtension, Row, Statement, ToSql, params};
+use std::collections::{HashMap, HashSet};
 
+/// The find queries end after their `WHERE` clause; [`ordered_sql`] appends
+/// the `ORDER BY` for [`FindFilters::sort`] and the `LIMIT`.
 const FIND_SESSIONS_SQL: &str = r#"
 SELECT s.path,
        s.title,
@@ -32,6 +48,7 @@ SELECT s.path,
        s.branch,
        s.last_message_at,
        s.snippet,
+       s.preview,
        bm25(sessions_fts) AS score
 FROM sessions_fts
 JOIN sessions s ON s.path = sessions_fts.path
@@ -42,10 +59,65 @@ WHERE sessions_fts MATCH ?1
   AND (?5 IS NULL OR s.branch = ?5)
   AND (?6 IS NULL OR s.last_message_at >= ?6)
   AND (?7 IS NULL OR s.last_message_at <= ?7)
-ORDER BY score ASC, s.last_message_at DESC
-LIMIT ?8;
+  AND (?8 IS NULL OR EXISTS (
+        SELECT 1 FROM messages_fts
+        WHERE messages_fts MATCH ?1
+          AND messages_fts.session_path = s.path
+          AND instr(',' || ?8 || ',', ',' || messages_fts.role || ',') > 0))
+  AND (?10 IS NULL OR s.path LIKE ?10 ESCAPE '\')
+  AND (?11 IS NULL OR s.agent IS NOT ?11)
+  AND (?12 IS NULL OR s.workspace IS NOT ?12)
+  AND (?13 IS NULL OR (s.repo_name IS NOT ?13 AND s.repo_root IS NOT ?13))
+  AND (?14 IS NULL OR instr(s.commands, ?14) > 0)
+  AND (?15 IS NULL OR MIN(s.user_message_count, s.assistant_message_count) >= ?15)
+"#;
+
+/// Score column and FTS source of [`FIND_SESSIONS_SQL`], swapped for
+/// [`ANY_FIELD_SOURCE`] under [`FindFilters::any_field`].
+const SESSIONS_FTS_SOURCE: &str = r#"       bm25(sessions_fts) AS score
+FROM sessions_fts
+JOIN sessions s ON s.path = sessions_fts.path
+WHERE sessions_fts MATCH ?1
+"#;
+
+/// Every session whose content matches `?1` or whose metadata contains
+/// `?16` (a `LIKE` pattern). Metadata-only matches have no bm25 score and
+/// rank after content matches.
+const ANY_FIELD_SOURCE: &str = r#"       COALESCE(f.score, 0.0) AS score
+FROM sessions s
+LEFT JOIN (
+    SELECT path, bm25(sessions_fts) AS score FROM sessions_fts WHERE sessions_fts MATCH ?1
+) f ON f.path = s.path
+WHERE (f.path IS NOT NULL
+       OR s.agent LIKE ?16 ESCAPE '\'
+       OR s.workspace LIKE ?16 ESCAPE '\'
+       OR s.repo_name LIKE ?16 ESCAPE '\'
+       OR s.branch LIKE ?16 ESCAPE '\'
+       OR s.title LIKE ?16 ESCAPE '\'
+       OR s.path LIKE ?16 ESCAPE '\')
+"#;
+
+/// [`FIND_SESSIONS_SQL`] without the metadata predicates, used when no
+/// filters are set so the planner never sees the `?N IS NULL OR` guards.
+const FIND_SESSIONS_UNFILTERED_SQL: &str = r#"
+SELECT s.path,
+       s.title,
+       s.agent,
+       s.workspace,
+       s.repo_root,
+       s.repo_name,
+       s.branch,
+       s.last_message_at,
+       s.snippet,
+       s.preview,
+       bm25(sessions_fts) AS score
+FROM sessions_fts
+JOIN sessions s ON s.path = sessions_fts.path
+WHERE sessions_fts MATCH ?1
 "#;
 
+/// `?16`, when set, narrows FTS matches to message ids found through the
+/// `messages(timestamp)` index; see [`date_prefilter`].
 const FIND_MESSAGES_SQL: &str = r#"
 SELECT m.session_path,
        m.turn_index,
@@ -58,7 +130,9 @@ SELECT m.session_path,
        s.repo_root,
        s.repo_name,
        s.branch,
-       bm25(messages_fts) AS score
+       bm25(messages_fts) AS score,
+       m.line,
+       COALESCE(m.timestamp, s.last_message_at) AS sort_time
 FROM messages_fts
 JOIN messages m ON m.id = messages_fts.message_id
 JOIN sessions s ON s.path = m.session_path
@@ -67,11 +141,151 @@ WHERE messages_fts MATCH ?1
   AND (?3 IS NULL OR s.workspace = ?3)
   AND (?4 IS NULL OR s.repo_name = ?4 OR s.repo_root = ?4)
   AND (?5 IS NULL OR s.branch = ?5)
-  AND (?6 IS NULL OR m.role = ?6)
+  AND (?6 IS NULL OR instr(',' || ?6 || ',', ',' || m.role || ',') > 0)
+  AND (?16 IS NULL OR messages_fts.message_id IN (
+        SELECT id FROM messages WHERE timestamp >= ?16 OR timestamp IS NULL))
   AND (?7 IS NULL OR COALESCE(m.timestamp, s.last_message_at) >= ?7)
   AND (?8 IS NULL OR COALESCE(m.timestamp, s.last_message_at) <= ?8)
-ORDER BY score ASC, COALESCE(m.timestamp, s.last_message_at) DESC
-LIMIT ?9;
+  AND (?9 IS NULL OR m.turn_index > (
+        SELECT MIN(anchor.turn_index)
+        FROM messages_fts
+        JOIN messages anchor ON anchor.id = messages_fts.message_id
+        WHERE messages_fts MATCH ?9
+          AND anchor.session_path = m.session_path))
+  AND (?11 IS NULL OR s.path LIKE ?11 ESCAPE '\')
+  AND (?12 IS NULL OR s.agent IS NOT ?12)
+  AND (?13 IS NULL OR s.workspace IS NOT ?13)
+  AND (?14 IS NULL OR (s.repo_name IS NOT ?14 AND s.repo_root IS NOT ?14))
+  AND (?15 IS NULL OR instr(s.commands, ?15) > 0)
+  AND (?17 IS NULL OR MIN(s.user_message_count, s.assistant_message_count) >= ?17)
+"#;
+
+/// [`FIND_MESSAGES_SQL`] (substituted for `{base}`) keeping at most `?18`
+/// hits per session, ranked within each session by score, before the
+/// overall limit applies. Parameters `?1`..`?17` are the base query's.
+const FIND_MESSAGES_PER_SESSION_SQL: &str = r#"
+SELECT *
+FROM (
+  SELECT *,
+         ROW_NUMBER() OVER (
+           PARTITION BY session_path
+           ORDER BY score ASC, sort_time DESC
+         ) AS session_rank
+  FROM ({base})
+)
+WHERE session_rank <= ?18
+"#;
+
+/// [`FIND_MESSAGES_SQL`] without the metadata predicates; see
+/// [`FIND_SESSIONS_UNFILTERED_SQL`].
+const FIND_MESSAGES_UNFILTERED_SQL: &str = r#"
+SELECT m.session_path,
+       m.turn_index,
+       m.role,
+       m.timestamp,
+       m.text,
+       s.title,
+       s.agent,
+       s.workspace,
+       s.repo_root,
+       s.repo_name,
+       s.branch,
+       bm25(messages_fts) AS score,
+       m.line
+FROM messages_fts
+JOIN messages m ON m.id = messages_fts.message_id
+JOIN sessions s ON s.path = m.session_path
+WHERE messages_fts MATCH ?1
+"#;
+
+const LOAD_CONTEXT_SQL: &str = r#"
+SELECT turn_index, role, timestamp, text
+FROM messages
+WHERE session_path = ?1 AND turn_index BETWEEN ?2 AND ?3
+  AND NOT (?4 AND turn_index = ?5)
+ORDER BY turn_index ASC;
+"#;
+
+/// The hit's own FTS row with matched terms wrapped in `\x01`/`\x02`.
+const MATCHED_TERMS_SQL: &str = r#"
+SELECT highlight(messages_fts, 0, char(1), char(2))
+FROM messages_fts
+WHERE messages_fts MATCH ?1
+  AND messages_fts.message_id = (
+        SELECT id FROM messages WHERE session_path = ?2 AND turn_index = ?3);
+"#;
+
+/// An FTS5 `snippet()` window of the hit's text, matched terms wrapped in
+/// `?4`/`?5`, at most `?6` tokens long.
+const EXCERPT_SQL: &str = r#"
+SELECT snippet(messages_fts, 0, ?4, ?5, '…', ?6)
+FROM messages_fts
+WHERE messages_fts MATCH ?1
+  AND messages_fts.message_id = (
+        SELECT id FROM messages WHERE session_path = ?2 AND turn_index = ?3);
+"#;
+
+/// `fts5vocab` views of both FTS tables, for [`TRIGRAMS_CONTAINING_SQL`].
+const CREATE_VOCAB_SQL: &str = r#"
+CREATE VIRTUAL TABLE IF NOT EXISTS temp.sessions_fts_vocab
+  USING fts5vocab(main, 'sessions_fts', 'row');
+CREATE VIRTUAL TABLE IF NOT EXISTS temp.messages_fts_vocab
+  USING fts5vocab(main, 'messages_fts', 'row');
+"#;
+
+/// Indexed trigrams containing the short term `?1`, most widespread first.
+const TRIGRAMS_CONTAINING_SQL: &str = r#"
+SELECT term
+FROM (
+  SELECT term, doc FROM temp.sessions_fts_vocab
+  UNION ALL
+  SELECT term, doc FROM temp.messages_fts_vocab
+)
+WHERE instr(term, ?1) > 0
+GROUP BY term
+ORDER BY SUM(doc) DESC
+LIMIT ?2;
+"#;
+
+/// Most trigrams one short term expands to. A one-letter Latin term can sit
+/// in thousands of them; past this many, the rarest are left out.
+const MAX_TRIGRAM_EXPANSION: i64 = 512;
+
+/// Tokens in a [`MessageHit::excerpt`] window (FTS5 allows at most 64).
+const EXCERPT_TOKENS: i64 = 24;
+
+/// Text of the message in session `?2` that best matches `?1`.
+const BEST_MESSAGE_SQL: &str = r#"
+SELECT m.text
+FROM messages_fts
+JOIN messages m ON m.id = messages_fts.message_id
+WHERE messages_fts MATCH ?1
+  AND messages_fts.session_path = ?2
+ORDER BY bm25(messages_fts) ASC
+LIMIT 1;
+"#;
+
+const PARENT_PATH_SQL: &str = "SELECT parent_path FROM sessions WHERE path = ?1";
+
+const CHILD_PATH_SQL: &str =
+    "SELECT path FROM sessions WHERE parent_path = ?1 ORDER BY path LIMIT 1";
+
+/// The last `?2` messages of a session, newest first.
+const TAIL_MESSAGES_SQL: &str = r#"
+SELECT turn_index, role, timestamp, text
+FROM messages
+WHERE session_path = ?1
+ORDER BY turn_index DESC
+LIMIT ?2;
+"#;
+
+/// The first `?2` messages of a session.
+const HEAD_MESSAGES_SQL: &str = r#"
+SELECT turn_index, role, timestamp, text
+FROM messages
+WHERE session_path = ?1
+ORDER BY turn_index ASC
+LIMIT ?2;
 "#;
 
 #[derive(Debug, thiserror::Error)]
@@ -83,43 +297,266 @@ pub enum QueryError {
         query: String,
         source: rusqlite::Error,
     },
+    #[error("unsupported column for distinct values: {column}")]
+    UnsupportedColumn { column: String },
     #[error("sqlite error: {source}")]
     Sqlite { source: rusqlite::Error },
+    #[error("{source}")]
+    Index { source: IndexError },
 }
 
+/// Session columns whose values may be listed by [`distinct_values`].
+const DISTINCT_VALUE_COLUMNS: &[&str] = &["agent", "workspace", "repo_name", "repo_root", "branch"];
+
 impl From<rusqlite::Error> for QueryError {
     fn from(source: rusqlite::Error) -> Self {
         Self::Sqlite { source }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+impl From<IndexError> for QueryError {
+    fn from(source: IndexError) -> Self {
+        Self::Index { source }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum FindScope {
     Session,
     #[default]
     Message,
+    /// User turns only, best match per session (see [`find_questions`]).
+    Question,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum QueryMode {
     #[default]
     Literal,
     Fts,
+    /// Literal words that must all occur within this many tokens of each
+    /// other, as an FTS5 `NEAR(...)` group. A single word matches plainly.
+    Near(usize),
+}
+
+/// Result ordering. Ties fall back to the other key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FindSort {
+    /// Best bm25 score first, newer first among equal scores.
+    #[default]
+    Relevance,
+    /// Most recent first (message timestamp, or the session's last message).
+    Newest,
+    /// Least recent first; results without any timestamp come last.
+    Oldest,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
 pub struct FindFilters {
     pub agent: Option<String>,
     pub workspace: Option<String>,
     pub repo: Option<String>,
     pub branch: Option<String>,
+    /// Message role to match; a comma-separated list (`user,assistant`)
+    /// matches any of them.
     pub role: Option<String>,
     pub after: Option<String>,
     pub before: Option<String>,
     pub limit: usize,
     pub around: usize,
+    /// Leave the matched turn out of its own `around` context, so context
+    /// holds only the surrounding messages.
+    pub exclude_self_context: bool,
+    /// Only return messages after the first turn in the same session that
+    /// matches this query (message scope only). Parsed like the main query.
+    pub after_match: Option<String>,
+    /// Exclude sessions from this agent.
+    pub not_agent: Option<String>,
+    /// Exclude sessions from this workspace.
+    pub not_workspace: Option<String>,
+    /// Exclude sessions from this repo (matched by name or root, like `repo`).
+    pub not_repo: Option<String>,
+    /// Only sessions that ran a shell command containing this substring
+    /// (case-sensitive).
+    pub command_contains: Option<String>,
+    /// Only sessions with at least this many user messages and this many
+    /// assistant messages: real back-and-forth rather than one-shot prompts.
+    pub min_exchanges: Option<u32>,
+    /// Only sessions whose file path contains this substring (matched
+    /// literally; `%` and `_` are not wildcards).
+    pub path_contains: Option<String>,
+    /// Cap on message hits from any single session (message scope only).
+    /// `None` or `0` means no cap.
+    pub per_session_limit: Option<usize>,
+    /// Drop stopwords from literal queries before matching. A query made
+    /// only of stopwords is matched as written.
+    pub drop_stopwords: bool,
+    /// Stopword list used by `drop_stopwords`; `None` means
+    /// [`DEFAULT_STOPWORDS`].
+    pub stopwords: Option<Vec<String>>,
     pub scope: FindScope,
     pub query_mode: QueryMode,
+    pub sort: FindSort,
+    /// Invert the order given by `sort`.
+    pub reverse: bool,
+    /// Rank this agent's results ahead of others of similar relevance,
+    /// without excluding anyone.
+    pub prefer_agent: Option<String>,
+    /// Treat files linked by `continues_from` metadata as one session:
+    /// session scope returns only the best hit per chain, and `around`
+    /// context continues into the previous or next file of the chain.
+    pub merge_continuations: bool,
+    /// Replace each session hit's stored snippet with the text of its
+    /// best-matching message (session scope only). Costs one extra FTS
+    /// lookup per hit; sessions with no matching message keep their snippet.
+    pub match_snippets: bool,
+    /// Fill [`MessageHit::matched_terms`] (message scope only). Costs one
+    /// extra FTS lookup per hit.
+    pub explain_matches: bool,
+    /// Fill [`MessageHit::excerpt`] with a window around the match, terms
+    /// wrapped in these markers (message scope only). Costs one extra FTS
+    /// lookup per hit.
+    pub excerpt: Option<ExcerptMarkers>,
+    /// Also match sessions whose agent, workspace, repo name, branch, title,
+    /// or path contains the query as a substring (case-insensitive for
+    /// ASCII), not only sessions whose content matches it (session scope
+    /// only).
+    pub any_field: bool,
+}
+
+/// Strings placed around each matched term in a [`MessageHit::excerpt`].
+/// Both empty gives a plain excerpt.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ExcerptMarkers {
+    pub open: String,
+    pub close: String,
+}
+
+/// Common English words dropped from literal queries under
+/// [`FindFilters::drop_stopwords`].
+pub const DEFAULT_STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "in", "is", "it", "of", "on",
+    "or", "that", "the", "this", "to", "was", "with",
+];
+
+impl FindFilters {
+    /// Whether any metadata predicate (agent, workspace, repo, branch, role,
+    /// date bound, path or command substring, anchor match, or exclusion) is
+    /// set. Without one, queries take the unfiltered path.
+    pub fn has_metadata_filters(&self) -> bool {
+        self.agent.is_some()
+            || self.workspace.is_some()
+            || self.repo.is_some()
+            || self.branch.is_some()
+            || self.role.is_some()
+            || self.after.is_some()
+            || self.before.is_some()
+            || self.after_match.is_some()
+            || self.path_contains.is_some()
+            || self.not_agent.is_some()
+            || self.not_workspace.is_some()
+            || self.not_repo.is_some()
+            || self.command_contains.is_some()
+            || self.min_exchanges.is_some()
+    }
+
+    /// The row limit queries apply: `limit`, or the default of 5 when it is
+    /// 0. A result set this long may have been cut short.
+    pub fn effective_limit(&self) -> usize {
+        normalize_limit(self.limit) as usize
+    }
+
+    /// `path_contains` as a `LIKE ... ESCAPE '\'` pattern.
+    fn path_pattern(&self) -> Option<String> {
+        self.path_contains.as_deref().map(substring_pattern)
+    }
+
+    fn active_stopwords(&self) -> Vec<&str> {
+        if !self.drop_stopwords {
+            return Vec::new();
+        }
+        match &self.stopwords {
+            Some(words) => words.iter().map(String::as_str).collect(),
+            None => DEFAULT_STOPWORDS.to_vec(),
+        }
+    }
+}
+
+/// Recency and agent expressions of one find query, for [`ordered_sql`].
+struct SortColumns {
+    time: &'static str,
+    agent: &'static str,
+}
+
+const SESSION_SORT: SortColumns = SortColumns {
+    time: "s.last_message_at",
+    agent: "s.agent",
+};
+
+const MESSAGE_SORT: SortColumns = SortColumns {
+    time: "COALESCE(m.timestamp, s.last_message_at)",
+    agent: "s.agent",
+};
+
+/// Columns of the outer select in [`FIND_MESSAGES_PER_SESSION_SQL`].
+const RANKED_MESSAGE_SORT: SortColumns = SortColumns {
+    time: "sort_time",
+    agent: "agent",
+};
+
+/// Complete a find query: `base` plus the `ORDER BY` for `filters.sort`
+/// (inverted by `filters.reverse`) and `LIMIT ?{limit_param}`.
+///
+/// With `filters.prefer_agent`, relevance ranks by whole-number bm25 band
+/// first and puts that agent's rows ahead within each band; newest order
+/// uses the preference only to break ties. The agent is appended to
+/// `params` and bound as the next parameter.
+fn ordered_sql<'p>(
+    base: &str,
+    filters: &'p FindFilters,
+    columns: &SortColumns,
+    limit_param: usize,
+    params: &mut Vec<&'p dyn ToSql>,
+) -> String {
+    let (first, second) = if filters.reverse {
+        ("DESC", "ASC")
+    } else {
+        ("ASC", "DESC")
+    };
+    let time = columns.time;
+    // Always ascending, so `reverse` never sends the preferred agent last.
+    let preferred = filters.prefer_agent.as_ref().map(|agent| {
+        params.push(agent);
+        format!(
+            "CASE WHEN {} = ?{} THEN 0 ELSE 1 END ASC",
+            columns.agent,
+            params.len()
+        )
+    });
+    let order = match (filters.sort, preferred) {
+        (FindSort::Relevance, None) => format!("score {first}, {time} {second}"),
+        (FindSort::Relevance, Some(preferred)) => {
+            format!("ROUND(score) {first}, {preferred}, score {first}, {time} {second}")
+        }
+        (FindSort::Newest, None) => format!("{time} {second}, score {first}"),
+        (FindSort::Newest, Some(preferred)) => {
+            format!("{time} {second}, {preferred}, score {first}")
+        }
+        (FindSort::Oldest, None) => format!("{time} {first} NULLS LAST, score {first}"),
+        (FindSort::Oldest, Some(preferred)) => {
+            format!("{time} {first} NULLS LAST, {preferred}, score {first}")
+        }
+    };
+    format!("{base}ORDER BY {order}\nLIMIT ?{limit_param};\n")
+}
+
+/// `needle` as a `LIKE ... ESCAPE '\'` pattern matching it anywhere.
+fn substring_pattern(needle: &str) -> String {
+    let escaped = needle
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{escaped}%")
 }
 
 fn map_rusqlite_error(error: rusqlite::Error, query: &str, mode: QueryMode) -> QueryError {
@@ -140,131 +577,493 @@ fn is_fts_syntax_error(error: &rusqlite::Error) -> bool {
         || message.contains("parse error in MATCH expression")
 }
 
+/// Search session-level content.
+///
+/// When `filters.role` is set, only sessions with at least one message of
+/// that role matching the query are returned; ranking still uses the whole
+/// session content. (At message scope the role filters the hits themselves.)
 pub fn find_sessions(
     conn: &Connection,
     query: &str,
     filters: &FindFilters,
 ) -> Result<Vec<SessionHit>, QueryError> {
-    let query = normalize_query(query, filters.query_mode)?;
+    let raw_query = query;
+    let query = match_expression(conn, query, filters.query_mode, &filters.active_stopwords())?;
     let limit = normalize_limit(filters.limit);
+
+    let mut results = fetch_sessions(conn, &query, raw_query, filters, limit)?;
+    if filters.merge_continuations {
+        // Merging drops hits after the SQL limit applied, so fetch more
+        // rows until `limit` chains are found or the matches run out.
+        let mut chain = ChainStatements::prepare(conn)?;
+        let mut roots: HashMap<String, String> = HashMap::new();
+        let mut fetched = limit;
+        loop {
+            let exhausted = (results.len() as i64) < fetched;
+            let mut seen = HashSet::new();
+            let mut merged = Vec::new();
+            for hit in results {
+                let root = match roots.get(&hit.path) {
+                    Some(root) => root.clone(),
+                    None => {
+                        let root = chain.root(&hit.path)?;
+                        roots.insert(hit.path.clone(), root.clone());
+                        root
+                    }
+                };
+                if seen.insert(root) {
+                    merged.push(hit);
+                }
+            }
+            if exhausted || merged.len() as i64 >= limit {
+                merged.truncate(limit as usize);
+                results = merged;
+                break;
+            }
+            fetched = fetched.saturating_mul(2);
+            results = fetch_sessions(conn, &query, raw_query, filters, fetched)?;
+        }
+    }
+
+    if filters.match_snippets {
+        let mut stmt = conn.prepare(BEST_MESSAGE_SQL)?;
+        for hit in &mut results {
+            let best: Option<String> = stmt
+                .query_row(params![&query, &hit.path], |row| row.get(0))
+                .optional()?;
+            if best.is_some() {
+                hit.snippet = best;
+            }
+        }
+    }
+
+    Ok(results)
+}
+
+/// The session rows for an already-built match expression, at most `limit`.
+fn fetch_sessions(
+    conn: &Connection,
+    query: &str,
+    raw_query: &str,
+    filters: &FindFilters,
+    limit: i64,
+) -> Result<Vec<SessionHit>, QueryError> {
     let mode = filters.query_mode;
+    let path_pattern = filters.path_pattern();
 
-    let mut stmt = conn.prepare(FIND_SESSIONS_SQL)?;
-    let rows = stmt
-        .query_map(
-            params![
-                &query,
-                &filters.agent,
-                &filters.workspace,
-                &filters.repo,
-                &filters.branch,
-                &filters.after,
-                &filters.before,
-                limit,
-            ],
-            |row| {
-                Ok(SessionHit {
-                    path: row.get(0)?,
-                    title: row.get(1)?,
-                    agent: row.get(2)?,
-                    workspace: row.get(3)?,
-                    repo_root: row.get(4)?,
-                    repo_name: row.get(5)?,
-                    branch: row.get(6)?,
-                    last_message_at: row.get(7)?,
-                    snippet: row.get(8)?,
-                    score: row.get(9)?,
-                })
-            },
+    let any_field_pattern = filters
+        .any_field
+        .then(|| substring_pattern(raw_query.trim()));
+
+    let filtered = filters.has_metadata_filters() || filters.any_field;
+    let mut params: Vec<&dyn ToSql> = if filtered {
+        vec![
+            &query,
+            &filters.agent,
+            &filters.workspace,
+            &filters.repo,
+            &filters.branch,
+            &filters.after,
+            &filters.before,
+            &filters.role,
+            &limit,
+            &path_pattern,
+            &filters.not_agent,
+            &filters.not_workspace,
+            &filters.not_repo,
+            &filters.command_contains,
+            &filters.min_exchanges,
+        ]
+    } else {
+        vec![&query, &limit]
+    };
+    let sql = if filters.any_field {
+        // `?16` only appears in the any-field form of the query.
+        params.push(&any_field_pattern);
+        let base = FIND_SESSIONS_SQL.replace(SESSIONS_FTS_SOURCE, ANY_FIELD_SOURCE);
+        ordered_sql(&base, filters, &SESSION_SORT, 9, &mut params)
+    } else if filtered {
+        ordered_sql(FIND_SESSIONS_SQL, filters, &SESSION_SORT, 9, &mut params)
+    } else {
+        ordered_sql(
+            FIND_SESSIONS_UNFILTERED_SQL,
+            filters,
+            &SESSION_SORT,
+            2,
+            &mut params,
         )
-        .map_err(|err| map_rusqlite_error(err, &query, mode))?;
+    };
+
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt
+        .query_map(params.as_slice(), |row| {
+            Ok(SessionHit {
+                path: row.get(0)?,
+                title: row.get(1)?,
+                agent: row.get(2)?,
+                workspace: row.get(3)?,
+                repo_root: row.get(4)?,
+                repo_name: row.get(5)?,
+                branch: row.get(6)?,
+                last_message_at: row.get(7)?,
+                snippet: row.get(8)?,
+                preview: row.get(9)?,
+                score: row.get(10)?,
+            })
+        })
+        .map_err(|err| map_rusqlite_error(err, query, mode))?;
 
     let mut results = Vec::new();
     for row in rows {
-        results.push(row.map_err(|err| map_rusqlite_error(err, &query, mode))?);
+        results.push(row.map_err(|err| map_rusqlite_error(err, query, mode))?);
     }
-
     Ok(results)
 }
 
+/// Search individual messages.
+///
+/// With `filters.per_session_limit`, at most that many of the best hits
+/// per session are kept, so one long session cannot fill every slot.
+///
+/// With `filters.after_match`, a second FTS lookup finds the first turn in
+/// each session matching that query, and only later messages of the same
+/// session are returned. Sessions where it never matches return nothing.
 pub fn find_messages(
     conn: &Connection,
     query: &str,
     filters: &FindFilters,
 ) -> Result<Vec<MessageHit>, QueryError> {
-    let query = normalize_query(query, filters.query_mode)?;
+    let mut results = Vec::new();
+    for_each_message(conn, query, filters, |hit| {
+        results.push(hit);
+        Ok::<_, QueryError>(())
+    })?;
+    Ok(results)
+}
+
+/// Streaming form of [`find_messages`]: `on_hit` receives each hit, with
+/// its `around` context already loaded, before the next row is read.
+/// Returns the number of hits delivered.
+///
+/// Lets callers emit results incrementally instead of buffering them all.
+/// The context statement is prepared once and reused for every hit.
+pub fn for_each_message<E, F>(
+    conn: &Connection,
+    query: &str,
+    filters: &FindFilters,
+    mut on_hit: F,
+) -> Result<usize, E>
+where
+    E: From<QueryError>,
+    F: FnMut(MessageHit) -> Result<(), E>,
+{
+    let query = match_expression(conn, query, filters.query_mode, &filters.active_stopwords())?;
     let limit = normalize_limit(filters.limit);
     let mode = filters.query_mode;
+    let path_pattern = filters.path_pattern();
+    let after_match = filters
+        .after_match
+        .as_deref()
+        .map(|anchor| match_expression(conn, anchor, mode, &filters.active_stopwords()))
+        .transpose()?;
+    let per_session_limit = filters.per_session_limit.filter(|cap| *cap > 0);
+    let date_prefilter = date_prefilter(conn, filters.after.as_deref())?;
 
-    let mut stmt = conn.prepare(FIND_MESSAGES_SQL)?;
-    let rows = stmt
-        .query_map(
-            params![
-                &query,
-                &filters.agent,
-                &filters.workspace,
-                &filters.repo,
-                &filters.branch,
-                &filters.role,
-                &filters.after,
-                &filters.before,
-                limit,
-            ],
-            |row| {
-                Ok(MessageHit {
-                    path: row.get(0)?,
-                    turn_index: row.get(1)?,
-                    role: row.get(2)?,
-                    timestamp: row.get(3)?,
-                    text: row.get(4)?,
-                    title: row.get(5)?,
-                    agent: row.get(6)?,
-                    workspace: row.get(7)?,
-                    repo_root: row.get(8)?,
-                    repo_name: row.get(9)?,
-                    branch: row.get(10)?,
-                    score: row.get(11)?,
-                    context: None,
-                })
-            },
+    let cap = per_session_limit.map(|cap| cap as i64);
+
+    let mut params: Vec<&dyn ToSql> = if cap.is_some() || filters.has_metadata_filters() {
+        vec![
+            &query,
+            &filters.agent,
+            &filters.workspace,
+            &filters.repo,
+            &filters.branch,
+            &filters.role,
+            &filters.after,
+            &filters.before,
+            &after_match,
+            &limit,
+            &path_pattern,
+            &filters.not_agent,
+            &filters.not_workspace,
+            &filters.not_repo,
+            &filters.command_contains,
+            &date_prefilter,
+            &filters.min_exchanges,
+        ]
+    } else {
+        vec![&query, &limit]
+    };
+    let sql = if cap.is_some() {
+        params.push(&cap);
+        let base = FIND_MESSAGES_PER_SESSION_SQL.replace("{base}", FIND_MESSAGES_SQL);
+        ordered_sql(&base, filters, &RANKED_MESSAGE_SORT, 10, &mut params)
+    } else if filters.has_metadata_filters() {
+        ordered_sql(FIND_MESSAGES_SQL, filters, &MESSAGE_SORT, 10, &mut params)
+    } else {
+        ordered_sql(
+            FIND_MESSAGES_UNFILTERED_SQL,
+            filters,
+            &MESSAGE_SORT,
+            2,
+            &mut params,
         )
+    };
+
+    let mut stmt = conn.prepare(&sql).map_err(QueryError::from)?;
+    let rows = stmt
+        .query_map(params.as_slice(), message_hit_from_row)
         .map_err(|err| map_rusqlite_error(err, &query, mode))?;
 
-    let mut results = Vec::new();
+    let mut extras = HitExtras::prepare(conn, filters)?;
+    let mut count = 0;
     for row in rows {
         let mut hit = row.map_err(|err| map_rusqlite_error(err, &query, mode))?;
-        if filters.around > 0 {
+        extras.fill(&mut hit, &query, filters)?;
+        on_hit(hit)?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Share of all messages below which `--after` pre-filters FTS matches by
+/// message id. Above it, building the id set costs more than it saves.
+const DATE_PREFILTER_MAX_SHARE: i64 = 4;
+
+/// The `--after` cutoff to pre-filter message ids with (via the
+/// `messages(timestamp)` index), or `None` when the cutoff keeps more than
+/// 1/[`DATE_PREFILTER_MAX_SHARE`] of messages. Counting stops at that
+/// threshold, so the check stays cheap on large histories.
+fn date_prefilter<'a>(
+    conn: &Connection,
+    after: Option<&'a str>,
+) -> Result<Option<&'a str>, QueryError> {
+    let Some(after) = after else {
+        return Ok(None);
+    };
+    let total: i64 = conn.query_row("SELECT COALESCE(MAX(id), 0) FROM messages", [], |row| {
+        row.get(0)
+    })?;
+    let threshold = total / DATE_PREFILTER_MAX_SHARE;
+    let recent: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM (
+           SELECT 1 FROM messages WHERE timestamp >= ?1 OR timestamp IS NULL LIMIT ?2
+         )",
+        params![after, threshold + 1],
+        |row| row.get(0),
+    )?;
+    Ok((recent <= threshold).then_some(after))
+}
+
+/// Per-hit lookups requested by the filters, each prepared once: `around`
+/// context, matched terms, and context across continuation files.
+struct HitExtras<'conn> {
+    context: Option<Statement<'conn>>,
+    terms: Option<Statement<'conn>>,
+    excerpt: Option<Statement<'conn>>,
+    chain: Option<ChainStatements<'conn>>,
+}
+
+impl<'conn> HitExtras<'conn> {
+    fn prepare(conn: &'conn Connection, filters: &FindFilters) -> Result<Self, QueryError> {
+        let context = if filters.around > 0 {
+            Some(conn.prepare(LOAD_CONTEXT_SQL)?)
+        } else {
+            None
+        };
+        let terms = if filters.explain_matches {
+            Some(conn.prepare(MATCHED_TERMS_SQL)?)
+        } else {
+            None
+        };
+        let excerpt = if filters.excerpt.is_some() {
+            Some(conn.prepare(EXCERPT_SQL)?)
+        } else {
+            None
+        };
+        let chain = if filters.merge_continuations && filters.around > 0 {
+            Some(ChainStatements::prepare(conn)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            context,
+            terms,
+            excerpt,
+            chain,
+        })
+    }
+
+    fn fill(
+        &mut self,
+        hit: &mut MessageHit,
+        query: &str,
+        filters: &FindFilters,
+    ) -> Result<(), QueryError> {
+        if let Some(terms) = self.terms.as_mut() {
+            hit.matched_terms = Some(load_matched_terms(terms, query, &hit.path, hit.turn_index)?);
+        }
+        if let (Some(excerpt), Some(markers)) = (self.excerpt.as_mut(), filters.excerpt.as_ref()) {
+            hit.excerpt = excerpt
+                .query_row(
+                    params![
+                        query,
+                        &hit.path,
+                        hit.turn_index,
+                        &markers.open,
+                        &markers.close,
+                        EXCERPT_TOKENS
+                    ],
+                    |row| row.get(0),
+                )
+                .optional()?;
+        }
+        if let Some(context) = self.context.as_mut() {
             hit.context = Some(load_context(
-                conn,
+                context,
                 &hit.path,
                 hit.turn_index,
                 filters.around,
+                filters.exclude_self_context,
             )?);
         }
-        results.push(hit);
+        if let (Some(chain), Some(context)) = (self.chain.as_mut(), hit.context.as_mut()) {
+            chain.extend_context(context, &hit.path, hit.turn_index, filters.around)?;
+        }
+        Ok(())
     }
+}
 
-    Ok(results)
+/// Map a row of the find-messages queries (shared column order).
+fn message_hit_from_row(row: &Row<'_>) -> rusqlite::Result<MessageHit> {
+    Ok(MessageHit {
+        path: row.get(0)?,
+        turn_index: row.get(1)?,
+        role: row.get(2)?,
+        timestamp: row.get(3)?,
+        text: row.get(4)?,
+        title: row.get(5)?,
+        agent: row.get(6)?,
+        workspace: row.get(7)?,
+        repo_root: row.get(8)?,
+        repo_name: row.get(9)?,
+        branch: row.get(10)?,
+        score: row.get(11)?,
+        context: None,
+        matched_terms: None,
+        excerpt: None,
+        line: row.get(12)?,
+    })
 }
 
-fn load_context(
+/// Search only user-role messages, returning the best-matching question
+/// from each session.
+///
+/// Answers often repeat the question's words many times over; restricting
+/// the match to what the user asked finds "the session where I asked about
+/// X" more precisely than session or message scope. `filters.role` and
+/// `filters.per_session_limit` are overridden.
+pub fn find_questions(
+    conn: &Connection,
+    query: &str,
+    filters: &FindFilters,
+) -> Result<Vec<MessageHit>, QueryError> {
+    let filters = FindFilters {
+        role: Some("user".to_string()),
+        per_session_limit: Some(1),
+        ..filters.clone()
+    };
+    find_messages(conn, query, &filters)
+}
+
+/// Whether one word of a literal query finds anything on its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TermProbe {
+    pub term: String,
+    pub matches: bool,
+}
+
+/// Re-run each word of a literal `query` by itself under the same filters,
+/// to show which words keep a query from matching. Costs one limit-1 query
+/// per word. Raw FTS and fully quoted queries return no probes: their terms
+/// are not independent words.
+pub fn probe_query_terms(
     conn: &Connection,
+    query: &str,
+    filters: &FindFilters,
+) -> Result<Vec<TermProbe>, QueryError> {
+    let query = query.trim();
+    if filters.query_mode == QueryMode::Fts || is_quoted_phrase(query) {
+        return Ok(Vec::new());
+    }
+    let probe_filters = FindFilters {
+        limit: 1,
+        around: 0,
+        explain_matches: false,
+        excerpt: None,
+        match_snippets: false,
+        ..filters.clone()
+    };
+    let mut probes = Vec::new();
+    for term in literal_tokens(query, &filters.active_stopwords()) {
+        let matches = match filters.scope {
+            FindScope::Session => !find_sessions(conn, term, &probe_filters)?.is_empty(),
+            FindScope::Message => !find_messages(conn, term, &probe_filters)?.is_empty(),
+            FindScope::Question => !find_questions(conn, term, &probe_filters)?.is_empty(),
+        };
+        probes.push(TermProbe {
+            term: term.to_string(),
+            matches,
+        });
+    }
+    Ok(probes)
+}
+
+/// List the distinct non-empty values of a session metadata column.
+///
+/// The column name is interpolated into SQL, so only names in
+/// `DISTINCT_VALUE_COLUMNS` are accepted.
+pub fn distinct_values(conn: &Connection, column: &str) -> Result<Vec<String>, QueryError> {
+    let Some(column) = DISTINCT_VALUE_COLUMNS
+        .iter()
+        .find(|allowed| **allowed == column)
+    else {
+        return Err(QueryError::UnsupportedColumn {
+            column: column.to_string(),
+        });
+    };
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT DISTINCT {column} FROM sessions
+         WHERE {column} IS NOT NULL AND {column} != ''
+         ORDER BY {column} ASC"
+    ))?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+
+    let mut values = Vec::new();
+    for row in rows {
+        values.push(row?);
+    }
+
+    Ok(values)
+}
+
+fn load_context(
+    stmt: &mut Statement<'_>,
     session_path: &str,
     turn_index: i64,
     around: usize,
+    exclude_self: bool,
 ) -> Result<Vec<MessageContext>, QueryError> {
     let around = around as i64;
     let start = turn_index.saturating_sub(around);
     let end = turn_index.saturating_add(around);
 
-    let mut stmt = conn.prepare(
-        "SELECT turn_index, role, timestamp, text
-         FROM messages
-         WHERE session_path = ?1 AND turn_index BETWEEN ?2 AND ?3
-         ORDER BY turn_index ASC",
-    )?;
-    let rows = stmt.query_map(params![session_path, start, end], |row| {
+    let params = params![session_path, start, end, exclude_self, turn_index];
+    let rows = stmt.query_map(params, |row| {
         Ok(MessageContext {
+            path: None,
             turn_index: row.get(0)?,
             role: row.get(1)?,
             timestamp: row.get(2)?,
@@ -280,26 +1079,232 @@ fn load_context(
     Ok(context)
 }
 
-fn normalize_query(query: &str, mode: QueryMode) -> Result<String, QueryError> {
+/// Statements for walking continuation chains (`sessions.parent_path`).
+struct ChainStatements<'conn> {
+    parent: Statement<'conn>,
+    child: Statement<'conn>,
+    tail: Statement<'conn>,
+    head: Statement<'conn>,
+}
+
+impl<'conn> ChainStatements<'conn> {
+    fn prepare(conn: &'conn Connection) -> Result<Self, QueryError> {
+        Ok(Self {
+            parent: conn.prepare(PARENT_PATH_SQL)?,
+            child: conn.prepare(CHILD_PATH_SQL)?,
+            tail: conn.prepare(TAIL_MESSAGES_SQL)?,
+            head: conn.prepare(HEAD_MESSAGES_SQL)?,
+        })
+    }
+
+    /// The first file of the chain `path` belongs to. Stops at a cycle.
+    fn root(&mut self, path: &str) -> Result<String, QueryError> {
+        let mut current = path.to_string();
+        let mut seen = HashSet::new();
+        while seen.insert(current.clone()) {
+            let parent: Option<String> = self
+                .parent
+                .query_row(params![&current], |row| row.get(0))
+                .optional()?
+                .flatten();
+            match parent {
+                Some(parent) => current = parent,
+                None => break,
+            }
+        }
+        Ok(current)
+    }
+
+    /// Top up `context` (loaded from `path` alone) with messages from the
+    /// previous and next file of the chain when the hit sits within
+    /// `around` turns of a file boundary.
+    fn extend_context(
+        &mut self,
+        context: &mut Vec<MessageContext>,
+        path: &str,
+        turn_index: i64,
+        around: usize,
+    ) -> Result<(), QueryError> {
+        let around = around as i64;
+
+        let missing_before = around - turn_index;
+        if missing_before > 0
+            && let Some(parent) = self
+                .parent
+                .query_row(params![path], |row| row.get::<_, Option<String>>(0))
+                .optional()?
+                .flatten()
+        {
+            let mut before = load_chain_messages(&mut self.tail, &parent, missing_before)?;
+            before.reverse();
+            before.append(context);
+            *context = before;
+        }
+
+        let after = context
+            .iter()
+            .filter(|message| message.path.is_none() && message.turn_index > turn_index)
+            .count() as i64;
+        let missing_after = around - after;
+        if missing_after > 0
+            && let Some(child) = self
+                .child
+                .query_row(params![path], |row| row.get::<_, String>(0))
+                .optional()?
+        {
+            context.extend(load_chain_messages(&mut self.head, &child, missing_after)?);
+        }
+
+        Ok(())
+    }
+}
+
+fn load_chain_messages(
+    stmt: &mut Statement<'_>,
+    path: &str,
+    limit: i64,
+) -> Result<Vec<MessageContext>, QueryError> {
+    let rows = stmt.query_map(params![path, limit], |row| {
+        Ok(MessageContext {
+            path: Some(path.to_string()),
+            turn_index: row.get(0)?,
+            role: row.get(1)?,
+            timestamp: row.get(2)?,
+            text: row.get(3)?,
+        })
+    })?;
+    let mut messages = Vec::new();
+    for row in rows {
+        messages.push(row?);
+    }
+    Ok(messages)
+}
+
+/// Query terms that matched one message, read back from FTS5 `highlight`.
+fn load_matched_terms(
+    stmt: &mut Statement<'_>,
+    query: &str,
+    session_path: &str,
+    turn_index: i64,
+) -> Result<Vec<String>, QueryError> {
+    let mut terms: Vec<String> = Vec::new();
+    let mut rows = stmt.query(params![query, session_path, turn_index])?;
+    while let Some(row) = rows.next()? {
+        let highlighted: String = row.get(0)?;
+        for marked in highlighted.split('\u{1}').skip(1) {
+            let Some((term, _)) = marked.split_once('\u{2}') else {
+                continue;
+            };
+            let term = term.to_lowercase();
+            if !term.is_empty() && !terms.contains(&term) {
+                terms.push(term);
+            }
+        }
+    }
+    Ok(terms)
+}
+
+/// [`normalize_query`], with short literal words expanded to the trigrams
+/// containing them when the index uses the `trigram` tokenizer.
+fn match_expression(
+    conn: &Connection,
+    query: &str,
+    mode: QueryMode,
+    stopwords: &[&str],
+) -> Result<String, QueryError> {
+    let expression = normalize_query(query, mode, stopwords)?;
+    if mode != QueryMode::Literal || fts_tokenizer(conn)? != FtsTokenizer::Trigram {
+        return Ok(expression);
+    }
+
+    let tokens = literal_tokens(query.trim(), stopwords);
+    if tokens.iter().all(|token| token.chars().count() >= 3) {
+        return Ok(expression);
+    }
+    conn.execute_batch(CREATE_VOCAB_SQL)?;
+    let mut stmt = conn.prepare_cached(TRIGRAMS_CONTAINING_SQL)?;
+    let mut parts = Vec::new();
+    for token in tokens {
+        let quoted = quote_term(token);
+        if token.chars().count() >= 3 {
+            parts.push(quoted);
+            continue;
+        }
+        let trigrams = stmt
+            .query_map(
+                params![token.to_lowercase(), MAX_TRIGRAM_EXPANSION],
+                |row| row.get::<_, String>(0),
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        if trigrams.is_empty() {
+            parts.push(quoted);
+        } else {
+            let alternatives: Vec<String> = trigrams.iter().map(|term| quote_term(term)).collect();
+            parts.push(format!("({})", alternatives.join(" OR ")));
+        }
+    }
+    // FTS5 only joins bare phrases implicitly; groups need an explicit AND.
+    Ok(parts.join(" AND "))
+}
+
+fn normalize_query(query: &str, mode: QueryMode, stopwords: &[&str]) -> Result<String, QueryError> {
     let query = query.trim();
     if query.is_empty() {
         return Err(QueryError::EmptyQuery);
     }
     match mode {
-        QueryMode::Literal => Ok(build_literal_query(query)),
+        QueryMode::Literal => Ok(build_literal_query(query, stopwords)),
         QueryMode::Fts => Ok(query.to_string()),
+        QueryMode::Near(distance) => Ok(build_near_query(query, stopwords, distance)),
+    }
+}
+
+fn build_near_query(query: &str, stopwords: &[&str], distance: usize) -> String {
+    let tokens = literal_tokens(query, stopwords);
+    if tokens.len() < 2 || is_quoted_phrase(query) {
+        return build_literal_query(query, stopwords);
     }
+    let terms: Vec<String> = tokens.iter().map(|token| quote_term(token)).collect();
+    format!("NEAR({}, {distance})", terms.join(" "))
 }
 
-fn build_literal_query(query: &str) -> String {
-    query
-        .split_whitespace()
-        .filter(|token| !token.is_empty())
-        .map(|token| format!("\"{}\"", token.replace('"', "\"\"")))
+fn build_literal_query(query: &str, stopwords: &[&str]) -> String {
+    literal_tokens(query, stopwords)
+        .iter()
+        .map(|token| quote_term(token))
         .collect::<Vec<_>>()
         .join(" ")
 }
 
+fn quote_term(term: &str) -> String {
+    format!("\"{}\"", term.replace('"', "\"\""))
+}
+
+fn is_quoted_phrase(query: &str) -> bool {
+    query.len() > 1 && query.starts_with('"') && query.ends_with('"')
+}
+
+/// The words a literal query matches on: whitespace-separated tokens minus
+/// stopwords, unless the query is one quoted phrase or nothing but stopwords.
+fn literal_tokens<'q>(query: &'q str, stopwords: &[&str]) -> Vec<&'q str> {
+    let tokens: Vec<&str> = query.split_whitespace().collect();
+    let kept: Vec<&str> = if is_quoted_phrase(query) {
+        tokens.clone()
+    } else {
+        tokens
+            .iter()
+            .copied()
+            .filter(|token| {
+                !stopwords
+                    .iter()
+                    .any(|stop| stop.eq_ignore_ascii_case(token))
+            })
+            .collect()
+    };
+    // A query of nothing but stopwords is still a query.
+    if kept.is_empty() { tokens } else { kept }
+}
+
 fn normalize_limit(limit: usize) -> i64 {
     (if limit == 0 { 5 } else { limit }) as i64
 }
@@ -311,15 +1316,65 @@ mod tests {
 
     #[test]
     fn literal_query_quotes_tokens() {
-        let query = normalize_query("quickdiff 2025-12-27", QueryMode::Literal)
-            .expect("literal query");
-        assert_eq!(query, "\"quickdiff\" \"2025-12-27\"");
+        assert_eq!(
+            normalize_query("quickdiff 2025-12-27", QueryMode::Literal, &[])
+                .ok()
+                .as_deref(),
+            Some("\"quickdiff\" \"2025-12-27\"")
+        );
+    }
+
+    #[test]
+    fn literal_query_drops_stopwords() {
+        assert_eq!(
+            normalize_query("the Rust borrow checker", QueryMode::Literal, &["the"])
+                .ok()
+                .as_deref(),
+            Some("\"Rust\" \"borrow\" \"checker\"")
+        );
+    }
+
+    #[test]
+    fn quoted_or_all_stopword_queries_keep_every_token() {
+        assert_eq!(
+            normalize_query("the and", QueryMode::Literal, &["the", "and"])
+                .ok()
+                .as_deref(),
+            Some("\"the\" \"and\"")
+        );
+
+        assert_eq!(
+            normalize_query("\"the end\"", QueryMode::Literal, &["the"])
+                .ok()
+                .as_deref(),
+            Some("\"\"\"the\" \"end\"\"\"")
+        );
+    }
+
+    #[test]
+    fn near_query_groups_words_and_falls_back_for_one_word() {
+        assert_eq!(
+            normalize_query("borrow the checker", QueryMode::Near(3), &["the"])
+                .ok()
+                .as_deref(),
+            Some("NEAR(\"borrow\" \"checker\", 3)")
+        );
+
+        assert_eq!(
+            normalize_query("borrow", QueryMode::Near(3), &[])
+                .ok()
+                .as_deref(),
+            Some("\"borrow\"")
+        );
     }
 
     #[test]
     fn fts_query_keeps_raw_input() {
-        let query = normalize_query("title:rust AND async", QueryMode::Fts)
-            .expect("fts query");
-        assert_eq!(query, "title:rust AND async");
+        assert_eq!(
+            normalize_query("title:rust AND async", QueryMode::Fts, &["and"])
+                .ok()
+                .as_deref(),
+            Some("title:rust AND async")
+        );
     }
 }
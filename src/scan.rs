@@ -7,31 +7,88 @@
 //! # Key Functions
 //!
 //! - [`index_root`]: Main entry point for indexing a sessions directory
+//! - [`index_root_with`]: Same, with [`IndexOptions`] (parser overrides, etc.)
+//! - [`import_file`]: Index one file in a custom format via a [`ParseSpec`]
 //!
 //! # Incremental Indexing
 //!
 //! Files are re-indexed only when mtime or size changes. Use `--full` to
 //! force a complete reindex.
 //!
+//! A file whose mtime or size changed is read and hashed first; if the
+//! hash matches the stored one it is skipped and its new mtime/size are
+//! recorded. [`IndexOptions::verify_hash`] hashes unchanged-looking files
+//! too, catching edits that kept both mtime and size.
+//!
+//! Sessions are keyed by absolute path: the root is canonicalized before the
+//! walk, so a relative `--root` stores the same paths as an absolute one.
+//!
+//! # Compressed Sessions
+//!
+//! Archived sessions such as `session.jsonl.zst` or `session.jsonl.gz` are
+//! decompressed as they are read (see [`crate::compress`]) and parsed by
+//! their inner extension; `.jsonl` archives stream straight into the
+//! line parser, hashed in the same pass. The path, mtime, size, and hash stored for skip logic are the
+//! compressed file's.
+//!
+//! # Rename Detection
+//!
+//! Each indexed file stores a content hash. When a new path hashes the same
+//! as an indexed session whose file no longer exists, the session row is
+//! moved to the new path in place instead of being removed and re-added.
+//!
+//! # System Prompts
+//!
+//! The first system/developer message is usually a large prompt repeated
+//! across sessions. Unless [`IndexOptions::index_system`] is set it is kept
+//! out of message search and stored in `sessions.system_prompt`.
+//!
+//! # Interruption
+//!
+//! [`IndexOptions::interrupt`] is checked between files. Once it is set the
+//! scan stops, skips pruning (unvisited files are not missing), commits the
+//! sessions indexed so far, and reports [`ScanStats::interrupted`]. How the
+//! flag gets set (e.g. a SIGINT handler) is up to the caller.
+//!
+//! # Pruning
+//!
+//! Sessions under the root whose files were not found by the walk are
+//! removed. Sessions outside it are left alone, so indexing a subdirectory
+//! reconciles only that subtree. [`IndexOptions::no_remove`] skips this, so a partial root or an
+//! unavailable mount indexes additively.
+//!
+//! # Dry Runs
+//!
+//! With [`IndexOptions::dry_run`] the scan walks and parses exactly as it
+//! would otherwise but writes nothing; [`ScanStats::plan`] lists the
+//! decision taken for every file (see [`PlannedFile`]).
+//!
 //! # Parse Failure Handling
 //!
-//! If a previously-indexed file fails to parse, its stale data is removed
-//! from the index to prevent returning outdated results.
+//! Unreadable archives, non-UTF-8 files, and malformed content all count as
+//! parse errors. If a previously-indexed file fails to parse, its stale data
+//! is removed from the index to prevent returning outdated results.
 //!
 //! # Git Integration
 //!
 //! Extracts `repo_root`, `repo_name`, and `branch` from the workspace directory
 //! using git commands. Results are cached per-workspace during a scan.
 
+use crate::compress::{SessionFormat, session_format};
 use crate::index::{
-    load_indexed_sessions, remove_session_tx, replace_messages_tx, upsert_session_tx,
+    IndexedSession, load_indexed_sessions, remove_session_tx, rename_session_tx,
+    replace_messages_tx, touch_session_tx, upsert_session_tx,
 };
 use crate::model::{MessageRecord, ParsedSession};
-use crate::parse::{parse_json, parse_jsonl, parse_markdown};
-use rusqlite::Connection;
+use crate::parse::{ParseError, ParseOptions, ParseSpec, parse_reader, parse_with_spec};
+use crate::util::{HashingReader, content_hash, hash_file};
+use rusqlite::{Connection, Transaction};
 use std::collections::{HashMap, HashSet};
+use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::UNIX_EPOCH;
 use walkdir::WalkDir;
 
@@ -41,15 +98,99 @@ pub struct ScanStats {
     pub indexed: usize,
     pub skipped: usize,
     pub removed: usize,
+    pub renamed: usize,
     pub parse_errors: usize,
+    /// Malformed JSONL lines skipped under [`ParseOptions::lenient`].
+    pub skipped_lines: usize,
+    /// The scan stopped early via [`IndexOptions::interrupt`]; the counts
+    /// cover only the files visited before that.
+    pub interrupted: bool,
+    /// Pruning was switched off with [`IndexOptions::no_remove`], so
+    /// `removed` counts only files that stopped parsing.
+    pub removal_skipped: bool,
+    /// Per-file decisions, collected only under [`IndexOptions::dry_run`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plan: Option<Vec<PlannedFile>>,
+}
+
+/// What a scan does (or, in a dry run, would do) with one file.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct PlannedFile {
+    pub path: String,
+    pub action: FileAction,
+    /// Why, e.g. `new`, `modified`, `unchanged`, or the parse error.
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileAction {
+    Index,
+    Skip,
+    Remove,
+    Error,
+}
+
+impl FileAction {
+    /// The lowercase name used in JSON and text output.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Index => "index",
+            Self::Skip => "skip",
+            Self::Remove => "remove",
+            Self::Error => "error",
+        }
+    }
+}
+
+/// Options controlling a single [`index_root_with`] run.
+#[derive(Debug, Clone, Default)]
+pub struct IndexOptions {
+    /// Re-index every file, ignoring the mtime/size cache.
+    pub full: bool,
+    /// Parser configuration applied to every session file.
+    pub parse: ParseOptions,
+    /// Keep the leading system/developer prompt in message search. By
+    /// default it is stored in `sessions.system_prompt` instead.
+    pub index_system: bool,
+    /// Store the first N characters of session content in
+    /// `sessions.preview`. `None` stores no preview.
+    pub preview_chars: Option<usize>,
+    /// Stop after the current file once this flag is set, committing the
+    /// progress so far.
+    pub interrupt: Option<Arc<AtomicBool>>,
+    /// Decide what to do with every file but leave the index untouched,
+    /// reporting the decisions in [`ScanStats::plan`].
+    pub dry_run: bool,
+    /// Hash every file, even when its mtime and size are unchanged, and
+    /// re-index it if the content hash differs from the stored one.
+    pub verify_hash: bool,
+    /// Keep sessions whose files are missing instead of pruning them, for
+    /// roots that are partial or may be temporarily unavailable.
+    pub no_remove: bool,
+}
+
+impl IndexOptions {
+    fn interrupted(&self) -> bool {
+        self.interrupt
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Relaxed))
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum ScanError {
+    #[error("sessions root {path} does not exist — create it or pass --root")]
+    RootNotFound { path: PathBuf },
     #[error("io error: {source}")]
     Io { source: std::io::Error },
     #[error("walk error: {source}")]
     Walk { source: walkdir::Error },
+    #[error("failed to parse {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: crate::parse::ParseError,
+    },
     #[error("system time error for {path}: {source}")]
     Time {
         path: PathBuf,
@@ -93,33 +234,56 @@ struct RepoInfo {
 }
 
 pub fn index_root(conn: &mut Connection, root: &Path, full: bool) -> Result<ScanStats, ScanError> {
-    let mut stats = ScanStats::default();
+    let options = IndexOptions {
+        full,
+        ..Default::default()
+    };
+    index_root_with(conn, root, &options)
+}
 
-    let existing = load_indexed_sessions(conn)?;
-    let mut existing_map = HashMap::new();
-    for entry in existing {
-        existing_map.insert(entry.path, (entry.mtime, entry.size));
+pub fn index_root_with(
+    conn: &mut Connection,
+    root: &Path,
+    options: &IndexOptions,
+) -> Result<ScanStats, ScanError> {
+    if !root.is_dir() {
+        return Err(ScanError::RootNotFound {
+            path: root.to_path_buf(),
+        });
     }
+    // Sessions are keyed by path, so store the same absolute path however
+    // the root was spelled (e.g. a relative `--root`).
+    let root = &std::fs::canonicalize(root)?;
+
+    let mut stats = ScanStats {
+        plan: options.dry_run.then(Vec::new),
+        ..Default::default()
+    };
+
+    let mut existing_map: HashMap<String, IndexedSession> = load_indexed_sessions(conn)?
+        .into_iter()
+        .map(|entry| (entry.path.clone(), entry))
+        .collect();
 
     let mut seen = HashSet::new();
     let mut repo_cache: HashMap<PathBuf, RepoInfo> = HashMap::new();
     let tx = conn.transaction()?;
 
     for entry in WalkDir::new(root) {
+        if options.interrupted() {
+            stats.interrupted = true;
+            break;
+        }
+
         let entry = entry?;
         if !entry.file_type().is_file() {
             continue;
         }
 
-        let Some(ext) = entry.path().extension().and_then(|ext| ext.to_str()) else {
+        let Some(format) = session_format(entry.path()) else {
             continue;
         };
 
-        let ext = ext.to_ascii_lowercase();
-        if !matches!(ext.as_str(), "jsonl" | "json" | "md") {
-            continue;
-        }
-
         stats.scanned += 1;
 
         let path = entry.path().to_path_buf();
@@ -129,78 +293,379 @@ pub fn index_root(conn: &mut Connection, root: &Path, full: bool) -> Result<Scan
         let metadata = entry.metadata()?;
         let mtime = modified_to_unix(&path, &metadata)?;
         let size = metadata.len() as i64;
+        let stat = (mtime, size);
+        let previous = existing_map.get(&path_str);
 
-        if !full
-            && let Some((prev_mtime, prev_size)) = existing_map.get(&path_str)
-            && *prev_mtime == mtime
-            && *prev_size == size
-        {
+        let stat_unchanged = previous.is_some_and(|prev| prev.mtime == mtime && prev.size == size);
+        if let Some(reason) = unchanged_reason(&path, previous, stat_unchanged, options)? {
             stats.skipped += 1;
+            stats.record(&path_str, FileAction::Skip, reason);
             continue;
         }
 
-        let contents = std::fs::read_to_string(&path)?;
-        let parsed = match parse_by_extension(&ext, &contents) {
+        let (hash, parsed) = parse_hashed(&path, &format, &options.parse)?;
+
+        if !options.full
+            && let Some(previous) = previous
+            && previous.hash.as_deref() == Some(hash.as_str())
+        {
+            let moved = (!stat_unchanged).then_some(stat);
+            skip_same_content(&tx, &path_str, moved, options, &mut stats)?;
+            continue;
+        }
+
+        if previous.is_none()
+            && let Some(old_path) = find_renamed_source(&existing_map, &seen, &hash)
+        {
+            move_renamed_session(&tx, &old_path, &path_str, stat, options, &mut stats)?;
+            existing_map.remove(&old_path);
+            continue;
+        }
+
+        let parsed = match parsed {
             Ok(parsed) => parsed,
-            Err(_) => {
-                // Remove stale data if file was previously indexed but now fails to parse
-                if existing_map.contains_key(&path_str) {
-                    remove_session_tx(&tx, &path_str)?;
-                    stats.removed += 1;
-                }
-                stats.parse_errors += 1;
+            Err(err) => {
+                let indexed = previous.is_some();
+                record_parse_failure(&tx, &path_str, &err, indexed, options, &mut stats)?;
                 continue;
             }
         };
 
-        let (mut record, messages) = parsed.into_parts(path_str, mtime, size, None);
-        if record.agent.is_none() {
-            record.agent = infer_agent_from_root(root);
-        }
-        let workspace_path = workspace_path_from_meta(record.workspace.as_deref())
-            .or_else(|| decode_workspace_from_session_path(&path));
-        let repo_info = infer_repo_info(workspace_path.as_deref(), &mut repo_cache);
-        record.repo_root = repo_info.repo_root;
-        record.repo_name = repo_info.repo_name;
-        record.branch = repo_info.branch;
-
-        let message_records: Vec<MessageRecord> = messages
-            .into_iter()
-            .enumerate()
-            .map(|(idx, message)| MessageRecord {
-                turn_index: idx as i64,
-                role: message.role,
-                timestamp: message.timestamp,
-                text: message.text,
-            })
-            .collect();
-
-        upsert_session_tx(&tx, &record)?;
-        replace_messages_tx(&tx, &record.path, &message_records)?;
+        stats.skipped_lines += parsed.skipped_lines;
+        let reason = reindex_reason(previous.is_some(), options.full);
+        stats.record(&path_str, FileAction::Index, reason);
         stats.indexed += 1;
+        if options.dry_run {
+            continue;
+        }
+        let source = SourceFile {
+            path: &path,
+            mtime,
+            size,
+            hash,
+        };
+        store_parsed_session(
+            &tx,
+            parsed,
+            source,
+            infer_agent_from_root(root),
+            options,
+            &mut repo_cache,
+        )?;
     }
 
-    for (path, _) in existing_map {
-        if !seen.contains(&path) {
-            remove_session_tx(&tx, &path)?;
-            stats.removed += 1;
-        }
+    // An interrupted walk never visited the remaining files, so they only
+    // look vanished.
+    stats.removal_skipped = options.no_remove;
+    if !stats.interrupted && !options.no_remove {
+        prune_missing(&tx, root, &existing_map, &seen, options, &mut stats)?;
     }
 
     tx.commit()?;
     Ok(stats)
 }
 
-fn parse_by_extension(
-    ext: &str,
-    contents: &str,
-) -> Result<ParsedSession, crate::parse::ParseError> {
-    match ext {
-        "jsonl" => parse_jsonl(contents),
-        "json" => parse_json(contents),
-        "md" => Ok(parse_markdown(contents)),
-        _ => Ok(ParsedSession::empty()),
+/// Why a file can be skipped without parsing, if it can: its mtime and size
+/// are unchanged and, under [`IndexOptions::verify_hash`], so is its content.
+fn unchanged_reason(
+    path: &Path,
+    previous: Option<&IndexedSession>,
+    stat_unchanged: bool,
+    options: &IndexOptions,
+) -> Result<Option<&'static str>, ScanError> {
+    if !stat_unchanged || options.full {
+        return Ok(None);
+    }
+    if !options.verify_hash {
+        return Ok(Some("unchanged"));
+    }
+    // Such files rarely changed, so hash them alone before paying for a parse.
+    let stored = previous.and_then(|prev| prev.hash.as_deref());
+    Ok((stored == Some(hash_file(path)?.as_str())).then_some("content unchanged"))
+}
+
+/// Parse `path` and hash its raw bytes in the same pass, so neither the
+/// compressed nor the decompressed file is held in memory. A touched or
+/// renamed file is then parsed for nothing, which is the rare case.
+fn parse_hashed(
+    path: &Path,
+    format: &SessionFormat,
+    options: &ParseOptions,
+) -> Result<(String, Result<ParsedSession, ParseError>), ScanError> {
+    let mut reader = HashingReader::new(File::open(path)?);
+    let parsed = parse_reader(&mut reader, format, options);
+    Ok((reader.finish()?, parsed))
+}
+
+/// Skip a file whose content hash matches the stored one. Rewritten mtimes
+/// (network filesystems, `git checkout`) do not mean new content, so a
+/// changed `(mtime, size)` is only recorded.
+fn skip_same_content(
+    tx: &Transaction<'_>,
+    path: &str,
+    moved: Option<(i64, i64)>,
+    options: &IndexOptions,
+    stats: &mut ScanStats,
+) -> Result<(), ScanError> {
+    if let Some((mtime, size)) = moved
+        && !options.dry_run
+    {
+        touch_session_tx(tx, path, mtime, size)?;
     }
+    stats.skipped += 1;
+    stats.record(path, FileAction::Skip, "content unchanged");
+    Ok(())
+}
+
+/// Point the session indexed at `old_path` to its new `path` instead of
+/// re-parsing it under a new ID.
+fn move_renamed_session(
+    tx: &Transaction<'_>,
+    old_path: &str,
+    path: &str,
+    (mtime, size): (i64, i64),
+    options: &IndexOptions,
+    stats: &mut ScanStats,
+) -> Result<(), ScanError> {
+    if !options.dry_run {
+        rename_session_tx(tx, old_path, path, mtime, size)?;
+    }
+    stats.record(path, FileAction::Index, format!("renamed from {old_path}"));
+    stats.renamed += 1;
+    Ok(())
+}
+
+/// Why a file that passed the skip check is (re)indexed.
+fn reindex_reason(indexed: bool, full: bool) -> &'static str {
+    match (indexed, full) {
+        (false, _) => "new",
+        (true, true) => "full reindex",
+        (true, false) => "modified",
+    }
+}
+
+/// Count a file that failed to parse. If it was previously indexed, its
+/// stale data is removed rather than left to match outdated results.
+fn record_parse_failure(
+    tx: &Transaction<'_>,
+    path: &str,
+    err: &crate::parse::ParseError,
+    indexed: bool,
+    options: &IndexOptions,
+    stats: &mut ScanStats,
+) -> Result<(), ScanError> {
+    let mut reason = err.to_string();
+    if indexed {
+        if !options.dry_run {
+            remove_session_tx(tx, path)?;
+        }
+        reason.push_str("; indexed session removed");
+        stats.removed += 1;
+    }
+    stats.record(path, FileAction::Error, reason);
+    stats.parse_errors += 1;
+    Ok(())
+}
+
+/// Remove indexed sessions under `root` whose files the walk did not see.
+fn prune_missing(
+    tx: &Transaction<'_>,
+    root: &Path,
+    existing: &HashMap<String, IndexedSession>,
+    seen: &HashSet<String>,
+    options: &IndexOptions,
+    stats: &mut ScanStats,
+) -> Result<(), ScanError> {
+    for path in existing.keys() {
+        // Sessions outside this root (e.g. `mmem import`) are not ours to
+        // prune. A relative path left by an older index is superseded by
+        // the absolute one just stored for the same file.
+        let under_root = Path::new(path).starts_with(root)
+            || (Path::new(path).is_relative()
+                && std::fs::canonicalize(path).is_ok_and(|file| file.starts_with(root)));
+        if seen.contains(path) || !under_root {
+            continue;
+        }
+        if !options.dry_run {
+            remove_session_tx(tx, path)?;
+        }
+        let reason = if Path::new(path).is_relative() {
+            "superseded by absolute path"
+        } else {
+            "file no longer exists"
+        };
+        stats.record(path, FileAction::Remove, reason);
+        stats.removed += 1;
+    }
+    Ok(())
+}
+
+impl ScanStats {
+    /// Record a decision when collecting a dry-run plan.
+    fn record(&mut self, path: &str, action: FileAction, reason: impl Into<String>) {
+        if let Some(plan) = self.plan.as_mut() {
+            plan.push(PlannedFile {
+                path: path.to_string(),
+                action,
+                reason: reason.into(),
+            });
+        }
+    }
+}
+
+/// Parse one file with a mapping spec and index it like a scanned session.
+///
+/// The session is keyed by the file's canonical path. Re-importing the same
+/// file replaces the previous import.
+pub fn import_file(
+    conn: &mut Connection,
+    path: &Path,
+    spec: &ParseSpec,
+    options: &IndexOptions,
+) -> Result<(), ScanError> {
+    let path = std::fs::canonicalize(path)?;
+    let metadata = std::fs::metadata(&path)?;
+    let mtime = modified_to_unix(&path, &metadata)?;
+    let size = metadata.len() as i64;
+
+    let bytes = std::fs::read(&path)?;
+    let hash = content_hash(&bytes);
+    let contents = String::from_utf8(bytes)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+    let parsed = parse_with_spec(&contents, spec).map_err(|source| ScanError::Parse {
+        path: path.clone(),
+        source,
+    })?;
+
+    let mut repo_cache = HashMap::new();
+    let tx = conn.transaction()?;
+    let source = SourceFile {
+        path: &path,
+        mtime,
+        size,
+        hash,
+    };
+    store_parsed_session(&tx, parsed, source, None, options, &mut repo_cache)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Resolve a `continues_from` reference next to the continuing file: an
+/// existing file (absolute, or relative to the file's directory), else the
+/// single sibling whose name starts with it (a session ID prefix).
+fn resolve_continuation(path: &Path, reference: &str) -> Option<String> {
+    let dir = path.parent()?;
+    let candidate = dir.join(reference);
+    if candidate != path && candidate.is_file() {
+        return Some(candidate.to_string_lossy().to_string());
+    }
+
+    let mut matches = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|sibling| {
+            sibling != path
+                && sibling.is_file()
+                && sibling
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(reference))
+        });
+    let parent = matches.next()?;
+    if matches.next().is_some() {
+        return None;
+    }
+    Some(parent.to_string_lossy().to_string())
+}
+
+/// File-level facts stored alongside a parsed session.
+struct SourceFile<'a> {
+    path: &'a Path,
+    mtime: i64,
+    size: i64,
+    hash: String,
+}
+
+/// Fill in inferred metadata and write a parsed session and its messages.
+fn store_parsed_session(
+    tx: &Transaction<'_>,
+    parsed: ParsedSession,
+    source: SourceFile<'_>,
+    fallback_agent: Option<String>,
+    options: &IndexOptions,
+    repo_cache: &mut HashMap<PathBuf, RepoInfo>,
+) -> Result<(), ScanError> {
+    let path_str = source.path.to_string_lossy().to_string();
+    let continues_from = parsed.continues_from.clone();
+    let (mut record, messages) =
+        parsed.into_parts(path_str, source.mtime, source.size, Some(source.hash));
+    let commands: Vec<&str> = messages
+        .iter()
+        .flat_map(|message| message.commands.iter().map(String::as_str))
+        .collect();
+    record.commands = (!commands.is_empty()).then(|| commands.join("\n"));
+    record.parent_path = continues_from
+        .as_deref()
+        .and_then(|reference| resolve_continuation(source.path, reference));
+    if record.agent.is_none() {
+        record.agent = fallback_agent;
+    }
+    if let Some(max_chars) = options.preview_chars {
+        record.preview = Some(record.content.chars().take(max_chars).collect());
+    }
+    let workspace_path = workspace_path_from_meta(record.workspace.as_deref())
+        .or_else(|| decode_workspace_from_session_path(source.path));
+    let repo_info = infer_repo_info(workspace_path.as_deref(), repo_cache);
+    record.repo_root = repo_info.repo_root;
+    record.repo_name = repo_info.repo_name;
+    record.branch = repo_info.branch;
+
+    let mut message_records: Vec<MessageRecord> = messages
+        .into_iter()
+        .enumerate()
+        .map(|(idx, message)| MessageRecord {
+            turn_index: idx as i64,
+            role: message.role,
+            timestamp: message.timestamp,
+            text: message.text,
+            tools: message.tools,
+            line: message.line.map(|line| line as i64),
+        })
+        .collect();
+    if !options.index_system {
+        record.system_prompt = take_system_prompt(&mut message_records);
+    }
+
+    upsert_session_tx(tx, &record)?;
+    replace_messages_tx(tx, &record.path, &message_records)?;
+    Ok(())
+}
+
+/// Find an indexed session whose file has vanished but whose content hash
+/// matches a newly discovered file, i.e. the file was moved or renamed.
+fn find_renamed_source(
+    existing: &HashMap<String, IndexedSession>,
+    seen: &HashSet<String>,
+    hash: &str,
+) -> Option<String> {
+    existing
+        .values()
+        .filter(|entry| entry.hash.as_deref() == Some(hash))
+        .filter(|entry| !seen.contains(&entry.path) && !Path::new(&entry.path).exists())
+        .map(|entry| entry.path.clone())
+        .min()
+}
+
+/// Remove the first system/developer message and return its text.
+///
+/// Turn indices of the remaining messages are left untouched so `--around`
+/// context and `show --turn` still line up with the session file.
+fn take_system_prompt(messages: &mut Vec<MessageRecord>) -> Option<String> {
+    let position = messages.iter().position(|message| {
+        matches!(message.role.as_deref(), Some("system") | Some("developer"))
+    })?;
+    Some(messages.remove(position).text)
 }
 
 fn modified_to_unix(path: &Path, metadata: &std::fs::Metadata) -> Result<i64, ScanError> {
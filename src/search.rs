@@ -0,0 +1,139 @@
+//! In-process search over an mmem database.
+//!
+//! [`Searcher`] owns a configured [`Connection`] with the schema in place,
+//! so embedding applications can query the index without repeating the
+//! setup the `mmem` binary does before every command.
+//!
+//! # Key Functions
+//!
+//! - [`Searcher::open_default`]: Open the database `mmem index` writes to
+//! - [`Searcher::open`] / [`Searcher::from_connection`]: Use another database
+//! - [`Searcher::search_sessions`]: Same results as `mmem find`
+//! - [`Searcher::search_messages`]: Same results as `mmem find --scope message`
+//! - [`default_db_path`]: Where the database lives by default
+//!
+//! # Example
+//!
+//! ```no_run
+//! use mmem::query::FindFilters;
+//! use mmem::search::Searcher;
+//!
+//! let searcher = Searcher::open_default()?;
+//! let filters = FindFilters {
+//!     limit: 5,
+//!     ..Default::default()
+//! };
+//! for hit in searcher.search_sessions("error handling", &filters)? {
+//!     println!("{} {}", hit.path, hit.title.unwrap_or_default());
+//! }
+//! # Ok::<(), mmem::search::SearchError>(())
+//! ```
+
+use crate::index::{IndexError, configure_connection, init_schema};
+use crate::model::{MessageHit, SessionHit};
+use crate::query::{FindFilters, QueryError, find_messages, find_sessions};
+use rusqlite::Connection;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SearchError {
+    #[error("failed to open {path}: {source}")]
+    Open {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("sqlite error: {source}")]
+    Sqlite { source: rusqlite::Error },
+    #[error("index error: {source}")]
+    Index { source: IndexError },
+    #[error("query error: {source}")]
+    Query { source: QueryError },
+}
+
+impl From<rusqlite::Error> for SearchError {
+    fn from(source: rusqlite::Error) -> Self {
+        Self::Sqlite { source }
+    }
+}
+
+impl From<IndexError> for SearchError {
+    fn from(source: IndexError) -> Self {
+        Self::Index { source }
+    }
+}
+
+impl From<QueryError> for SearchError {
+    fn from(source: QueryError) -> Self {
+        Self::Query { source }
+    }
+}
+
+/// `~/.config/marvin/mmem.sqlite`, the database `mmem index` writes to.
+pub fn default_db_path() -> PathBuf {
+    let home = std::env::var_os("HOME").unwrap_or_else(|| ".".into());
+    PathBuf::from(home).join(".config/marvin/mmem.sqlite")
+}
+
+/// A database connection ready for queries.
+#[derive(Debug)]
+pub struct Searcher {
+    conn: Connection,
+}
+
+impl Searcher {
+    /// Open (or create) the database at [`default_db_path`].
+    pub fn open_default() -> Result<Self, SearchError> {
+        Self::open(&default_db_path())
+    }
+
+    /// Open (or create) the database at `path`, creating its directory.
+    pub fn open(path: &Path) -> Result<Self, SearchError> {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent).map_err(|source| SearchError::Open {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        }
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Configure `conn` (see [`configure_connection`]) and create or migrate
+    /// the schema once, up front.
+    pub fn from_connection(conn: Connection) -> Result<Self, SearchError> {
+        configure_connection(&conn)?;
+        init_schema(&conn)?;
+        Ok(Self { conn })
+    }
+
+    /// The underlying connection, e.g. for [`crate::stats`] or
+    /// [`crate::query::distinct_values`].
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Mutable access, for writing through [`crate::scan`] or [`crate::index`].
+    pub fn connection_mut(&mut self) -> &mut Connection {
+        &mut self.conn
+    }
+
+    /// Search session content; see [`find_sessions`].
+    pub fn search_sessions(
+        &self,
+        query: &str,
+        filters: &FindFilters,
+    ) -> Result<Vec<SessionHit>, SearchError> {
+        Ok(find_sessions(&self.conn, query, filters)?)
+    }
+
+    /// Search individual messages; see [`find_messages`].
+    pub fn search_messages(
+        &self,
+        query: &str,
+        filters: &FindFilters,
+    ) -> Result<Vec<MessageHit>, SearchError> {
+        Ok(find_messages(&self.conn, query, filters)?)
+    }
+}
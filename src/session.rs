@@ -7,9 +7,17 @@
 //!
 //! - [`load_entry_by_turn`]: Load a specific message by turn index
 //! - [`load_entry_by_line`]: Load a specific line from a session file
+//! - [`load_entry_by_line_with`]: Same, optionally inheriting role/timestamp
+//!   from the nearest preceding message
 //! - [`scan_tool_calls`]: Find all tool calls in a session
+//! - [`scan_tool_calls_with`]: Same, with [`ToolScanOptions`] (per-tool caps,
+//!   paging)
 //! - [`extract_tool_calls`]: Extract tool calls from a JSON message
 //! - [`resolve_session_path`]: Resolve a session ID prefix to a file path
+//! - [`session_id_from_path`]: Derive the short session ID from a file path
+//!
+//! Tool calls with an `id` are paired with the `function_call_output` entry
+//! whose `call_id` matches, which fills in [`ToolCallMatch::output`].
 //!
 //! # Turn Index Semantics
 //!
@@ -17,9 +25,10 @@
 //! events, including toolCall-only entries with no text content.
 
 use crate::model::ParsedMessage;
-use crate::parse::{extract_content_array, extract_message};
+use crate::parse::{extract_content_array, extract_message, strip_bom, tool_output};
 use crate::util::expand_home;
 use serde_json::Value;
+use std::collections::HashMap;
 use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
@@ -35,6 +44,9 @@ pub struct SessionEntry {
 
 #[derive(Debug, Clone)]
 pub struct ToolCall {
+    /// The call's `id`, which a later `function_call_output` entry refers
+    /// to as its `call_id`.
+    pub id: Option<String>,
     pub name: String,
     pub arguments: Value,
 }
@@ -44,6 +56,21 @@ pub struct ToolCallMatch {
     pub line: usize,
     pub message_index: Option<usize>,
     pub tool: ToolCall,
+    /// Text of the call's result entry, when the session records one.
+    pub output: Option<String>,
+}
+
+/// Which tool calls [`scan_tool_calls_with`] returns, after name filtering.
+#[derive(Debug, Clone, Default)]
+pub struct ToolScanOptions {
+    /// Stop after this many calls.
+    pub limit: Option<usize>,
+    /// Keep at most this many calls of each tool name (case-insensitively),
+    /// for a balanced sample when one tool dominates.
+    pub per_tool_limit: Option<usize>,
+    /// Skip this many calls (counted after `per_tool_limit`) before
+    /// collecting, to page through a long listing.
+    pub offset: usize,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -83,7 +110,7 @@ pub fn load_entry_by_turn(path: &Path, turn: usize) -> Result<SessionEntry, Sess
     for (line_idx, line) in reader.lines().enumerate() {
         let line_no = line_idx + 1;
         let line = line?;
-        let line = line.trim();
+        let line = strip_bom(&line).trim();
         if line.is_empty() {
             continue;
         }
@@ -113,18 +140,43 @@ pub fn load_entry_by_turn(path: &Path, turn: usize) -> Result<SessionEntry, Sess
 }
 
 pub fn load_entry_by_line(path: &Path, line: usize) -> Result<SessionEntry, SessionError> {
+    load_entry_by_line_with(path, line, false)
+}
+
+/// [`load_entry_by_line`], optionally backfilling a missing role or
+/// timestamp from the nearest preceding message line.
+///
+/// Bare `session_meta` lines and tool envelopes carry neither; with
+/// `backfill` they report the context they appear in instead.
+pub fn load_entry_by_line_with(
+    path: &Path,
+    line: usize,
+    backfill: bool,
+) -> Result<SessionEntry, SessionError> {
     ensure_jsonl(path)?;
 
     let file = std::fs::File::open(path)?;
     let reader = BufReader::new(file);
+    let mut last_role = None;
+    let mut last_timestamp = None;
 
     for (line_idx, line_value) in reader.lines().enumerate() {
         let line_no = line_idx + 1;
         if line_no != line {
+            if backfill {
+                let line_value = line_value?;
+                let preceding = serde_json::from_str::<Value>(strip_bom(&line_value).trim())
+                    .ok()
+                    .and_then(|value| extract_message(&value));
+                if let Some(message) = preceding {
+                    last_role = message.role.or(last_role);
+                    last_timestamp = message.timestamp.or(last_timestamp);
+                }
+            }
             continue;
         }
         let line_value = line_value?;
-        let trimmed = line_value.trim();
+        let trimmed = strip_bom(&line_value).trim();
         if trimmed.is_empty() {
             return Err(SessionError::LineOutOfRange { line });
         }
@@ -136,16 +188,38 @@ pub fn load_entry_by_line(path: &Path, line: usize) -> Result<SessionEntry, Sess
             })?;
         let parsed = extract_message(&value);
         let message_index = None;
-        return Ok(build_entry(value, line_no, message_index, parsed));
+        let mut entry = build_entry(value, line_no, message_index, parsed);
+        if backfill {
+            entry.role = entry.role.or(last_role);
+            entry.timestamp = entry.timestamp.or(last_timestamp);
+        }
+        return Ok(entry);
     }
 
     Err(SessionError::LineOutOfRange { line })
 }
 
+/// Find tool calls in a session, in file order. An empty `tools` keeps every
+/// call; otherwise a call is kept when its name matches any entry
+/// (case-insensitively).
 pub fn scan_tool_calls(
     path: &Path,
-    tool: Option<&str>,
+    tools: &[&str],
     limit: Option<usize>,
+) -> Result<Vec<ToolCallMatch>, SessionError> {
+    let options = ToolScanOptions {
+        limit,
+        ..Default::default()
+    };
+    scan_tool_calls_with(path, tools, &options)
+}
+
+/// [`scan_tool_calls`] with per-tool caps and paging. Capped tools are
+/// skipped while scanning continues for the others.
+pub fn scan_tool_calls_with(
+    path: &Path,
+    tools: &[&str],
+    options: &ToolScanOptions,
 ) -> Result<Vec<ToolCallMatch>, SessionError> {
     ensure_jsonl(path)?;
 
@@ -153,12 +227,15 @@ pub fn scan_tool_calls(
     let reader = BufReader::new(file);
     let mut message_index = 0usize;
     let mut matches = Vec::new();
-    let max_matches = limit.unwrap_or(usize::MAX);
+    let max_matches = options.limit.unwrap_or(usize::MAX);
+    let per_tool_cap = options.per_tool_limit.unwrap_or(usize::MAX);
+    let mut per_tool: HashMap<String, usize> = HashMap::new();
+    let mut to_skip = options.offset;
 
-    for (line_idx, line) in reader.lines().enumerate() {
+    'lines: for (line_idx, line) in reader.lines().enumerate() {
         let line_no = line_idx + 1;
         let line = line?;
-        let line = line.trim();
+        let line = strip_bom(&line).trim();
         if line.is_empty() {
             continue;
         }
@@ -172,29 +249,84 @@ pub fn scan_tool_calls(
         let message_index_opt = parsed.as_ref().map(|_| message_index);
         let tool_calls = extract_tool_calls(&value);
         for tool_call in tool_calls {
-            if let Some(filter) = tool
-                && !tool_call.name.eq_ignore_ascii_case(filter)
+            if !tools.is_empty()
+                && !tools
+                    .iter()
+                    .any(|filter| tool_call.name.eq_ignore_ascii_case(filter))
             {
                 continue;
             }
+            let seen = per_tool.entry(tool_call.name.to_lowercase()).or_default();
+            if *seen >= per_tool_cap {
+                continue;
+            }
+            *seen += 1;
+            if to_skip > 0 {
+                to_skip -= 1;
+                continue;
+            }
             matches.push(ToolCallMatch {
                 line: line_no,
                 message_index: message_index_opt,
                 tool: tool_call,
+                output: None,
             });
             if matches.len() >= max_matches {
-                return Ok(matches);
+                break 'lines;
             }
         }
+        // With named tools, nothing more can match once each is capped.
+        if !tools.is_empty()
+            && tools.iter().all(|tool| {
+                per_tool
+                    .get(&tool.to_lowercase())
+                    .is_some_and(|seen| *seen >= per_tool_cap)
+            })
+        {
+            break;
+        }
 
         if parsed.is_some() {
             message_index += 1;
         }
     }
 
+    attach_tool_outputs(path, &mut matches)?;
     Ok(matches)
 }
 
+/// Fill in [`ToolCallMatch::output`] from the `function_call_output`
+/// entries whose `call_id` matches a call's `id`. Results follow their
+/// calls, so the file is read again from the first match on.
+fn attach_tool_outputs(path: &Path, matches: &mut [ToolCallMatch]) -> Result<(), SessionError> {
+    let Some(first_line) = matches
+        .iter()
+        .filter(|item| item.tool.id.is_some())
+        .map(|item| item.line)
+        .min()
+    else {
+        return Ok(());
+    };
+
+    let reader = BufReader::new(std::fs::File::open(path)?);
+    for line in reader.lines().skip(first_line) {
+        let line = line?;
+        let Ok(value) = serde_json::from_str::<Value>(strip_bom(&line).trim()) else {
+            continue;
+        };
+        let Some(output) = tool_output(&value) else {
+            continue;
+        };
+        if let Some(item) = matches.iter_mut().find(|item| {
+            item.output.is_none() && item.tool.id.is_some() && item.tool.id == output.call_id
+        }) {
+            item.output = Some(output.text);
+        }
+    }
+
+    Ok(())
+}
+
 pub fn extract_tool_calls(value: &Value) -> Vec<ToolCall> {
     let Some(content) = extract_content_array(value) else {
         return Vec::new();
@@ -215,7 +347,12 @@ pub fn extract_tool_calls(value: &Value) -> Vec<ToolCall> {
             .unwrap_or("unknown")
             .to_string();
         let arguments = item.get("arguments").cloned().unwrap_or(Value::Null);
-        tools.push(ToolCall { name, arguments });
+        let id = item.get("id").and_then(|v| v.as_str()).map(str::to_string);
+        tools.push(ToolCall {
+            id,
+            name,
+            arguments,
+        });
     }
 
     tools
@@ -240,8 +377,12 @@ fn build_entry(
     }
 }
 
-
-
+/// Resolve `input` to a session file: an existing path (`~` expanded), or a
+/// file name prefix such as a session ID.
+///
+/// A prefix is looked up in every directory under `root`, since Marvin keeps
+/// sessions in per-workspace subdirectories. Matches anywhere in the tree
+/// count toward [`SessionError::Ambiguous`].
 pub fn resolve_session_path(input: &str, root: &Path) -> Result<PathBuf, SessionError> {
     let expanded = expand_home(input);
     if expanded.exists() {
@@ -267,6 +408,22 @@ pub fn resolve_session_path(input: &str, root: &Path) -> Result<PathBuf, Session
     }
 }
 
+/// Derive the short session ID for a session file: the leading run of digits
+/// when an `_` follows it (Marvin's `<millis>_<slug>.jsonl`), otherwise the
+/// whole file stem. Other numeric prefixes, such as the year in
+/// `2024-01-01-notes.jsonl`, are not IDs and would match unrelated files.
+///
+/// The ID is always a prefix of the file name, so passing it back to
+/// [`resolve_session_path`] finds the same file.
+pub fn session_id_from_path(path: &str) -> Option<String> {
+    let stem = Path::new(path).file_stem()?.to_str()?;
+    let digits = stem.len() - stem.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits > 0 && stem[digits..].starts_with('_') {
+        return Some(stem[..digits].to_string());
+    }
+    (!stem.is_empty()).then(|| stem.to_string())
+}
+
 fn collect_session_matches(prefix: &str, root: &Path) -> Vec<PathBuf> {
     let mut matches = Vec::new();
     for entry in WalkDir::new(root).into_iter().filter_map(Result::ok) {
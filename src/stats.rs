@@ -4,8 +4,12 @@
 //!
 //! - [`load_stats`]: Get session count and date bounds
 //! - [`load_agents`]: List unique agents with session counts
+//! - [`load_tool_usage`]: Rank tool names by invocation count
+//! - [`load_repo_activity`]: Session and message counts per repository
+//! - [`db_footprint`]: On-disk size of the database and its WAL
 
 use rusqlite::Connection;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, serde::Serialize)]
 pub struct StatsReport {
@@ -13,6 +17,19 @@ pub struct StatsReport {
     pub oldest_message_at: Option<String>,
     pub newest_message_at: Option<String>,
     pub parse_failures: Option<i64>,
+    /// Filled in by callers that know the database file (see
+    /// [`db_footprint`]); a connection alone can't tell.
+    #[serde(flatten)]
+    pub footprint: Option<DbFootprint>,
+}
+
+/// Size of the database on disk.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct DbFootprint {
+    pub db_path: PathBuf,
+    pub db_size_bytes: u64,
+    /// Size of the `-wal` file, if one exists (WAL mode between checkpoints).
+    pub wal_size_bytes: Option<u64>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -39,6 +56,21 @@ pub fn load_stats(conn: &Connection) -> Result<StatsReport, StatsError> {
         oldest_message_at: oldest,
         newest_message_at: newest,
         parse_failures: None,
+        footprint: None,
+    })
+}
+
+/// Measure the database file at `db_path` and its write-ahead log.
+pub fn db_footprint(db_path: &Path) -> std::io::Result<DbFootprint> {
+    let db_size_bytes = std::fs::metadata(db_path)?.len();
+    let mut wal_path = db_path.as_os_str().to_owned();
+    wal_path.push("-wal");
+    let wal_size_bytes = std::fs::metadata(&wal_path).ok().map(|meta| meta.len());
+
+    Ok(DbFootprint {
+        db_path: db_path.to_path_buf(),
+        db_size_bytes,
+        wal_size_bytes,
     })
 }
 
@@ -69,3 +101,66 @@ pub fn load_agents(conn: &Connection) -> Result<Vec<AgentInfo>, StatsError> {
     }
     Ok(agents)
 }
+
+#[derive(Debug, serde::Serialize)]
+pub struct ToolUsage {
+    pub tool: String,
+    pub count: i64,
+}
+
+/// Tool names ranked by how often they were called across all sessions.
+pub fn load_tool_usage(conn: &Connection) -> Result<Vec<ToolUsage>, StatsError> {
+    let mut stmt = conn.prepare(
+        "SELECT name, COUNT(*) as count
+         FROM message_tools
+         GROUP BY name
+         ORDER BY count DESC, name ASC",
+    )?;
+
+    let rows = stmt.query_map([], |row| {
+        Ok(ToolUsage {
+            tool: row.get(0)?,
+            count: row.get(1)?,
+        })
+    })?;
+
+    let mut tools = Vec::new();
+    for row in rows {
+        tools.push(row?);
+    }
+    Ok(tools)
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct RepoActivity {
+    pub repo: String,
+    pub sessions: i64,
+    pub messages: i64,
+}
+
+/// Session and message counts per `repo_name`, most messages first.
+/// Sessions outside a git repository are grouped as `(unknown)`.
+pub fn load_repo_activity(conn: &Connection) -> Result<Vec<RepoActivity>, StatsError> {
+    let mut stmt = conn.prepare(
+        "SELECT COALESCE(repo_name, '(unknown)') as repo,
+                COUNT(*) as sessions,
+                COALESCE(SUM(message_count), 0) as messages
+         FROM sessions
+         GROUP BY repo_name
+         ORDER BY messages DESC, sessions DESC, repo ASC",
+    )?;
+
+    let rows = stmt.query_map([], |row| {
+        Ok(RepoActivity {
+            repo: row.get(0)?,
+            sessions: row.get(1)?,
+            messages: row.get(2)?,
+        })
+    })?;
+
+    let mut repos = Vec::new();
+    for row in rows {
+        repos.push(row?);
+    }
+    Ok(repos)
+}
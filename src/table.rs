@@ -0,0 +1,130 @@
+//! Aligned columnar output for `find --format table`.
+//!
+//! Columns start at the width of their widest cell and, when the row would
+//! overflow the terminal, the widest columns are narrowed first. Cells that
+//! no longer fit are cut with a trailing `…`.
+
+/// Width used when stdout is not a terminal and `COLUMNS` is unset.
+const FALLBACK_WIDTH: usize = 120;
+
+/// Columns never shrink below this, so short fields stay readable.
+const MIN_COLUMN_WIDTH: usize = 6;
+
+const COLUMN_GAP: &str = "  ";
+
+/// Terminal width for table layout: `COLUMNS` if set, else the size of the
+/// terminal attached to stdout, else [`FALLBACK_WIDTH`].
+pub fn terminal_width() -> usize {
+    if let Some(columns) = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .filter(|columns| *columns > 0)
+    {
+        return columns;
+    }
+    stdout_width().unwrap_or(FALLBACK_WIDTH)
+}
+
+fn stdout_width() -> Option<usize> {
+    terminal_size::terminal_size_of(std::io::stdout())
+        .map(|(terminal_size::Width(columns), _)| usize::from(columns))
+        .filter(|columns| *columns > 0)
+}
+
+/// Lay out `headers` and `rows` as aligned lines no wider than `width`
+/// (unless every column is already at its minimum).
+pub fn render_table(headers: &[String], rows: &[Vec<String>], width: usize) -> Vec<String> {
+    let mut widths: Vec<usize> = headers
+        .iter()
+        .map(|header| header.chars().count())
+        .collect();
+    for row in rows {
+        for (column, cell) in row.iter().enumerate() {
+            widths[column] = widths[column].max(cell.chars().count());
+        }
+    }
+    fit_widths(&mut widths, width);
+
+    std::iter::once(headers)
+        .chain(rows.iter().map(Vec::as_slice))
+        .map(|cells| render_row(cells, &widths))
+        .collect()
+}
+
+fn fit_widths(widths: &mut [usize], width: usize) {
+    let gaps = COLUMN_GAP.len() * widths.len().saturating_sub(1);
+    let available = width.saturating_sub(gaps);
+    let mut total: usize = widths.iter().sum();
+    while total > available {
+        let Some(widest) = (0..widths.len())
+            .filter(|column| widths[*column] > MIN_COLUMN_WIDTH)
+            .max_by_key(|column| (widths[*column], std::cmp::Reverse(*column)))
+        else {
+            break;
+        };
+        widths[widest] -= 1;
+        total -= 1;
+    }
+}
+
+fn render_row(cells: &[String], widths: &[usize]) -> String {
+    let mut line = String::new();
+    for (column, cell) in cells.iter().enumerate() {
+        if column > 0 {
+            line.push_str(COLUMN_GAP);
+        }
+        let width = widths[column];
+        let cell = truncate(cell, width);
+        line.push_str(&cell);
+        if column + 1 < cells.len() {
+            let pad = width.saturating_sub(cell.chars().count());
+            line.extend(std::iter::repeat_n(' ', pad));
+        }
+    }
+    line
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn aligns_columns_to_widest_cell() {
+        let headers = strings(&["ROLE", "TEXT"]);
+        let rows = vec![strings(&["user", "hi"]), strings(&["assistant", "hello"])];
+        let lines = render_table(&headers, &rows, 80);
+        assert_eq!(lines[0], "ROLE       TEXT");
+        assert_eq!(lines[1], "user       hi");
+        assert_eq!(lines[2], "assistant  hello");
+    }
+
+    #[test]
+    fn truncates_widest_column_to_fit() {
+        let headers = strings(&["ID", "TITLE"]);
+        let rows = vec![strings(&["42", "a very long session title indeed"])];
+        let lines = render_table(&headers, &rows, 20);
+        assert_eq!(lines[1], "42  a very long ses…");
+        assert!(lines.iter().all(|line| line.chars().count() <= 20));
+    }
+
+    #[test]
+    fn stops_shrinking_at_minimum_width() {
+        let headers = strings(&["PATH", "TITLE"]);
+        let rows = vec![strings(&["/tmp/sessions/1.jsonl", "debugging"])];
+        let lines = render_table(&headers, &rows, 4);
+        assert_eq!(lines[1], "/tmp/…  debug…");
+    }
+}
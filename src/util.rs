@@ -1,6 +1,11 @@
 //! Shared utility functions.
 
-use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use time::format_description::well_known::Rfc3339;
+use time::{Date, OffsetDateTime};
 
 /// Expand `~` and `~/...` paths to absolute paths using `$HOME`.
 ///
@@ -31,6 +36,118 @@ pub fn expand_home(path: &str) -> PathBuf {
     PathBuf::from(path)
 }
 
+/// SHA-256 of file contents, as lowercase hex.
+///
+/// Used to recognize unchanged content across renames and re-indexing, where
+/// a collision would keep stale content or move the wrong session, so the
+/// hash has to be collision-resistant. The value is persisted, so the
+/// algorithm must not change between releases; hashes stored by earlier
+/// builds never match and only cost one re-parse.
+pub fn content_hash(bytes: &[u8]) -> String {
+    let mut hasher = ContentHasher::default();
+    hasher.update(bytes);
+    hasher.finish()
+}
+
+/// [`content_hash`] computed incrementally, for content read in chunks.
+#[derive(Debug, Clone, Default)]
+pub struct ContentHasher(Sha256);
+
+impl ContentHasher {
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    pub fn finish(self) -> String {
+        self.0
+            .finalize()
+            .iter()
+            .fold(String::new(), |mut hex, byte| {
+                let _ = write!(hex, "{byte:02x}");
+                hex
+            })
+    }
+}
+
+/// A reader that feeds every byte it yields into a [`ContentHasher`], so a
+/// file can be hashed in the same pass that parses it.
+#[derive(Debug)]
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: ContentHasher,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: ContentHasher::default(),
+        }
+    }
+
+    /// Hash whatever the consumer left unread and return the
+    /// [`content_hash`] of the whole input.
+    pub fn finish(mut self) -> std::io::Result<String> {
+        std::io::copy(&mut self, &mut std::io::sink())?;
+        Ok(self.hasher.finish())
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// [`content_hash`] of a file, read in chunks.
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    HashingReader::new(std::fs::File::open(path)?).finish()
+}
+
+/// Parse a `--after`/`--before` bound: an RFC 3339 timestamp or a plain
+/// `YYYY-MM-DD` date (taken as midnight UTC). Returns `None` for anything
+/// else, since bounds are otherwise compared as plain strings.
+pub fn parse_date_bound(value: &str) -> Option<OffsetDateTime> {
+    let value = value.trim();
+    if let Ok(timestamp) = OffsetDateTime::parse(value, &Rfc3339) {
+        return Some(timestamp);
+    }
+    let format = time::format_description::parse("[year]-[month]-[day]").ok()?;
+    let date = Date::parse(value, &format).ok()?;
+    Some(date.midnight().assume_utc())
+}
+
+/// Rewrite a stored timestamp as RFC 3339 in UTC.
+///
+/// Accepts RFC 3339 with any offset, plain `YYYY-MM-DD` dates, and Unix
+/// epochs in seconds or milliseconds (integer or fractional, as
+/// transcripts store them). Returns `None` for anything else so callers
+/// can keep the raw value.
+pub fn normalize_timestamp(value: &str) -> Option<String> {
+    // Epochs past this many seconds (year 5138) are taken as milliseconds.
+    const MAX_EPOCH_SECONDS: f64 = 1e11;
+
+    let value = value.trim();
+    let timestamp = match value.parse::<f64>() {
+        Ok(epoch) if epoch.is_finite() => {
+            let seconds = if epoch.abs() >= MAX_EPOCH_SECONDS {
+                epoch / 1000.0
+            } else {
+                epoch
+            };
+            let nanos = (seconds * 1e9).round() as i128;
+            OffsetDateTime::from_unix_timestamp_nanos(nanos).ok()?
+        }
+        _ => parse_date_bound(value)?,
+    };
+    timestamp
+        .to_offset(time::UtcOffset::UTC)
+        .format(&Rfc3339)
+        .ok()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -57,8 +174,58 @@ mod tests {
         assert_eq!(expand_home("/tmp/file"), PathBuf::from("/tmp/file"));
     }
 
+    #[test]
+    fn content_hash_is_stable() {
+        assert_eq!(
+            content_hash(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            content_hash(b"a"),
+            "ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb"
+        );
+    }
+
+    #[test]
+    fn chunked_content_hash_matches_one_shot() {
+        let mut hasher = ContentHasher::default();
+        hasher.update(b"hello ");
+        hasher.update(b"world");
+        assert_eq!(hasher.finish(), content_hash(b"hello world"));
+
+        let mut reader = HashingReader::new(&b"hello world"[..]);
+        let mut head = [0; 5];
+        assert!(reader.read_exact(&mut head).is_ok());
+        assert_eq!(reader.finish().ok(), Some(content_hash(b"hello world")));
+    }
+
     #[test]
     fn preserves_relative_paths() {
         assert_eq!(expand_home("relative/path"), PathBuf::from("relative/path"));
     }
+
+    #[test]
+    fn parses_dates_and_timestamps_as_bounds() {
+        let date = parse_date_bound("2024-01-01");
+        assert!(date.is_some());
+        assert_eq!(date, parse_date_bound("2024-01-01T00:00:00Z"));
+        assert!(parse_date_bound("2024-01-01T12:00:00+02:00") > date);
+        assert!(parse_date_bound("last tuesday").is_none());
+    }
+
+    #[test]
+    fn normalizes_timestamps_to_utc_rfc3339() {
+        let cases = [
+            ("2024-01-01T12:00:00+02:00", "2024-01-01T10:00:00Z"),
+            ("2024-01-01T10:00:00Z", "2024-01-01T10:00:00Z"),
+            ("2024-01-01", "2024-01-01T00:00:00Z"),
+            ("1704103200", "2024-01-01T10:00:00Z"),
+            ("1704103200000", "2024-01-01T10:00:00Z"),
+            ("1704103200.5", "2024-01-01T10:00:00.5Z"),
+        ];
+        for (raw, expected) in cases {
+            assert_eq!(normalize_timestamp(raw).as_deref(), Some(expected), "{raw}");
+        }
+        assert!(normalize_timestamp("yesterday").is_none());
+    }
 }
@@ -0,0 +1,77 @@
+use mmem::compress::session_format;
+use std::path::Path;
+
+#[test]
+fn classifies_plain_session_files() {
+    let format = session_format(Path::new("/tmp/a.JSONL")).expect("jsonl");
+    assert_eq!(format.ext, "jsonl");
+    assert!(format.compression.is_none());
+
+    assert!(session_format(Path::new("/tmp/notes.txt")).is_none());
+    assert!(session_format(Path::new("/tmp/README")).is_none());
+}
+
+#[test]
+fn classifies_file_names_by_action() {
+    // (file name, expected parser extension; None means ignored)
+    let cases = [
+        ("session.jsonl", Some("jsonl")),
+        ("SESSION.JSONL", Some("jsonl")),
+        ("export.Json", Some("json")),
+        ("notes.md", Some("md")),
+        ("session.jsonl.bak", None),
+        ("session.jsonl~", None),
+        ("session.jsonl.tmp", None),
+        ("session.JSONL.BAK", None),
+        (".session.jsonl.swp", None),
+        (".session.jsonl.swo", None),
+        ("#session.jsonl#", None),
+        (".#session.jsonl", None),
+        ("session.txt", None),
+        ("session", None),
+    ];
+    for (name, expected) in cases {
+        let format = session_format(&Path::new("/tmp").join(name));
+        assert_eq!(
+            format.as_ref().map(|format| format.ext.as_str()),
+            expected,
+            "{name}"
+        );
+    }
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn looks_through_zstd_suffix() {
+    use mmem::compress::Compression;
+
+    let format = session_format(Path::new("/tmp/a.jsonl.zst")).expect("zst");
+    assert_eq!(format.ext, "jsonl");
+    assert_eq!(format.compression, Some(Compression::Zstd));
+
+    assert!(session_format(Path::new("/tmp/a.tar.zst")).is_none());
+}
+
+#[cfg(not(feature = "zstd"))]
+#[test]
+fn ignores_zstd_without_feature() {
+    assert!(session_format(Path::new("/tmp/a.jsonl.zst")).is_none());
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn looks_through_gzip_suffix() {
+    use mmem::compress::Compression;
+
+    let format = session_format(Path::new("/tmp/a.JSONL.GZ")).expect("gz");
+    assert_eq!(format.ext, "jsonl");
+    assert_eq!(format.compression, Some(Compression::Gzip));
+
+    assert!(session_format(Path::new("/tmp/a.tar.gz")).is_none());
+}
+
+#[cfg(not(feature = "gzip"))]
+#[test]
+fn ignores_gzip_without_feature() {
+    assert!(session_format(Path::new("/tmp/a.jsonl.gz")).is_none());
+}
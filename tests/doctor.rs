@@ -1,4 +1,6 @@
-use mmem::doctor::run_doctor;
+use mmem::doctor::{DoctorOptions, probe_file, run_doctor, run_doctor_with};
+use mmem::index::init_schema;
+use mmem::scan::index_root;
 
 #[test]
 fn doctor_reports_missing_db() {
@@ -12,3 +14,121 @@ fn doctor_reports_missing_db() {
     assert!(report.schema_error.is_none());
     assert_eq!(report.indexed_sessions, 0);
 }
+
+#[test]
+fn doctor_reports_sqlite_capabilities() {
+    let root = tempfile::tempdir().expect("root");
+    let report = run_doctor(&root.path().join("missing.sqlite"), root.path());
+
+    assert!(report.sqlite_version.starts_with("3."));
+    // The bundled SQLite is built with JSON1 and a trigram-capable FTS5.
+    assert!(report.json1_available);
+    assert!(report.trigram_available);
+}
+
+#[test]
+fn doctor_detects_and_repairs_orphaned_messages() {
+    let root = tempfile::tempdir().expect("root");
+    let db_path = root.path().join("mmem.sqlite");
+    let conn = rusqlite::Connection::open(&db_path).expect("db");
+    init_schema(&conn).expect("schema");
+    conn.execute(
+        "INSERT INTO messages (session_path, turn_index, role, timestamp, text)
+         VALUES ('/gone.jsonl', 0, 'user', NULL, 'orphan')",
+        [],
+    )
+    .expect("insert orphan");
+    drop(conn);
+
+    let report = run_doctor(&db_path, root.path());
+    assert!(report.schema_ok);
+    assert_eq!(report.orphaned_messages, 1);
+    assert_eq!(report.repaired_messages, 0);
+
+    let repaired = run_doctor_with(&db_path, root.path(), DoctorOptions { repair: true });
+    assert_eq!(repaired.orphaned_messages, 1);
+    assert_eq!(repaired.repaired_messages, 1);
+
+    let after = run_doctor(&db_path, root.path());
+    assert_eq!(after.orphaned_messages, 0);
+}
+
+#[test]
+fn probe_explains_indexed_and_missing_files() {
+    let root = tempfile::tempdir().expect("root");
+    let db_path = root.path().join("mmem.sqlite");
+    let session = root.path().join("a.jsonl");
+    std::fs::copy("tests/fixtures/session.jsonl", &session).expect("copy fixture");
+    let mut conn = rusqlite::Connection::open(&db_path).expect("db");
+    init_schema(&conn).expect("schema");
+    index_root(&mut conn, root.path(), false).expect("index");
+    drop(conn);
+
+    let report = probe_file(&db_path, root.path(), &session);
+    assert!(report.parsed);
+    assert!(report.indexed);
+    assert_eq!(report.message_count, Some(2));
+    assert_eq!(report.agent.as_deref(), Some("gpt-4"));
+    assert_eq!(report.stored_mtime, report.current_mtime);
+    assert!(report.reason_not_indexed.is_none());
+
+    let broken = root.path().join("b.jsonl");
+    std::fs::write(&broken, "{not json").expect("write broken");
+    let report = probe_file(&db_path, root.path(), &broken);
+    assert!(!report.parsed);
+    assert!(!report.indexed);
+    let reason = report.reason_not_indexed.as_deref().expect("reason");
+    assert!(reason.starts_with("parse error"), "{reason}");
+
+    let json = serde_json::to_value(&report).expect("json");
+    for key in [
+        "parsed",
+        "message_count",
+        "title",
+        "agent",
+        "indexed",
+        "stored_mtime",
+        "current_mtime",
+        "reason_not_indexed",
+    ] {
+        assert!(json.get(key).is_some(), "missing {key}");
+    }
+
+    let missing = probe_file(&db_path, root.path(), &root.path().join("gone.jsonl"));
+    assert_eq!(
+        missing.reason_not_indexed.as_deref(),
+        Some("file not found")
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn probe_follows_symlinks_to_the_indexed_path() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let sessions = dir.path().join("sessions");
+    std::fs::create_dir(&sessions).expect("sessions dir");
+    std::fs::copy("tests/fixtures/session.jsonl", sessions.join("a.jsonl")).expect("copy");
+    let link = dir.path().join("link");
+    std::os::unix::fs::symlink(&sessions, &link).expect("symlink");
+
+    let db_path = dir.path().join("mmem.sqlite");
+    let mut conn = rusqlite::Connection::open(&db_path).expect("db");
+    init_schema(&conn).expect("schema");
+    index_root(&mut conn, &link, false).expect("index");
+    drop(conn);
+
+    let report = probe_file(&db_path, &link, &link.join("a.jsonl"));
+    assert!(report.indexed, "{:?}", report.reason_not_indexed);
+    assert_eq!(
+        report.path,
+        std::fs::canonicalize(sessions.join("a.jsonl")).expect("real")
+    );
+
+    let unindexed = link.join("b.jsonl");
+    std::fs::copy("tests/fixtures/session.jsonl", &unindexed).expect("copy");
+    let report = probe_file(&db_path, &link, &unindexed);
+    assert_eq!(
+        report.reason_not_indexed.as_deref(),
+        Some("not indexed yet; run `mmem index`")
+    );
+}
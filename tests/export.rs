@@ -0,0 +1,100 @@
+use mmem::export::{
+    ExportError, export_path_in, session_to_markdown, session_to_transcript, write_export,
+};
+use mmem::model::ParsedSession;
+use mmem::parse::{parse_jsonl, parse_markdown};
+use std::collections::HashSet;
+
+fn roles_and_texts(session: &ParsedSession) -> Vec<(Option<String>, String)> {
+    session
+        .messages
+        .iter()
+        .filter(|message| !message.text.trim().is_empty())
+        .map(|message| (message.role.clone(), message.text.trim().to_string()))
+        .collect()
+}
+
+#[test]
+fn renders_session_as_markdown() {
+    let parsed = parse_jsonl(include_str!("fixtures/session.jsonl")).expect("parse");
+    let markdown = session_to_markdown(&parsed);
+
+    assert!(markdown.starts_with("# hello\n"));
+    assert!(markdown.contains("\n## user\n\nhello\n"));
+    assert!(markdown.contains("\n## assistant\n\nhi there\n"));
+}
+
+#[test]
+fn transcript_export_round_trips_through_markdown_parser() {
+    let parsed = parse_jsonl(include_str!("fixtures/session.jsonl")).expect("parse");
+    let transcript = session_to_transcript(&parsed);
+    assert_eq!(transcript, "user: hello\n\nassistant: hi there\n");
+
+    let reparsed = parse_markdown(&transcript);
+    assert_eq!(roles_and_texts(&reparsed), roles_and_texts(&parsed));
+    assert_eq!(reparsed.title, parsed.title);
+}
+
+#[test]
+fn transcript_export_keeps_multiline_messages_and_fences() {
+    let input = concat!(
+        r#"{"role":"user","content":"two paragraphs:\n\nsecond one\n  indented"}"#,
+        "\n",
+        r#"{"role":"assistant","content":"```rust\nuser: not a turn\n\nlet x = 1;\n```\ndone"}"#,
+        "\n",
+        r#"{"role":"user","content":"thanks"}"#,
+        "\n",
+    );
+    let parsed = parse_jsonl(input).expect("parse");
+    let reparsed = parse_markdown(&session_to_transcript(&parsed));
+
+    assert_eq!(roles_and_texts(&reparsed), roles_and_texts(&parsed));
+    assert_eq!(reparsed.messages.len(), 3);
+}
+
+#[test]
+fn refuses_to_overwrite_without_force() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let out = dir.path().join("notes.md");
+    std::fs::write(&out, "keep me").expect("write existing");
+
+    let err = write_export(&out, "new", false).expect_err("existing file");
+    assert!(matches!(err, ExportError::Exists { .. }));
+    assert_eq!(std::fs::read_to_string(&out).expect("read"), "keep me");
+
+    write_export(&out, "new", true).expect("forced write");
+    assert_eq!(std::fs::read_to_string(&out).expect("read"), "new");
+}
+
+#[test]
+fn creates_new_file_without_force() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let out = dir.path().join("fresh.md");
+
+    write_export(&out, "fresh", false).expect("write");
+    assert_eq!(std::fs::read_to_string(&out).expect("read"), "fresh");
+}
+
+#[test]
+fn names_batch_exports_after_titles_and_disambiguates_collisions() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let mut taken = HashSet::new();
+
+    let first = export_path_in(dir.path(), Some("Fix the  Build!\nmore"), "111", &taken);
+    assert_eq!(first, dir.path().join("fix-the-build.md"));
+    taken.insert(first);
+
+    let second = export_path_in(dir.path(), Some("fix the build"), "222", &taken);
+    assert_eq!(second, dir.path().join("fix-the-build-222.md"));
+
+    taken.insert(second);
+
+    let third = export_path_in(dir.path(), Some("Fix the build"), "222", &taken);
+    assert_eq!(third, dir.path().join("fix-the-build-222-2.md"));
+
+    let untitled = export_path_in(dir.path(), None, "333", &taken);
+    assert_eq!(untitled, dir.path().join("333.md"));
+    std::fs::write(&untitled, "existing").expect("write");
+    let clash = export_path_in(dir.path(), Some("!!!"), "333", &taken);
+    assert_eq!(clash, dir.path().join("333-2.md"));
+}
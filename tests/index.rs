@@ -1,5 +1,11 @@
-use mmem::index::{init_schema, remove_session, upsert_session};
-use mmem::model::SessionRecord;
+use mmem::index::{
+    FtsTokenizer, IndexError, configure_connection, dedup_session_content, fts_tokenizer,
+    init_schema, init_schema_with, messages_fts_is_external, open_read_only, remove_session,
+    rename_session_tx, replace_messages_tx, schema_is_current, upsert_session, upsert_session_tx,
+    use_external_message_fts,
+};
+use mmem::model::{MessageRecord, SessionRecord};
+use mmem::query::{FindFilters, find_messages};
 use rusqlite::{Connection, params};
 
 fn sample_record() -> SessionRecord {
@@ -14,11 +20,17 @@ fn sample_record() -> SessionRecord {
         workspace: Some("ws-test".to_string()),
         title: Some("hello".to_string()),
         message_count: 2,
+        user_message_count: 0,
+        assistant_message_count: 0,
         snippet: "hello".to_string(),
         content: "[user] hello\n[assistant] hi".to_string(),
         repo_root: None,
         repo_name: None,
         branch: None,
+        system_prompt: None,
+        preview: None,
+        parent_path: None,
+        commands: None,
     }
 }
 
@@ -79,3 +91,511 @@ fn indexes_and_removes_sessions() {
         .expect("remaining count");
     assert_eq!(remaining, 0);
 }
+
+#[test]
+fn migrates_sessions_table_missing_repo_columns() {
+    let conn = Connection::open_in_memory().expect("open memory db");
+    conn.execute_batch(
+        "CREATE TABLE sessions (
+            path TEXT PRIMARY KEY,
+            mtime INTEGER NOT NULL,
+            size INTEGER NOT NULL,
+            hash TEXT,
+            created_at TEXT,
+            last_message_at TEXT,
+            agent TEXT,
+            workspace TEXT,
+            title TEXT,
+            message_count INTEGER,
+            snippet TEXT
+        );",
+    )
+    .expect("legacy sessions table");
+
+    init_schema(&conn).expect("schema migrates");
+    conn.prepare("SELECT repo_root, repo_name, branch FROM sessions")
+        .expect("repo columns exist");
+}
+
+#[test]
+fn reports_outdated_messages_table() {
+    let conn = Connection::open_in_memory().expect("open memory db");
+    conn.execute_batch(
+        "CREATE TABLE messages (
+            id INTEGER PRIMARY KEY,
+            session_path TEXT NOT NULL,
+            turn_index INTEGER NOT NULL,
+            text TEXT
+        );",
+    )
+    .expect("legacy messages table");
+
+    let err = init_schema(&conn).expect_err("schema mismatch");
+    assert!(matches!(
+        err,
+        IndexError::SchemaMismatch { ref table, ref column }
+            if table == "messages" && column == "role"
+    ));
+    assert!(err.to_string().contains("mmem index --full"));
+}
+
+#[test]
+fn replaces_messages_and_keeps_them_searchable() {
+    let mut conn = Connection::open_in_memory().expect("open memory db");
+    init_schema(&conn).expect("schema");
+
+    let record = sample_record();
+    let message = |turn_index: i64, text: &str| MessageRecord {
+        turn_index,
+        role: Some("user".to_string()),
+        timestamp: None,
+        text: text.to_string(),
+        tools: Vec::new(),
+        line: None,
+    };
+
+    let tx = conn.transaction().expect("tx");
+    upsert_session_tx(&tx, &record).expect("session");
+    replace_messages_tx(
+        &tx,
+        &record.path,
+        &[message(0, "alpha"), message(1, "beta")],
+    )
+    .expect("messages");
+    tx.commit().expect("commit");
+
+    let filters = FindFilters {
+        limit: 10,
+        ..Default::default()
+    };
+    let hits = find_messages(&conn, "beta", &filters).expect("query");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].turn_index, 1);
+
+    let tx = conn.transaction().expect("tx");
+    replace_messages_tx(&tx, &record.path, &[message(0, "gamma")]).expect("replace");
+    tx.commit().expect("commit");
+
+    let count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM messages", [], |row| row.get(0))
+        .expect("messages count");
+    assert_eq!(count, 1);
+    assert!(
+        find_messages(&conn, "beta", &filters)
+            .expect("query")
+            .is_empty()
+    );
+    assert_eq!(
+        find_messages(&conn, "gamma", &filters)
+            .expect("query")
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn reindexing_with_fewer_messages_leaves_no_stale_turns() {
+    let mut conn = Connection::open_in_memory().expect("open memory db");
+    init_schema(&conn).expect("schema");
+
+    let record = sample_record();
+    let message = |turn_index: i64, text: &str| MessageRecord {
+        turn_index,
+        role: Some("user".to_string()),
+        timestamp: None,
+        text: text.to_string(),
+        tools: Vec::new(),
+        line: None,
+    };
+
+    let tx = conn.transaction().expect("tx");
+    upsert_session_tx(&tx, &record).expect("session");
+    replace_messages_tx(
+        &tx,
+        &record.path,
+        &[message(0, "one"), message(1, "two"), message(2, "three")],
+    )
+    .expect("first pass");
+    replace_messages_tx(&tx, &record.path, &[message(0, "uno"), message(1, "dos")])
+        .expect("second pass");
+    tx.commit().expect("commit");
+
+    let turns: Vec<i64> = conn
+        .prepare("SELECT turn_index FROM messages ORDER BY turn_index")
+        .expect("prepare")
+        .query_map([], |row| row.get(0))
+        .expect("query")
+        .collect::<Result<_, _>>()
+        .expect("turns");
+    assert_eq!(turns, vec![0, 1]);
+    let fts_count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM messages_fts", [], |row| row.get(0))
+        .expect("fts count");
+    assert_eq!(fts_count, 2);
+
+    let tx = conn.transaction().expect("tx");
+    let err = replace_messages_tx(&tx, &record.path, &[message(0, "a"), message(0, "b")]);
+    assert!(err.is_err(), "duplicate turn_index must be rejected");
+}
+
+#[test]
+fn failed_message_replacement_rolls_back_with_its_transaction() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let mut conn = Connection::open(dir.path().join("fresh.sqlite")).expect("open db");
+    init_schema(&conn).expect("schema");
+
+    let record = sample_record();
+    let message = |turn_index: i64, text: &str| MessageRecord {
+        turn_index,
+        role: Some("user".to_string()),
+        timestamp: None,
+        text: text.to_string(),
+        tools: Vec::new(),
+        line: None,
+    };
+    let tx = conn.transaction().expect("tx");
+    upsert_session_tx(&tx, &record).expect("session");
+    replace_messages_tx(
+        &tx,
+        &record.path,
+        &[message(0, "kept"), message(1, "also kept")],
+    )
+    .expect("messages");
+    tx.commit().expect("commit");
+
+    let tx = conn.transaction().expect("tx");
+    let err = replace_messages_tx(&tx, &record.path, &[message(0, "new"), message(0, "clash")]);
+    assert!(err.is_err());
+    drop(tx);
+
+    let filters = FindFilters {
+        limit: 10,
+        ..Default::default()
+    };
+    let hits = find_messages(&conn, "kept", &filters).expect("find");
+    assert_eq!(hits.len(), 2, "both original messages are still indexed");
+    assert!(
+        find_messages(&conn, "new", &filters)
+            .expect("find")
+            .is_empty()
+    );
+}
+
+#[test]
+fn migrates_duplicate_turns_to_unique_index() {
+    let conn = Connection::open_in_memory().expect("open memory db");
+    conn.execute_batch(
+        "CREATE TABLE messages (
+            id INTEGER PRIMARY KEY,
+            session_path TEXT NOT NULL,
+            turn_index INTEGER NOT NULL,
+            role TEXT,
+            timestamp TEXT,
+            text TEXT
+        );
+        INSERT INTO messages (session_path, turn_index, text) VALUES
+            ('/tmp/a.jsonl', 0, 'first'),
+            ('/tmp/a.jsonl', 0, 'duplicate'),
+            ('/tmp/a.jsonl', 1, 'second');",
+    )
+    .expect("legacy messages table");
+
+    init_schema(&conn).expect("schema");
+
+    let texts: Vec<String> = conn
+        .prepare("SELECT text FROM messages ORDER BY turn_index")
+        .expect("prepare")
+        .query_map([], |row| row.get(0))
+        .expect("query")
+        .collect::<Result<_, _>>()
+        .expect("texts");
+    assert_eq!(texts, vec!["first".to_string(), "second".to_string()]);
+
+    let duplicate = conn.execute(
+        "INSERT INTO messages (session_path, turn_index, text) VALUES ('/tmp/a.jsonl', 1, 'x')",
+        [],
+    );
+    assert!(duplicate.is_err());
+    init_schema(&conn).expect("idempotent");
+}
+
+#[test]
+fn records_schema_version_and_opens_read_only() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let db_path = dir.path().join("mmem.sqlite");
+    let conn = Connection::open(&db_path).expect("db");
+    configure_connection(&conn).expect("configure");
+    assert!(!schema_is_current(&conn).expect("fresh version"));
+    init_schema(&conn).expect("schema");
+    assert!(schema_is_current(&conn).expect("current version"));
+
+    let reader = open_read_only(&db_path).expect("read only");
+    assert!(schema_is_current(&reader).expect("reader version"));
+    let write = reader.execute("DELETE FROM sessions", []);
+    assert!(write.is_err());
+}
+
+#[test]
+fn migration_backfills_role_counts_from_stored_messages() {
+    let conn = Connection::open_in_memory().expect("open memory db");
+    conn.execute_batch(
+        "CREATE TABLE sessions (
+            path TEXT PRIMARY KEY,
+            mtime INTEGER NOT NULL,
+            size INTEGER NOT NULL,
+            hash TEXT,
+            created_at TEXT,
+            last_message_at TEXT,
+            agent TEXT,
+            workspace TEXT,
+            title TEXT,
+            message_count INTEGER,
+            snippet TEXT
+        );
+        CREATE TABLE messages (
+            id INTEGER PRIMARY KEY,
+            session_path TEXT NOT NULL,
+            turn_index INTEGER NOT NULL,
+            role TEXT,
+            timestamp TEXT,
+            text TEXT NOT NULL
+        );
+        INSERT INTO sessions (path, mtime, size, message_count) VALUES ('/tmp/a.jsonl', 0, 0, 3);
+        INSERT INTO messages (session_path, turn_index, role, text) VALUES
+            ('/tmp/a.jsonl', 0, 'user', 'q1'),
+            ('/tmp/a.jsonl', 1, 'assistant', 'a1'),
+            ('/tmp/a.jsonl', 2, 'user', 'q2');",
+    )
+    .expect("legacy tables");
+
+    init_schema(&conn).expect("schema migrates");
+    let counts: (i64, i64) = conn
+        .query_row(
+            "SELECT user_message_count, assistant_message_count FROM sessions",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .expect("counts");
+    assert_eq!(counts, (2, 1));
+}
+
+#[test]
+fn external_content_message_index_stays_in_sync() {
+    let mut conn = Connection::open_in_memory().expect("open memory db");
+    init_schema(&conn).expect("schema");
+    let record = sample_record();
+    let message = |turn: i64, text: &str| MessageRecord {
+        turn_index: turn,
+        role: Some("user".to_string()),
+        timestamp: None,
+        text: text.to_string(),
+        tools: Vec::new(),
+        line: None,
+    };
+    let tx = conn.transaction().expect("tx");
+    upsert_session_tx(&tx, &record).expect("session");
+    replace_messages_tx(
+        &tx,
+        &record.path,
+        &[message(0, "alpha"), message(1, "beta")],
+    )
+    .expect("messages");
+    tx.commit().expect("commit");
+
+    assert!(!messages_fts_is_external(&conn).expect("layout"));
+    assert!(use_external_message_fts(&mut conn).expect("convert"));
+    assert!(!use_external_message_fts(&mut conn).expect("already converted"));
+    assert!(messages_fts_is_external(&conn).expect("layout"));
+
+    let filters = FindFilters {
+        limit: 10,
+        ..Default::default()
+    };
+    // Existing messages were rebuilt into the index.
+    assert_eq!(
+        find_messages(&conn, "beta", &filters).expect("find").len(),
+        1
+    );
+
+    let tx = conn.transaction().expect("tx");
+    replace_messages_tx(&tx, &record.path, &[message(0, "gamma")]).expect("replace");
+    tx.commit().expect("commit");
+    assert!(
+        find_messages(&conn, "alpha", &filters)
+            .expect("find")
+            .is_empty()
+    );
+    assert_eq!(
+        find_messages(&conn, "gamma", &filters).expect("find").len(),
+        1
+    );
+
+    let tx = conn.transaction().expect("tx");
+    rename_session_tx(&tx, &record.path, "/tmp/moved.jsonl", 1, 1).expect("rename");
+    tx.commit().expect("commit");
+    let hits = find_messages(&conn, "gamma", &filters).expect("find");
+    assert_eq!(hits[0].path, "/tmp/moved.jsonl");
+
+    remove_session(&mut conn, "/tmp/moved.jsonl").expect("remove");
+    assert!(
+        find_messages(&conn, "gamma", &filters)
+            .expect("find")
+            .is_empty()
+    );
+    conn.execute_batch("INSERT INTO messages_fts (messages_fts) VALUES ('integrity-check')")
+        .expect("index matches messages");
+}
+
+#[test]
+fn switching_tokenizer_keeps_external_content_layout() {
+    let mut conn = Connection::open_in_memory().expect("open memory db");
+    init_schema(&conn).expect("schema");
+    assert_eq!(
+        fts_tokenizer(&conn).expect("tokenizer"),
+        FtsTokenizer::Unicode61
+    );
+    let record = sample_record();
+    let message = |turn: i64, text: &str| MessageRecord {
+        turn_index: turn,
+        role: Some("user".to_string()),
+        timestamp: None,
+        text: text.to_string(),
+        tools: Vec::new(),
+        line: None,
+    };
+    let tx = conn.transaction().expect("tx");
+    upsert_session_tx(&tx, &record).expect("session");
+    replace_messages_tx(&tx, &record.path, &[message(0, "在东京见面")]).expect("messages");
+    tx.commit().expect("commit");
+    assert!(use_external_message_fts(&mut conn).expect("convert"));
+
+    init_schema_with(&conn, Some(FtsTokenizer::Trigram)).expect("retokenize");
+    assert_eq!(
+        fts_tokenizer(&conn).expect("tokenizer"),
+        FtsTokenizer::Trigram
+    );
+    assert!(messages_fts_is_external(&conn).expect("layout"));
+    // A plain init_schema keeps the tokenizer.
+    init_schema(&conn).expect("schema again");
+    assert_eq!(
+        fts_tokenizer(&conn).expect("tokenizer"),
+        FtsTokenizer::Trigram
+    );
+
+    let filters = FindFilters {
+        limit: 10,
+        ..Default::default()
+    };
+    assert_eq!(
+        find_messages(&conn, "东京", &filters).expect("find").len(),
+        1
+    );
+
+    let tx = conn.transaction().expect("tx");
+    replace_messages_tx(&tx, &record.path, &[message(0, "去大阪吧")]).expect("replace");
+    tx.commit().expect("commit");
+    assert!(
+        find_messages(&conn, "东京", &filters)
+            .expect("find")
+            .is_empty()
+    );
+    assert_eq!(
+        find_messages(&conn, "大阪", &filters).expect("find").len(),
+        1
+    );
+}
+
+#[test]
+fn dedup_content_clears_repeated_messages_in_both_fts_layouts() {
+    let body: Vec<String> = (0..150).map(|line| format!("line{line}")).collect();
+    let context = format!(
+        "Here is the file you asked about, repeated by the agent as context: \
+         fn main() {{ println!(\"hello\"); }} {}",
+        body.join(" ")
+    );
+    let context = context.as_str();
+    let edited = context.replace("line75", "edited75");
+    let unrelated: Vec<String> = (0..150).map(|line| format!("other{line}")).collect();
+    let unrelated = unrelated.join(" ");
+    for external in [false, true] {
+        let mut conn = Connection::open_in_memory().expect("open memory db");
+        init_schema(&conn).expect("schema");
+        if external {
+            use_external_message_fts(&mut conn).expect("external");
+        }
+        let message = |turn: i64, role: &str, text: &str| MessageRecord {
+            turn_index: turn,
+            role: Some(role.to_string()),
+            timestamp: None,
+            text: text.to_string(),
+            tools: Vec::new(),
+            line: None,
+        };
+        let messages = [
+            message(0, "user", context),
+            message(1, "assistant", "ok"),
+            message(2, "user", &context.replace(' ', "  ")),
+            message(3, "assistant", "ok"),
+            message(4, "user", &edited),
+            message(5, "assistant", &unrelated),
+        ];
+        let mut record = sample_record();
+        record.content = messages
+            .iter()
+            .map(|m| format!("[{}] {}", m.role.as_deref().unwrap_or_default(), m.text))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let tx = conn.transaction().expect("tx");
+        upsert_session_tx(&tx, &record).expect("session");
+        replace_messages_tx(&tx, &record.path, &messages).expect("messages");
+        tx.commit().expect("commit");
+
+        let stats = dedup_session_content(&mut conn).expect("dedup");
+        assert_eq!(stats.sessions, 1);
+        assert_eq!(stats.messages, 2, "short replies are not duplicates");
+        let cleared = |text: &str| 2 * text.len() as i64 + "\n[user] ".len() as i64;
+        assert_eq!(
+            stats.bytes_saved,
+            cleared(&messages[2].text) + cleared(&edited)
+        );
+
+        let texts: Vec<String> = conn
+            .prepare("SELECT text FROM messages ORDER BY turn_index")
+            .expect("prepare")
+            .query_map([], |row| row.get(0))
+            .expect("query")
+            .collect::<Result<_, _>>()
+            .expect("texts");
+        assert_eq!(texts, [context, "ok", "", "ok", "", unrelated.as_str()]);
+        let content: String = conn
+            .query_row("SELECT content FROM sessions_fts", [], |row| row.get(0))
+            .expect("content");
+        assert_eq!(
+            content,
+            format!("[user] {context}\n[assistant] ok\n[assistant] ok\n[assistant] {unrelated}")
+        );
+
+        let filters = FindFilters {
+            limit: 10,
+            ..Default::default()
+        };
+        let hits = find_messages(&conn, "println", &filters).expect("find");
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].turn_index, 0);
+        let fts_rows: i64 = conn
+            .query_row("SELECT COUNT(*) FROM messages_fts", [], |row| row.get(0))
+            .expect("fts rows");
+        assert_eq!(fts_rows, 6, "cleared messages keep their search row");
+
+        assert_eq!(
+            dedup_session_content(&mut conn).expect("again"),
+            Default::default()
+        );
+        if external {
+            conn.execute_batch(
+                "INSERT INTO messages_fts (messages_fts) VALUES ('integrity-check')",
+            )
+            .expect("index matches messages");
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use mmem::model::{MessageContext, MessageHit};
+
+#[test]
+fn message_hit_round_trips_through_json() {
+    let hit = MessageHit {
+        path: "/tmp/a.jsonl".to_string(),
+        title: Some("title".to_string()),
+        agent: Some("gpt-4".to_string()),
+        workspace: None,
+        repo_root: None,
+        repo_name: Some("mmem".to_string()),
+        branch: None,
+        turn_index: 3,
+        role: Some("user".to_string()),
+        timestamp: Some("2024-01-01T00:00:01Z".to_string()),
+        text: "alpha".to_string(),
+        score: -1.5,
+        context: Some(vec![MessageContext {
+            path: None,
+            turn_index: 2,
+            role: Some("assistant".to_string()),
+            timestamp: None,
+            text: "before".to_string(),
+        }]),
+        matched_terms: Some(vec!["alpha".to_string()]),
+        excerpt: Some("[alpha]".to_string()),
+        line: Some(7),
+    };
+
+    let json = serde_json::to_string(&hit).expect("serialize");
+    let decoded: MessageHit = serde_json::from_str(&json).expect("deserialize");
+    assert_eq!(decoded, hit);
+}
@@ -1,4 +1,8 @@
-use mmem::parse::{parse_json, parse_jsonl, parse_markdown};
+use mmem::parse::{
+    ParseError, ParseOptions, ParseSpec, parse_file, parse_json, parse_jsonl,
+    parse_jsonl_reader_with, parse_jsonl_with, parse_markdown, parse_markdown_with,
+    parse_with_spec,
+};
 
 #[test]
 fn handles_empty_jsonl_file() {
@@ -21,6 +25,8 @@ fn parses_jsonl_sessions() {
     let parsed = parse_jsonl(input).expect("jsonl parse");
 
     assert_eq!(parsed.message_count, 2);
+    assert_eq!(parsed.user_message_count, 1);
+    assert_eq!(parsed.assistant_message_count, 1);
     assert_eq!(parsed.messages.len(), 2);
     assert_eq!(parsed.title.as_deref(), Some("hello"));
     assert_eq!(parsed.agent.as_deref(), Some("gpt-4"));
@@ -57,6 +63,27 @@ fn parses_json_sessions() {
     assert_eq!(parsed.messages[1].role.as_deref(), Some("assistant"));
 }
 
+#[test]
+fn parses_gemini_parts_sessions() {
+    let input = include_str!("fixtures/session_gemini.json");
+    let parsed = parse_json(input).expect("gemini parse");
+
+    assert_eq!(parsed.message_count, 2);
+    assert_eq!(parsed.agent.as_deref(), Some("gemini-1.5-pro"));
+    assert_eq!(parsed.title.as_deref(), Some("how do I reverse a list"));
+    assert_eq!(parsed.messages[0].role.as_deref(), Some("user"));
+    assert_eq!(parsed.messages[1].role.as_deref(), Some("assistant"));
+    assert_eq!(
+        parsed.messages[1].text,
+        "Use reverse()\nor slicing with [::-1]"
+    );
+
+    let line = r#"{"role":"model","parts":[{"text":"jsonl part"}]}"#;
+    let parsed = parse_jsonl(line).expect("gemini jsonl parse");
+    assert_eq!(parsed.messages[0].role.as_deref(), Some("assistant"));
+    assert_eq!(parsed.messages[0].text, "jsonl part");
+}
+
 #[test]
 fn parses_markdown_sessions() {
     let input = include_str!("fixtures/session.md");
@@ -71,6 +98,64 @@ fn parses_markdown_sessions() {
     assert_eq!(parsed.messages[1].role.as_deref(), Some("assistant"));
 }
 
+#[test]
+fn snippet_prefers_first_user_message() {
+    let input = concat!(
+        "{\"role\":\"system\",\"content\":\"You are a helpful coding agent.\"}\n",
+        "{\"role\":\"user\",\"content\":\"why is the build red?\"}\n",
+    );
+    let parsed = parse_jsonl(input).expect("jsonl parse");
+    assert_eq!(parsed.snippet, "why is the build red?");
+
+    let parsed = parse_jsonl("{\"role\":\"assistant\",\"content\":\"hi\"}\n").expect("parse");
+    assert_eq!(parsed.snippet, "[assistant] hi");
+}
+
+#[test]
+fn keeps_markdown_code_fences_in_one_message() {
+    let input = include_str!("fixtures/session_code.md");
+    let parsed = parse_markdown(input);
+
+    assert_eq!(parsed.messages.len(), 3);
+    let answer = &parsed.messages[1];
+    assert_eq!(answer.role.as_deref(), Some("assistant"));
+    assert!(answer.text.starts_with("use read_line:\n```rust\n"));
+    assert!(answer.text.contains("\nuser: input\n"));
+    assert!(answer.text.ends_with("\n```"));
+    assert_eq!(parsed.messages[2].text, "thanks");
+}
+
+#[test]
+fn inline_fence_span_after_role_prefix_does_not_open_a_fence() {
+    let parsed = parse_markdown("user: ```ls``` please\nassistant: ok\nuser: thanks\n");
+    let texts: Vec<&str> = parsed.messages.iter().map(|m| m.text.as_str()).collect();
+    assert_eq!(texts, ["```ls``` please", "ok", "thanks"]);
+
+    let parsed = parse_markdown("```ls``` first\nuser: hi\n");
+    assert_eq!(parsed.messages.len(), 2);
+}
+
+#[test]
+fn markdown_continuation_lines_join_the_current_message() {
+    let parsed = parse_markdown("User: first line\nsecond line\n\n\nlater\nAssistant: ok\n");
+
+    assert_eq!(parsed.messages.len(), 2);
+    assert_eq!(
+        parsed.messages[0].text,
+        "first line\nsecond line\n\n\nlater"
+    );
+    assert_eq!(parsed.messages[1].text, "ok");
+}
+
+#[test]
+fn markdown_lines_before_any_role_stay_separate() {
+    let parsed = parse_markdown("notes\nmore notes\nUser: hi\n");
+
+    assert_eq!(parsed.messages.len(), 3);
+    assert_eq!(parsed.messages[0].role, None);
+    assert_eq!(parsed.messages[1].text, "more notes");
+}
+
 #[test]
 fn includes_toolcall_only_entries_in_message_count() {
     let input = include_str!("fixtures/session_toolcall_only.jsonl");
@@ -83,8 +168,319 @@ fn includes_toolcall_only_entries_in_message_count() {
     // First message is toolCall-only (empty text)
     assert_eq!(parsed.messages[0].role.as_deref(), Some("assistant"));
     assert!(parsed.messages[0].text.is_empty());
+    assert_eq!(parsed.messages[0].tools, vec!["read".to_string()]);
 
     // Second message has text
     assert_eq!(parsed.messages[1].role.as_deref(), Some("user"));
     assert_eq!(parsed.messages[1].text, "thanks");
 }
+
+#[test]
+fn parses_content_given_as_a_single_block_object() {
+    let input = include_str!("fixtures/session_object_content.jsonl");
+    let parsed = parse_jsonl(input).expect("jsonl parse");
+
+    let texts: Vec<&str> = parsed.messages.iter().map(|m| m.text.as_str()).collect();
+    assert_eq!(
+        texts,
+        [
+            "why does the cache miss?",
+            "the key includes the mtime",
+            "drop it from the key",
+            ""
+        ]
+    );
+    assert_eq!(parsed.messages[0].role.as_deref(), Some("user"));
+    assert_eq!(parsed.messages[2].role.as_deref(), Some("assistant"));
+    assert_eq!(parsed.messages[3].tools, vec!["read".to_string()]);
+}
+
+#[test]
+fn parses_function_call_output_entries_as_tool_messages() {
+    let input = include_str!("fixtures/session_function_output.jsonl");
+    let parsed = parse_jsonl(input).expect("jsonl parse");
+
+    let roles: Vec<Option<&str>> = parsed.messages.iter().map(|m| m.role.as_deref()).collect();
+    assert_eq!(
+        roles,
+        [
+            Some("user"),
+            Some("assistant"),
+            Some("tool"),
+            Some("tool"),
+            Some("assistant")
+        ]
+    );
+    assert_eq!(
+        parsed.messages[2].text,
+        "error[E0432]: unresolved import `serde_yaml`"
+    );
+    assert_eq!(
+        parsed.messages[2].timestamp.as_deref(),
+        Some("2024-06-01T10:00:09Z")
+    );
+    // Codex wraps the output in a JSON string with metadata.
+    assert_eq!(parsed.messages[3].text, "Cargo.toml\nsrc");
+    assert!(parsed.content.contains("unresolved import"));
+}
+
+#[test]
+fn parses_jsonl_with_bom_and_leading_blank_lines() {
+    let plain = parse_jsonl(include_str!("fixtures/session.jsonl")).expect("plain parse");
+    let mut bom = parse_jsonl(include_str!("fixtures/session_bom.jsonl")).expect("bom parse");
+    // The leading blank lines shift every source line down by two.
+    for (bom, plain) in bom.messages.iter_mut().zip(&plain.messages) {
+        assert_eq!(bom.line, plain.line.map(|line| line + 2));
+        bom.line = plain.line;
+    }
+    assert_eq!(bom, plain);
+}
+
+#[test]
+fn reader_parse_matches_in_memory_parse() {
+    let options = ParseOptions::default();
+    for input in [
+        include_str!("fixtures/session.jsonl"),
+        include_str!("fixtures/session_bom.jsonl"),
+        include_str!("fixtures/session_tools.jsonl"),
+    ] {
+        let streamed = parse_jsonl_reader_with(input.as_bytes(), &options).expect("reader");
+        assert_eq!(streamed, parse_jsonl(input).expect("in memory"));
+    }
+
+    let err = parse_jsonl_reader_with(&b"{\"role\":\"user\"}\nnot json\n"[..], &options)
+        .expect_err("bad line");
+    assert!(matches!(err, ParseError::InvalidJsonl { line: 2, .. }));
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn streamed_gzip_parse_matches_buffered_decompression() {
+    use flate2::write::GzEncoder;
+    use mmem::compress::Compression;
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().expect("tempdir");
+    let input = include_str!("fixtures/session_tools.jsonl");
+    let gz_path = dir.path().join("session.jsonl.gz");
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(input.as_bytes()).expect("compress");
+    std::fs::write(&gz_path, encoder.finish().expect("finish")).expect("write");
+
+    let compressed = std::fs::read(&gz_path).expect("read gz");
+    let buffered = Compression::Gzip
+        .decompress(&compressed)
+        .expect("decompress");
+    let buffered = parse_jsonl(std::str::from_utf8(&buffered).expect("utf8")).expect("buffered");
+    assert_eq!(parse_file(&gz_path).expect("streamed"), buffered);
+
+    // A corrupt archive is still a decompression error, not a parse error.
+    std::fs::write(&gz_path, &compressed[..compressed.len() / 2]).expect("truncate");
+    let err = parse_file(&gz_path).expect_err("truncated");
+    assert!(matches!(err, ParseError::Decompress { .. }), "{err:?}");
+}
+
+#[test]
+fn records_source_line_of_each_message() {
+    let input = "{\"role\":\"user\",\"content\":\"one\"}\n\n{\"type\":\"meta\"}\n\
+                 {\"role\":\"assistant\",\"content\":\"two\"}\n";
+    let parsed = parse_jsonl(input).expect("parse");
+    let lines: Vec<Option<usize>> = parsed.messages.iter().map(|m| m.line).collect();
+    assert_eq!(lines, vec![Some(1), Some(4)]);
+
+    let parsed = parse_markdown("# Notes\n\nuser: first\nmore\n\nassistant: second\n");
+    let lines: Vec<Option<usize>> = parsed.messages.iter().map(|m| m.line).collect();
+    assert_eq!(lines, vec![Some(1), Some(3), Some(6)]);
+}
+
+#[test]
+fn parses_json_with_bom() {
+    let input = include_str!("fixtures/session.json");
+    let plain = parse_json(input).expect("plain parse");
+    let bom = parse_json(&format!("\u{feff}\n{input}")).expect("bom parse");
+    assert_eq!(bom, plain);
+}
+
+#[test]
+fn reads_custom_timestamp_fields() {
+    let input = "{\"role\":\"user\",\"content\":\"hello\",\"sent_at\":\"2024-03-01T00:00:00Z\"}\n";
+    let default = parse_jsonl(input).expect("default parse");
+    assert!(default.messages[0].timestamp.is_none());
+
+    let options = ParseOptions {
+        timestamp_fields: vec!["sent_at".to_string()],
+        ..Default::default()
+    };
+    let parsed = parse_jsonl_with(input, &options).expect("custom parse");
+    assert_eq!(
+        parsed.messages[0].timestamp.as_deref(),
+        Some("2024-03-01T00:00:00Z")
+    );
+    assert_eq!(parsed.created_at.as_deref(), Some("2024-03-01T00:00:00Z"));
+}
+
+#[test]
+fn applies_title_agent_and_role_overrides() {
+    let input = concat!(
+        "{\"type\":\"session_meta\",\"bot\":\"helper\",\"subject\":\"Release prep\"}\n",
+        "{\"speaker\":\"x\",\"role\":\"Human\",\"content\":\"ship it?\"}\n",
+        "{\"role\":\"ai\",\"content\":\"yes\"}\n",
+    );
+    let options = ParseOptions {
+        title_fields: vec!["subject".to_string()],
+        agent_fields: vec!["bot".to_string()],
+        role_aliases: vec![
+            ("human".to_string(), "user".to_string()),
+            ("ai".to_string(), "assistant".to_string()),
+        ],
+        ..Default::default()
+    };
+
+    let parsed = parse_jsonl_with(input, &options).expect("parse");
+    assert_eq!(parsed.title.as_deref(), Some("Release prep"));
+    assert_eq!(parsed.agent.as_deref(), Some("helper"));
+    assert_eq!(parsed.messages[0].role.as_deref(), Some("user"));
+    assert_eq!(parsed.messages[1].role.as_deref(), Some("assistant"));
+}
+
+#[test]
+fn applies_role_aliases_to_markdown_prefixes() {
+    let options = ParseOptions {
+        role_aliases: vec![("human".to_string(), "user".to_string())],
+        ..Default::default()
+    };
+
+    let parsed = parse_markdown_with("Human: hello\nAssistant: hi\n", &options);
+    assert_eq!(parsed.messages[0].role.as_deref(), Some("user"));
+    assert_eq!(parsed.messages[0].text, "hello");
+    assert_eq!(parsed.title.as_deref(), Some("hello"));
+}
+
+#[test]
+fn strips_fences_wrapping_whole_messages_when_asked() {
+    let input = concat!(
+        r#"{"role":"user","content":"```rust\nfn main() {}\n```"}"#,
+        "\n",
+        r#"{"role":"assistant","content":"try:\n```sh\ncargo run\n```"}"#,
+        "\n",
+        r#"{"role":"assistant","content":"~~~~\nlet a = 1;\n\nlet b = 2;\n~~~~~"}"#,
+        "\n",
+        r#"{"role":"assistant","content":"```\none\n```\n```\ntwo\n```"}"#,
+        "\n",
+    );
+    let options = ParseOptions {
+        strip_code_fences: true,
+        ..Default::default()
+    };
+
+    let parsed = parse_jsonl_with(input, &options).expect("parse");
+    let texts: Vec<&str> = parsed.messages.iter().map(|m| m.text.as_str()).collect();
+    assert_eq!(
+        texts,
+        [
+            "fn main() {}",
+            "try:\n```sh\ncargo run\n```",
+            "let a = 1;\n\nlet b = 2;",
+            "```\none\n```\n```\ntwo\n```",
+        ]
+    );
+    assert_eq!(parsed.title.as_deref(), Some("fn main() {}"));
+
+    let verbatim = parse_jsonl(input).expect("parse");
+    assert!(verbatim.messages[0].text.starts_with("```rust\n"));
+}
+
+#[test]
+fn lenient_mode_skips_truncated_lines() {
+    let input = concat!(
+        r#"{"type":"message","message":{"role":"user","content":"first"}}"#,
+        "\n",
+        r#"{"type":"message","message":{"role":"assist"#,
+        "\n",
+        r#"{"type":"message","message":{"role":"user","content":"second"}}"#,
+        "\n",
+    );
+
+    let err = parse_jsonl(input).expect_err("strict mode rejects bad line");
+    assert!(err.to_string().contains("line 2"));
+
+    let options = ParseOptions {
+        lenient: true,
+        ..Default::default()
+    };
+    let parsed = parse_jsonl_with(input, &options).expect("lenient parse");
+    assert_eq!(parsed.skipped_lines, 1);
+    assert_eq!(parsed.messages.len(), 2);
+    assert_eq!(parsed.messages[1].text, "second");
+}
+
+#[cfg(feature = "json5")]
+#[test]
+fn lenient_json_accepts_comments_and_trailing_commas() {
+    let input = include_str!("fixtures/session_json5.json");
+    assert!(parse_json(input).is_err());
+
+    let options = ParseOptions {
+        lenient_json: true,
+        ..Default::default()
+    };
+    let parsed = mmem::parse::parse_json_with(input, &options).expect("lenient json parse");
+    assert_eq!(parsed.agent.as_deref(), Some("gpt-4"));
+    assert_eq!(parsed.messages.len(), 2);
+    assert_eq!(parsed.messages[0].text, "see https://example.com/a,]");
+    assert_eq!(parsed.messages[1].text, "done");
+
+    let line = r#"{"message":{"role":"user","content":"hi",},} // note"#;
+    assert!(parse_jsonl(line).is_err());
+    let parsed = parse_jsonl_with(line, &options).expect("lenient jsonl parse");
+    assert_eq!(parsed.messages[0].text, "hi");
+}
+
+#[test]
+fn parses_custom_format_with_spec() {
+    let spec: ParseSpec = serde_json::from_str(
+        r#"{"messages_path": "$.data.threads[0].conversation", "role_field": "speaker",
+            "text_field": "body.value", "time_field": "at", "title_path": "$.subject"}"#,
+    )
+    .expect("spec");
+    let input = r#"{
+        "subject": "Quarterly planning",
+        "data": {"threads": [{"conversation": [
+            {"speaker": "Human", "body": {"value": "draft the agenda"}, "at": "2024-05-01T10:00:00Z"},
+            {"speaker": "bot", "body": {"value": ""}},
+            {"speaker": "Bot", "body": {"value": "here it is"}, "at": 1714557660}
+        ]}]}
+    }"#;
+
+    let parsed = parse_with_spec(input, &spec).expect("spec parse");
+    assert_eq!(parsed.title.as_deref(), Some("Quarterly planning"));
+    assert_eq!(parsed.messages.len(), 2);
+    assert_eq!(parsed.messages[0].role.as_deref(), Some("human"));
+    assert_eq!(parsed.messages[0].text, "draft the agenda");
+    assert_eq!(parsed.created_at.as_deref(), Some("2024-05-01T10:00:00Z"));
+    assert_eq!(parsed.messages[1].timestamp.as_deref(), Some("1714557660"));
+
+    let missing: ParseSpec =
+        serde_json::from_str(r#"{"messages_path": "$.nope", "text_field": "body"}"#).expect("spec");
+    assert!(parse_with_spec(input, &missing).is_err());
+}
+
+#[test]
+fn parse_file_dispatches_by_extension() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let jsonl = dir.path().join("session.JSONL");
+    std::fs::write(&jsonl, include_str!("fixtures/session_toolcall_only.jsonl")).expect("write");
+    let parsed = parse_file(&jsonl).expect("parse jsonl file");
+    assert_eq!(parsed.message_count, 2);
+
+    let other = dir.path().join("notes.txt");
+    std::fs::write(&other, "hello").expect("write");
+    assert!(matches!(
+        parse_file(&other),
+        Err(ParseError::UnsupportedFormat { .. })
+    ));
+    assert!(matches!(
+        parse_file(&dir.path().join("missing.md")),
+        Err(ParseError::Io { .. })
+    ));
+}
@@ -1,6 +1,9 @@
 use mmem::index::{init_schema, replace_messages_tx, upsert_session_tx};
 use mmem::model::{MessageRecord, SessionRecord};
-use mmem::query::{FindFilters, FindScope, QueryError, QueryMode, find_messages};
+use mmem::query::{
+    ExcerptMarkers, FindFilters, FindScope, FindSort, QueryError, QueryMode, distinct_values,
+    find_messages, find_questions, find_sessions, for_each_message, probe_query_terms,
+};
 use rusqlite::Connection;
 
 fn record(path: &str, agent: &str, workspace: &str, last_message_at: &str) -> SessionRecord {
@@ -15,11 +18,17 @@ fn record(path: &str, agent: &str, workspace: &str, last_message_at: &str) -> Se
         workspace: Some(workspace.to_string()),
         title: Some("title".to_string()),
         message_count: 2,
+        user_message_count: 0,
+        assistant_message_count: 0,
         snippet: "snippet".to_string(),
         content: "alpha beta".to_string(),
         repo_root: None,
         repo_name: None,
         branch: None,
+        system_prompt: None,
+        preview: None,
+        parent_path: None,
+        commands: None,
     }
 }
 
@@ -46,6 +55,8 @@ fn finds_messages_with_filters() {
             role: Some("user".to_string()),
             timestamp: Some("2024-01-01T00:00:01Z".to_string()),
             text: "alpha".to_string(),
+            tools: Vec::new(),
+            line: None,
         }],
     );
     insert_session(
@@ -56,6 +67,8 @@ fn finds_messages_with_filters() {
             role: Some("user".to_string()),
             timestamp: Some("2024-01-02T00:00:01Z".to_string()),
             text: "alpha".to_string(),
+            tools: Vec::new(),
+            line: None,
         }],
     );
 
@@ -120,3 +133,942 @@ fn returns_empty_for_no_matches() {
     let results = find_messages(&conn, "nonexistent query term xyz", &filters).expect("query");
     assert!(results.is_empty());
 }
+
+#[test]
+fn session_hits_carry_repo_metadata() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let mut rec = record("/tmp/a.jsonl", "gpt-4", "ws-a", "2024-01-01T00:00:01Z");
+    rec.repo_root = Some("/src/mmem".to_string());
+    rec.repo_name = Some("mmem".to_string());
+    rec.branch = Some("main".to_string());
+    insert_session(&mut conn, &rec, &[]);
+
+    let filters = FindFilters {
+        limit: 10,
+        scope: FindScope::Session,
+        ..Default::default()
+    };
+    let results = find_sessions(&conn, "alpha", &filters).expect("query");
+    assert_eq!(results.len(), 1);
+    let hit = &results[0];
+    assert_eq!(hit.title.as_deref(), Some("title"));
+    assert_eq!(hit.agent.as_deref(), Some("gpt-4"));
+    assert_eq!(hit.workspace.as_deref(), Some("ws-a"));
+    assert_eq!(hit.repo_root.as_deref(), Some("/src/mmem"));
+    assert_eq!(hit.repo_name.as_deref(), Some("mmem"));
+    assert_eq!(hit.branch.as_deref(), Some("main"));
+    assert_eq!(hit.last_message_at.as_deref(), Some("2024-01-01T00:00:01Z"));
+    assert_eq!(hit.snippet.as_deref(), Some("snippet"));
+}
+
+#[test]
+fn lists_distinct_values_for_allowed_columns() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    insert_session(
+        &mut conn,
+        &record("/tmp/a.jsonl", "gpt-4", "ws-a", "2024-01-01T00:00:01Z"),
+        &[],
+    );
+    insert_session(
+        &mut conn,
+        &record("/tmp/b.jsonl", "claude", "ws-a", "2024-01-02T00:00:01Z"),
+        &[],
+    );
+    insert_session(
+        &mut conn,
+        &record("/tmp/c.jsonl", "gpt-4", "ws-b", "2024-01-03T00:00:01Z"),
+        &[],
+    );
+
+    let agents = distinct_values(&conn, "agent").expect("agents");
+    assert_eq!(agents, vec!["claude".to_string(), "gpt-4".to_string()]);
+
+    let err = distinct_values(&conn, "agent; DROP TABLE sessions").expect_err("rejected");
+    assert!(matches!(err, QueryError::UnsupportedColumn { .. }));
+}
+
+#[test]
+fn session_scope_role_requires_matching_message_of_role() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let message = |turn_index: i64, role: &str, text: &str| MessageRecord {
+        turn_index,
+        role: Some(role.to_string()),
+        timestamp: None,
+        text: text.to_string(),
+        tools: Vec::new(),
+        line: None,
+    };
+
+    let mut rec_a = record("/tmp/a.jsonl", "gpt-4", "ws", "2024-01-01T00:00:01Z");
+    rec_a.content = "deploy the service\nsure".to_string();
+    insert_session(
+        &mut conn,
+        &rec_a,
+        &[
+            message(0, "user", "deploy the service"),
+            message(1, "assistant", "sure"),
+        ],
+    );
+    let mut rec_b = record("/tmp/b.jsonl", "gpt-4", "ws", "2024-01-02T00:00:01Z");
+    rec_b.content = "hello\nrun deploy now".to_string();
+    insert_session(
+        &mut conn,
+        &rec_b,
+        &[
+            message(0, "user", "hello"),
+            message(1, "assistant", "run deploy now"),
+        ],
+    );
+
+    let mut filters = FindFilters {
+        limit: 10,
+        scope: FindScope::Session,
+        ..Default::default()
+    };
+    assert_eq!(
+        find_sessions(&conn, "deploy", &filters).expect("all").len(),
+        2
+    );
+
+    filters.role = Some("user".to_string());
+    let results = find_sessions(&conn, "deploy", &filters).expect("user");
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].path, "/tmp/a.jsonl");
+
+    filters.role = Some("assistant".to_string());
+    let results = find_sessions(&conn, "deploy", &filters).expect("assistant");
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].path, "/tmp/b.jsonl");
+}
+
+#[test]
+fn around_context_can_exclude_the_matched_turn() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let messages: Vec<MessageRecord> = ["setup", "needle here", "followup"]
+        .iter()
+        .enumerate()
+        .map(|(turn, text)| MessageRecord {
+            turn_index: turn as i64,
+            role: Some("user".to_string()),
+            timestamp: None,
+            text: text.to_string(),
+            tools: Vec::new(),
+            line: None,
+        })
+        .collect();
+    let rec = record("/tmp/a.jsonl", "gpt-4", "ws", "2024-01-01T00:00:01Z");
+    insert_session(&mut conn, &rec, &messages);
+
+    let mut filters = FindFilters {
+        around: 1,
+        limit: 5,
+        ..Default::default()
+    };
+    let turns = |filters: &FindFilters| -> Vec<i64> {
+        let hits = find_messages(&conn, "needle", filters).expect("query");
+        hits[0]
+            .context
+            .as_ref()
+            .expect("context")
+            .iter()
+            .map(|message| message.turn_index)
+            .collect()
+    };
+
+    assert_eq!(turns(&filters), vec![0, 1, 2]);
+    filters.exclude_self_context = true;
+    assert_eq!(turns(&filters), vec![0, 2]);
+}
+
+#[test]
+fn after_match_limits_hits_to_later_turns_in_the_same_session() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let messages = |texts: &[&str]| -> Vec<MessageRecord> {
+        texts
+            .iter()
+            .enumerate()
+            .map(|(turn, text)| MessageRecord {
+                turn_index: turn as i64,
+                role: Some("user".to_string()),
+                timestamp: None,
+                text: text.to_string(),
+                tools: Vec::new(),
+                line: None,
+            })
+            .collect()
+    };
+    let rec_a = record("/tmp/a.jsonl", "gpt-4", "ws", "2024-01-01T00:00:01Z");
+    insert_session(
+        &mut conn,
+        &rec_a,
+        &messages(&["retry once", "error reported", "retry again", "error again"]),
+    );
+    let rec_b = record("/tmp/b.jsonl", "gpt-4", "ws", "2024-01-02T00:00:01Z");
+    insert_session(&mut conn, &rec_b, &messages(&["retry without incident"]));
+
+    let filters = FindFilters {
+        limit: 10,
+        after_match: Some("error".to_string()),
+        ..Default::default()
+    };
+    let hits = find_messages(&conn, "retry", &filters).expect("query");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].path, "/tmp/a.jsonl");
+    assert_eq!(hits[0].turn_index, 2);
+}
+
+#[test]
+fn per_session_limit_keeps_results_diverse() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let messages = |count: usize| -> Vec<MessageRecord> {
+        (0..count)
+            .map(|turn| MessageRecord {
+                turn_index: turn as i64,
+                role: Some("user".to_string()),
+                timestamp: None,
+                text: format!("deploy failed again {turn}"),
+                tools: Vec::new(),
+                line: None,
+            })
+            .collect()
+    };
+    let busy = record("/tmp/busy.jsonl", "gpt-4", "ws", "2024-01-02T00:00:01Z");
+    insert_session(&mut conn, &busy, &messages(8));
+    let quiet = record("/tmp/quiet.jsonl", "gpt-4", "ws", "2024-01-01T00:00:01Z");
+    insert_session(&mut conn, &quiet, &messages(1));
+
+    let mut filters = FindFilters {
+        limit: 5,
+        ..Default::default()
+    };
+    let hits = find_messages(&conn, "deploy", &filters).expect("query");
+    assert_eq!(hits.len(), 5);
+
+    filters.per_session_limit = Some(2);
+    let hits = find_messages(&conn, "deploy", &filters).expect("query");
+    let busy_hits = hits
+        .iter()
+        .filter(|hit| hit.path == "/tmp/busy.jsonl")
+        .count();
+    assert_eq!(busy_hits, 2);
+    assert_eq!(hits.len(), 3);
+    assert!(hits.iter().any(|hit| hit.path == "/tmp/quiet.jsonl"));
+}
+
+#[test]
+fn question_scope_matches_user_turns_once_per_session() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let turn = |turn_index: i64, role: &str, text: &str| MessageRecord {
+        turn_index,
+        role: Some(role.to_string()),
+        timestamp: None,
+        text: text.to_string(),
+        tools: Vec::new(),
+        line: None,
+    };
+    let asked = record("/tmp/asked.jsonl", "gpt-4", "ws", "2024-01-01T00:00:01Z");
+    insert_session(
+        &mut conn,
+        &asked,
+        &[
+            turn(0, "user", "how do lifetimes work"),
+            turn(1, "assistant", "lifetimes lifetimes lifetimes"),
+            turn(2, "user", "and lifetimes in structs"),
+        ],
+    );
+    let answered = record("/tmp/answered.jsonl", "gpt-4", "ws", "2024-01-02T00:00:01Z");
+    insert_session(
+        &mut conn,
+        &answered,
+        &[
+            turn(0, "user", "fix my build"),
+            turn(1, "assistant", "this is about lifetimes"),
+        ],
+    );
+
+    let filters = FindFilters {
+        limit: 10,
+        role: Some("assistant".to_string()),
+        ..Default::default()
+    };
+    let hits = find_questions(&conn, "lifetimes", &filters).expect("query");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].path, "/tmp/asked.jsonl");
+    assert_eq!(hits[0].role.as_deref(), Some("user"));
+}
+
+#[test]
+fn streams_message_hits_with_context() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let messages: Vec<MessageRecord> = ["needle one", "filler", "needle two"]
+        .iter()
+        .enumerate()
+        .map(|(turn, text)| MessageRecord {
+            turn_index: turn as i64,
+            role: Some("user".to_string()),
+            timestamp: None,
+            text: text.to_string(),
+            tools: Vec::new(),
+            line: None,
+        })
+        .collect();
+    let rec = record("/tmp/a.jsonl", "gpt-4", "ws", "2024-01-01T00:00:01Z");
+    insert_session(&mut conn, &rec, &messages);
+
+    let filters = FindFilters {
+        around: 1,
+        limit: 5,
+        ..Default::default()
+    };
+    let mut turns = Vec::new();
+    let count = for_each_message(&conn, "needle", &filters, |hit| {
+        assert!(
+            hit.context
+                .as_ref()
+                .is_some_and(|context| context.len() == 2)
+        );
+        turns.push(hit.turn_index);
+        Ok::<_, QueryError>(())
+    })
+    .expect("stream");
+    assert_eq!(count, 2);
+    turns.sort();
+    assert_eq!(turns, vec![0, 2]);
+
+    let mut seen = 0;
+    let result = for_each_message(&conn, "needle", &filters, |_| {
+        seen += 1;
+        Err(QueryError::EmptyQuery)
+    });
+    assert!(matches!(result, Err(QueryError::EmptyQuery)));
+    assert_eq!(seen, 1);
+}
+
+#[test]
+fn path_contains_matches_literal_substrings() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let message = |text: &str| MessageRecord {
+        turn_index: 0,
+        role: Some("user".to_string()),
+        timestamp: None,
+        text: text.to_string(),
+        tools: Vec::new(),
+        line: None,
+    };
+    for path in [
+        "/s/2024-05_x/a.jsonl",
+        "/s/2024-05ax/b.jsonl",
+        "/s/100%/c.jsonl",
+    ] {
+        let rec = record(path, "gpt-4", "ws", "2024-01-01T00:00:01Z");
+        insert_session(&mut conn, &rec, &[message("alpha")]);
+    }
+
+    let mut filters = FindFilters {
+        limit: 10,
+        path_contains: Some("05_x".to_string()),
+        ..Default::default()
+    };
+    let hits = find_messages(&conn, "alpha", &filters).expect("messages");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].path, "/s/2024-05_x/a.jsonl");
+
+    filters.path_contains = Some("0%/".to_string());
+    let hits = find_sessions(&conn, "alpha", &filters).expect("sessions");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].path, "/s/100%/c.jsonl");
+
+    filters.per_session_limit = Some(1);
+    filters.path_contains = Some("2024".to_string());
+    let hits = find_messages(&conn, "alpha", &filters).expect("capped messages");
+    assert_eq!(hits.len(), 2);
+}
+
+#[test]
+fn sort_newest_with_reverse_yields_oldest_first() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    for (path, at) in [
+        ("/s/mid.jsonl", "2024-02-01T00:00:00Z"),
+        ("/s/old.jsonl", "2024-01-01T00:00:00Z"),
+        ("/s/new.jsonl", "2024-03-01T00:00:00Z"),
+    ] {
+        let message = MessageRecord {
+            turn_index: 0,
+            role: Some("user".to_string()),
+            timestamp: Some(at.to_string()),
+            text: "alpha".to_string(),
+            tools: Vec::new(),
+            line: None,
+        };
+        insert_session(&mut conn, &record(path, "gpt-4", "ws", at), &[message]);
+    }
+
+    let mut filters = FindFilters {
+        limit: 10,
+        sort: FindSort::Newest,
+        ..Default::default()
+    };
+    let paths = |hits: Vec<String>| hits.join(" ");
+    let sessions = find_sessions(&conn, "alpha", &filters).expect("sessions");
+    assert_eq!(
+        paths(sessions.into_iter().map(|hit| hit.path).collect()),
+        "/s/new.jsonl /s/mid.jsonl /s/old.jsonl"
+    );
+
+    filters.reverse = true;
+    let sessions = find_sessions(&conn, "alpha", &filters).expect("sessions");
+    assert_eq!(
+        paths(sessions.into_iter().map(|hit| hit.path).collect()),
+        "/s/old.jsonl /s/mid.jsonl /s/new.jsonl"
+    );
+
+    filters.role = Some("user".to_string());
+    let messages = find_messages(&conn, "alpha", &filters).expect("messages");
+    assert_eq!(
+        paths(messages.into_iter().map(|hit| hit.path).collect()),
+        "/s/old.jsonl /s/mid.jsonl /s/new.jsonl"
+    );
+}
+
+#[test]
+fn sort_oldest_ignores_score_and_puts_undated_last() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    for (path, at, text) in [
+        (
+            "/s/new.jsonl",
+            Some("2024-03-01T00:00:00Z"),
+            "alpha alpha alpha",
+        ),
+        ("/s/undated.jsonl", None, "alpha alpha"),
+        (
+            "/s/old.jsonl",
+            Some("2024-01-01T00:00:00Z"),
+            "alpha and a lot of other words",
+        ),
+    ] {
+        let message = MessageRecord {
+            turn_index: 0,
+            role: Some("user".to_string()),
+            timestamp: at.map(str::to_string),
+            text: text.to_string(),
+            tools: Vec::new(),
+            line: None,
+        };
+        let mut session = record(path, "gpt-4", "ws", at.unwrap_or_default());
+        session.last_message_at = at.map(str::to_string);
+        insert_session(&mut conn, &session, &[message]);
+    }
+
+    let mut filters = FindFilters {
+        limit: 10,
+        ..Default::default()
+    };
+    let relevance = find_sessions(&conn, "alpha", &filters).expect("relevance");
+    assert_eq!(relevance[0].path, "/s/new.jsonl");
+
+    filters.sort = FindSort::Oldest;
+    let sessions = find_sessions(&conn, "alpha", &filters).expect("sessions");
+    let paths: Vec<&str> = sessions.iter().map(|hit| hit.path.as_str()).collect();
+    assert_eq!(paths, ["/s/old.jsonl", "/s/new.jsonl", "/s/undated.jsonl"]);
+
+    filters.role = Some("user".to_string());
+    let messages = find_messages(&conn, "alpha", &filters).expect("messages");
+    let paths: Vec<&str> = messages.iter().map(|hit| hit.path.as_str()).collect();
+    assert_eq!(paths, ["/s/old.jsonl", "/s/new.jsonl", "/s/undated.jsonl"]);
+}
+
+#[test]
+fn explain_matches_reports_terms_found_in_each_hit() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let message = |turn_index: i64, text: &str| MessageRecord {
+        turn_index,
+        role: Some("user".to_string()),
+        timestamp: None,
+        text: text.to_string(),
+        tools: Vec::new(),
+        line: None,
+    };
+    let rec = record("/s/a.jsonl", "gpt-4", "ws", "2024-01-01T00:00:01Z");
+    insert_session(
+        &mut conn,
+        &rec,
+        &[
+            message(0, "Alpha and beta, alpha again"),
+            message(1, "only beta"),
+        ],
+    );
+
+    let mut filters = FindFilters {
+        limit: 10,
+        query_mode: QueryMode::Fts,
+        ..Default::default()
+    };
+    let hits = find_messages(&conn, "alpha OR beta", &filters).expect("messages");
+    assert!(hits.iter().all(|hit| hit.matched_terms.is_none()));
+
+    filters.explain_matches = true;
+    let hits = find_messages(&conn, "alpha OR beta", &filters).expect("messages");
+    let terms = |turn: i64| {
+        let hit = hits.iter().find(|hit| hit.turn_index == turn).expect("hit");
+        hit.matched_terms.clone().expect("terms")
+    };
+    assert_eq!(terms(0), ["alpha", "beta"]);
+    assert_eq!(terms(1), ["beta"]);
+}
+
+#[test]
+fn exclusion_filters_remove_matching_sessions() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let message = MessageRecord {
+        turn_index: 0,
+        role: Some("user".to_string()),
+        timestamp: None,
+        text: "alpha".to_string(),
+        tools: Vec::new(),
+        line: None,
+    };
+    let mut scratch = record("/s/scratch.jsonl", "gpt-4", "ws-a", "2024-01-01T00:00:01Z");
+    scratch.repo_name = Some("scratch".to_string());
+    insert_session(&mut conn, &scratch, std::slice::from_ref(&message));
+    let other = record("/s/other.jsonl", "claude", "ws-b", "2024-01-01T00:00:02Z");
+    insert_session(&mut conn, &other, &[message]);
+
+    let mut filters = FindFilters {
+        limit: 10,
+        not_repo: Some("scratch".to_string()),
+        ..Default::default()
+    };
+    let hits = find_sessions(&conn, "alpha", &filters).expect("sessions");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].path, "/s/other.jsonl");
+
+    filters.not_repo = None;
+    filters.not_agent = Some("claude".to_string());
+    let hits = find_messages(&conn, "alpha", &filters).expect("messages");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].path, "/s/scratch.jsonl");
+
+    filters.agent = Some("claude".to_string());
+    assert!(
+        find_messages(&conn, "alpha", &filters)
+            .expect("messages")
+            .is_empty()
+    );
+}
+
+#[test]
+fn prefer_agent_ranks_without_filtering() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let message = MessageRecord {
+        turn_index: 0,
+        role: Some("user".to_string()),
+        timestamp: None,
+        text: "alpha".to_string(),
+        tools: Vec::new(),
+        line: None,
+    };
+    let newer = record("/s/newer.jsonl", "gpt-4", "ws", "2024-02-01T00:00:00Z");
+    insert_session(&mut conn, &newer, std::slice::from_ref(&message));
+    let older = record("/s/older.jsonl", "o'brien", "ws", "2024-01-01T00:00:00Z");
+    insert_session(&mut conn, &older, &[message]);
+
+    let mut filters = FindFilters {
+        limit: 10,
+        ..Default::default()
+    };
+    let hits = find_sessions(&conn, "alpha", &filters).expect("sessions");
+    assert_eq!(hits[0].path, "/s/newer.jsonl");
+
+    filters.prefer_agent = Some("o'brien".to_string());
+    let hits = find_sessions(&conn, "alpha", &filters).expect("sessions");
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0].path, "/s/older.jsonl");
+
+    // Reversing the order never demotes the preferred agent.
+    filters.reverse = true;
+    let hits = find_sessions(&conn, "alpha", &filters).expect("reversed");
+    assert_eq!(hits[0].path, "/s/older.jsonl");
+    filters.reverse = false;
+
+    let hits = find_messages(&conn, "alpha", &filters).expect("messages");
+    assert_eq!(hits[0].path, "/s/older.jsonl");
+    filters.workspace = Some("ws".to_string());
+    let hits = find_messages(&conn, "alpha", &filters).expect("filtered messages");
+    assert_eq!(hits[0].path, "/s/older.jsonl");
+
+    filters.per_session_limit = Some(1);
+    let hits = find_messages(&conn, "alpha", &filters).expect("messages");
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0].path, "/s/older.jsonl");
+}
+
+#[test]
+fn session_snippets_can_show_the_best_matching_message() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let message = |turn_index: i64, text: &str| MessageRecord {
+        turn_index,
+        role: Some("user".to_string()),
+        timestamp: None,
+        text: text.to_string(),
+        tools: Vec::new(),
+        line: None,
+    };
+    let rec = record("/s/a.jsonl", "gpt-4", "ws", "2024-01-01T00:00:01Z");
+    insert_session(
+        &mut conn,
+        &rec,
+        &[
+            message(0, "standard template opener"),
+            message(1, "why does alpha fail"),
+        ],
+    );
+
+    let mut filters = FindFilters {
+        limit: 10,
+        ..Default::default()
+    };
+    let hits = find_sessions(&conn, "alpha", &filters).expect("sessions");
+    assert_eq!(hits[0].snippet.as_deref(), Some("snippet"));
+
+    filters.match_snippets = true;
+    let hits = find_sessions(&conn, "alpha", &filters).expect("sessions");
+    assert_eq!(hits[0].snippet.as_deref(), Some("why does alpha fail"));
+
+    // Only the session content matches; the stored snippet stays.
+    let hits = find_sessions(&conn, "beta", &filters).expect("sessions");
+    assert_eq!(hits[0].snippet.as_deref(), Some("snippet"));
+}
+
+#[test]
+fn effective_limit_matches_applied_limit() {
+    let mut filters = FindFilters::default();
+    assert_eq!(filters.effective_limit(), 5);
+    filters.limit = 12;
+    assert_eq!(filters.effective_limit(), 12);
+}
+
+#[test]
+fn role_filter_accepts_a_list_of_roles() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let messages: Vec<MessageRecord> = ["user", "assistant", "tool"]
+        .iter()
+        .enumerate()
+        .map(|(turn, role)| MessageRecord {
+            turn_index: turn as i64,
+            role: Some(role.to_string()),
+            timestamp: None,
+            text: "shared words".to_string(),
+            tools: Vec::new(),
+            line: None,
+        })
+        .collect();
+    let rec = record("/tmp/a.jsonl", "gpt-4", "ws", "2024-01-01T00:00:01Z");
+    insert_session(&mut conn, &rec, &messages);
+
+    let filters = FindFilters {
+        limit: 10,
+        role: Some("user,assistant".to_string()),
+        ..Default::default()
+    };
+    let mut roles: Vec<String> = find_messages(&conn, "shared", &filters)
+        .expect("query")
+        .into_iter()
+        .filter_map(|hit| hit.role)
+        .collect();
+    roles.sort();
+    assert_eq!(roles, vec!["assistant", "user"]);
+}
+
+#[test]
+fn after_cutoff_keeps_untimestamped_messages_of_recent_sessions() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let message = |turn: i64, timestamp: Option<&str>| MessageRecord {
+        turn_index: turn,
+        role: Some("user".to_string()),
+        timestamp: timestamp.map(str::to_string),
+        text: "alpha".to_string(),
+        tools: Vec::new(),
+        line: None,
+    };
+    // Mostly old messages, so the cutoff is selective enough to pre-filter.
+    let mut history: Vec<MessageRecord> = (0..12)
+        .map(|turn| message(turn, Some("2023-01-01T00:00:00Z")))
+        .collect();
+    history.push(message(12, Some("2024-07-01T00:00:00Z")));
+    let history_rec = record("/tmp/history.jsonl", "gpt-4", "ws", "2024-07-01T00:00:00Z");
+    insert_session(&mut conn, &history_rec, &history);
+    let recent = record("/tmp/recent.jsonl", "gpt-4", "ws", "2024-08-01T00:00:00Z");
+    insert_session(&mut conn, &recent, &[message(0, None)]);
+    let stale = record("/tmp/stale.jsonl", "gpt-4", "ws", "2023-02-01T00:00:00Z");
+    insert_session(&mut conn, &stale, &[message(0, None)]);
+
+    let mut filters = FindFilters {
+        after: Some("2024-06-01".to_string()),
+        limit: 10,
+        ..Default::default()
+    };
+    let hits = |filters: &FindFilters| {
+        let mut found: Vec<(String, i64)> = find_messages(&conn, "alpha", filters)
+            .expect("query")
+            .into_iter()
+            .map(|hit| (hit.path, hit.turn_index))
+            .collect();
+        found.sort();
+        found
+    };
+    let expected = vec![
+        ("/tmp/history.jsonl".to_string(), 12),
+        ("/tmp/recent.jsonl".to_string(), 0),
+    ];
+    assert_eq!(hits(&filters), expected);
+
+    filters.per_session_limit = Some(5);
+    assert_eq!(hits(&filters), expected);
+}
+
+#[test]
+fn excerpt_windows_the_match_with_markers() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+    let filler = "lorem ipsum dolor sit amet ".repeat(20);
+    let text = format!("{filler}the flaky deadlock shows up here {filler}");
+    let rec = record("/tmp/a.jsonl", "gpt-4", "ws", "2024-01-01T00:00:00Z");
+    insert_session(
+        &mut conn,
+        &rec,
+        &[MessageRecord {
+            turn_index: 0,
+            role: Some("user".to_string()),
+            timestamp: None,
+            text,
+            tools: Vec::new(),
+            line: None,
+        }],
+    );
+
+    let mut filters = FindFilters {
+        limit: 10,
+        ..Default::default()
+    };
+    let hits = find_messages(&conn, "deadlock", &filters).expect("query");
+    assert!(hits[0].excerpt.is_none());
+
+    filters.excerpt = Some(ExcerptMarkers {
+        open: "<".to_string(),
+        close: ">".to_string(),
+    });
+    let hits = find_messages(&conn, "deadlock", &filters).expect("query");
+    let excerpt = hits[0].excerpt.as_deref().expect("excerpt");
+    assert!(excerpt.contains("<deadlock>"), "{excerpt}");
+    assert!(
+        excerpt.starts_with('…') && excerpt.ends_with('…'),
+        "{excerpt}"
+    );
+    assert!(excerpt.len() < hits[0].text.len() / 4);
+}
+
+#[test]
+fn min_exchanges_keeps_sessions_with_back_and_forth() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+    let message = MessageRecord {
+        turn_index: 0,
+        role: Some("user".to_string()),
+        timestamp: None,
+        text: "alpha".to_string(),
+        tools: Vec::new(),
+        line: None,
+    };
+    let mut chatty = record("/tmp/chatty.jsonl", "gpt-4", "ws", "2024-01-01T00:00:00Z");
+    chatty.user_message_count = 3;
+    chatty.assistant_message_count = 2;
+    insert_session(&mut conn, &chatty, std::slice::from_ref(&message));
+    let mut one_shot = record("/tmp/one-shot.jsonl", "gpt-4", "ws", "2024-01-01T00:00:00Z");
+    one_shot.user_message_count = 5;
+    one_shot.assistant_message_count = 1;
+    insert_session(&mut conn, &one_shot, &[message]);
+
+    let filters = FindFilters {
+        min_exchanges: Some(2),
+        limit: 10,
+        ..Default::default()
+    };
+    let messages = find_messages(&conn, "alpha", &filters).expect("messages");
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].path, "/tmp/chatty.jsonl");
+
+    let filters = FindFilters {
+        scope: FindScope::Session,
+        ..filters
+    };
+    let sessions = find_sessions(&conn, "alpha", &filters).expect("sessions");
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].path, "/tmp/chatty.jsonl");
+}
+
+#[test]
+fn probes_which_terms_match_on_their_own() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+    let rec = record("/tmp/a.jsonl", "gpt-4", "ws", "2024-01-01T00:00:00Z");
+    insert_session(
+        &mut conn,
+        &rec,
+        &[MessageRecord {
+            turn_index: 0,
+            role: Some("user".to_string()),
+            timestamp: None,
+            text: "alpha beta".to_string(),
+            tools: Vec::new(),
+            line: None,
+        }],
+    );
+
+    let filters = FindFilters {
+        limit: 10,
+        drop_stopwords: true,
+        ..Default::default()
+    };
+    assert!(
+        find_messages(&conn, "alpha the gamma", &filters)
+            .expect("query")
+            .is_empty()
+    );
+    let probes = probe_query_terms(&conn, "alpha the gamma", &filters).expect("probes");
+    let summary: Vec<(&str, bool)> = probes
+        .iter()
+        .map(|probe| (probe.term.as_str(), probe.matches))
+        .collect();
+    assert_eq!(summary, [("alpha", true), ("gamma", false)]);
+
+    let fts = FindFilters {
+        query_mode: QueryMode::Fts,
+        ..filters
+    };
+    assert!(
+        probe_query_terms(&conn, "alpha gamma", &fts)
+            .expect("fts")
+            .is_empty()
+    );
+}
+
+#[test]
+fn any_field_matches_sessions_by_repo_name_alone() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let mut by_repo = record("/s/repo.jsonl", "gpt-4", "ws", "2024-01-01T00:00:01Z");
+    by_repo.repo_name = Some("Quokka-Server".to_string());
+    insert_session(&mut conn, &by_repo, &[]);
+    let mut by_content = record("/s/content.jsonl", "gpt-4", "ws", "2024-01-01T00:00:02Z");
+    by_content.content = "deploying quokka to staging".to_string();
+    insert_session(&mut conn, &by_content, &[]);
+    let unrelated = record("/s/other.jsonl", "gpt-4", "ws", "2024-01-01T00:00:03Z");
+    insert_session(&mut conn, &unrelated, &[]);
+
+    let mut filters = FindFilters {
+        limit: 10,
+        scope: FindScope::Session,
+        ..Default::default()
+    };
+    let content_only = find_sessions(&conn, "quokka", &filters).expect("content");
+    let paths: Vec<&str> = content_only.iter().map(|hit| hit.path.as_str()).collect();
+    assert_eq!(paths, ["/s/content.jsonl"]);
+
+    filters.any_field = true;
+    let anywhere = find_sessions(&conn, "quokka", &filters).expect("any field");
+    let paths: Vec<&str> = anywhere.iter().map(|hit| hit.path.as_str()).collect();
+    assert_eq!(
+        paths,
+        ["/s/content.jsonl", "/s/repo.jsonl"],
+        "content matches rank first"
+    );
+
+    // Metadata filters still apply on top.
+    filters.agent = Some("claude".to_string());
+    assert!(
+        find_sessions(&conn, "quokka", &filters)
+            .expect("filtered")
+            .is_empty()
+    );
+    // LIKE wildcards in the query are matched literally.
+    filters.agent = None;
+    assert!(
+        find_sessions(&conn, "q%a", &filters)
+            .expect("wildcard")
+            .is_empty()
+    );
+}
+
+#[test]
+fn near_excludes_sessions_where_the_words_are_far_apart() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let filler = vec!["filler"; 20].join(" ");
+    let mut close = record("/s/close.jsonl", "gpt-4", "ws", "2024-01-01T00:00:01Z");
+    close.content = "the cache eviction policy".to_string();
+    insert_session(&mut conn, &close, &[]);
+    let mut far = record("/s/far.jsonl", "gpt-4", "ws", "2024-01-01T00:00:02Z");
+    far.content = format!("the cache {filler} eviction");
+    insert_session(&mut conn, &far, &[]);
+
+    let mut filters = FindFilters {
+        limit: 10,
+        scope: FindScope::Session,
+        ..Default::default()
+    };
+    assert_eq!(
+        find_sessions(&conn, "cache eviction", &filters)
+            .expect("literal")
+            .len(),
+        2
+    );
+
+    filters.query_mode = QueryMode::Near(3);
+    let hits = find_sessions(&conn, "cache eviction", &filters).expect("near");
+    let paths: Vec<&str> = hits.iter().map(|hit| hit.path.as_str()).collect();
+    assert_eq!(paths, ["/s/close.jsonl"]);
+
+    // One word has nothing to be near; it matches plainly.
+    assert_eq!(
+        find_sessions(&conn, "eviction", &filters)
+            .expect("one word")
+            .len(),
+        2
+    );
+}
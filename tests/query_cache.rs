@@ -0,0 +1,110 @@
+#![cfg(feature = "query-cache")]
+
+use mmem::cache::QuerySession;
+use mmem::index::{init_schema, replace_messages_tx, upsert_session_tx};
+use mmem::model::{MessageRecord, SessionRecord};
+use mmem::query::FindFilters;
+use rusqlite::Connection;
+
+fn insert(conn: &mut Connection, path: &str) {
+    let record = SessionRecord {
+        path: path.to_string(),
+        mtime: 1700000000,
+        size: 1,
+        hash: None,
+        created_at: None,
+        last_message_at: Some("2024-01-01T00:00:01Z".to_string()),
+        agent: None,
+        workspace: None,
+        title: None,
+        message_count: 1,
+        user_message_count: 0,
+        assistant_message_count: 0,
+        snippet: String::new(),
+        content: "alpha".to_string(),
+        repo_root: None,
+        repo_name: None,
+        branch: None,
+        system_prompt: None,
+        preview: None,
+        parent_path: None,
+        commands: None,
+    };
+    let tx = conn.transaction().expect("tx");
+    upsert_session_tx(&tx, &record).expect("session");
+    replace_messages_tx(
+        &tx,
+        path,
+        &[MessageRecord {
+            turn_index: 0,
+            role: Some("user".to_string()),
+            timestamp: None,
+            text: "alpha".to_string(),
+            tools: Vec::new(),
+            line: None,
+        }],
+    )
+    .expect("messages");
+    tx.commit().expect("commit");
+}
+
+#[test]
+fn caches_until_index_is_written() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let db_path = dir.path().join("mmem.sqlite");
+    let mut writer = Connection::open(&db_path).expect("writer");
+    init_schema(&writer).expect("schema");
+    insert(&mut writer, "/tmp/a.jsonl");
+
+    let reader = Connection::open(&db_path).expect("reader");
+    let mut session = QuerySession::new(&reader, 8);
+    let filters = FindFilters {
+        limit: 10,
+        ..Default::default()
+    };
+
+    let first = session.find_sessions("alpha", &filters).expect("query");
+    let second = session.find_sessions("alpha", &filters).expect("cached");
+    assert_eq!(first.len(), 1);
+    assert_eq!(second.len(), 1);
+    assert_eq!(session.cached_entries(), 1);
+
+    insert(&mut writer, "/tmp/b.jsonl");
+    let refreshed = session.find_sessions("alpha", &filters).expect("refreshed");
+    assert_eq!(refreshed.len(), 2);
+}
+
+#[test]
+fn commits_from_another_connection_invalidate_the_cache() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let db_path = dir.path().join("mmem.sqlite");
+    let mut writer = Connection::open(&db_path).expect("writer");
+    init_schema(&writer).expect("schema");
+    insert(&mut writer, "/tmp/a.jsonl");
+
+    let reader = Connection::open(&db_path).expect("reader");
+    let mut session = QuerySession::new(&reader, 8);
+    let filters = FindFilters {
+        limit: 10,
+        ..Default::default()
+    };
+    assert_eq!(
+        session
+            .find_sessions("alpha", &filters)
+            .expect("query")
+            .len(),
+        1
+    );
+    assert_eq!(session.cached_entries(), 1);
+
+    // Raw SQL bypasses the in-process write generation, as a separate
+    // `mmem index` process would.
+    writer
+        .execute_batch(
+            "INSERT INTO sessions (path, mtime, size) VALUES ('/tmp/b.jsonl', 1, 1);
+             INSERT INTO sessions_fts (content, path) VALUES ('alpha', '/tmp/b.jsonl');",
+        )
+        .expect("raw insert");
+    let refreshed = session.find_sessions("alpha", &filters).expect("refreshed");
+    assert_eq!(refreshed.len(), 2);
+}
@@ -1,6 +1,10 @@
-use mmem::index::init_schema;
-use mmem::scan::index_root;
+use mmem::index::{FtsTokenizer, init_schema, init_schema_with, open_read_only};
+use mmem::parse::ParseSpec;
+use mmem::query::{FindFilters, find_messages, find_sessions};
+use mmem::scan::{FileAction, IndexOptions, ScanError, import_file, index_root, index_root_with};
 use rusqlite::Connection;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 
 #[test]
 fn indexes_skips_and_removes_files() {
@@ -38,6 +42,205 @@ fn indexes_skips_and_removes_files() {
     assert_eq!(stats.removed, 1);
 }
 
+#[test]
+fn dry_run_plans_every_file_without_writing() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    for name in ["a.md", "b.md", "c.md"] {
+        std::fs::write(dir.path().join(name), "User: hi\n").expect("write md");
+    }
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+    index_root(&mut conn, dir.path(), false).expect("index");
+
+    std::fs::write(dir.path().join("b.md"), "User: hi again\n").expect("modify");
+    std::fs::remove_file(dir.path().join("c.md")).expect("remove");
+    std::fs::write(dir.path().join("d.jsonl"), "{not json\n").expect("write broken");
+    std::fs::write(dir.path().join("e.md"), "User: new\n").expect("write new");
+
+    let options = IndexOptions {
+        dry_run: true,
+        ..Default::default()
+    };
+    let stats = index_root_with(&mut conn, dir.path(), &options).expect("dry run");
+    let mut plan: Vec<(String, FileAction, String)> = stats
+        .plan
+        .clone()
+        .expect("plan")
+        .into_iter()
+        .map(|file| {
+            let name = file.path.rsplit('/').next().unwrap_or_default().to_string();
+            (name, file.action, file.reason)
+        })
+        .collect();
+    plan.sort_by(|a, b| a.0.cmp(&b.0));
+    let actions: Vec<(&str, FileAction)> = plan
+        .iter()
+        .map(|(name, action, _)| (name.as_str(), *action))
+        .collect();
+    assert_eq!(
+        actions,
+        vec![
+            ("a.md", FileAction::Skip),
+            ("b.md", FileAction::Index),
+            ("c.md", FileAction::Remove),
+            ("d.jsonl", FileAction::Error),
+            ("e.md", FileAction::Index),
+        ]
+    );
+    assert_eq!(plan[1].2, "modified");
+    assert_eq!(plan[4].2, "new");
+    assert!(plan[3].2.contains("line 1"), "{}", plan[3].2);
+
+    // Nothing was written, so a real run makes the same decisions.
+    let count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM sessions", [], |row| row.get(0))
+        .expect("count");
+    assert_eq!(count, 3);
+    let live = index_root(&mut conn, dir.path(), false).expect("index");
+    assert!(live.plan.is_none());
+    assert_eq!(
+        (live.indexed, live.skipped, live.removed, live.parse_errors),
+        (
+            stats.indexed,
+            stats.skipped,
+            stats.removed,
+            stats.parse_errors
+        )
+    );
+}
+
+#[test]
+fn no_remove_keeps_sessions_of_missing_files() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    std::fs::write(dir.path().join("a.md"), "User: hi\n").expect("write a");
+    std::fs::write(dir.path().join("b.md"), "User: hello\n").expect("write b");
+
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+    index_root(&mut conn, dir.path(), false).expect("index");
+    std::fs::remove_file(dir.path().join("b.md")).expect("remove b");
+
+    let options = IndexOptions {
+        no_remove: true,
+        ..Default::default()
+    };
+    let stats = index_root_with(&mut conn, dir.path(), &options).expect("additive index");
+    assert_eq!(stats.removed, 0);
+    assert!(stats.removal_skipped);
+    let count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM sessions", [], |row| row.get(0))
+        .expect("count");
+    assert_eq!(count, 2);
+
+    let stats = index_root(&mut conn, dir.path(), false).expect("index");
+    assert_eq!(stats.removed, 1);
+    assert!(!stats.removal_skipped);
+}
+
+#[test]
+fn content_hash_decides_when_mtime_is_unreliable() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("a.md");
+    std::fs::write(&path, "User: hi\n").expect("write");
+    let set_mtime = |secs: u64| {
+        let file = std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .expect("open");
+        let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+        file.set_modified(time).expect("set mtime");
+    };
+    set_mtime(1_700_000_000);
+
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+    index_root(&mut conn, dir.path(), false).expect("index");
+
+    // A rewritten mtime alone is not a change, and is remembered.
+    set_mtime(1_800_000_000);
+    let stats = index_root(&mut conn, dir.path(), false).expect("touched");
+    assert_eq!((stats.indexed, stats.skipped), (0, 1));
+    let mtime: i64 = conn
+        .query_row("SELECT mtime FROM sessions", [], |row| row.get(0))
+        .expect("mtime");
+    assert_eq!(mtime, 1_800_000_000);
+
+    // Same size and mtime but new content needs --verify-hash.
+    std::fs::write(&path, "User: ho\n").expect("rewrite");
+    set_mtime(1_800_000_000);
+    let stats = index_root(&mut conn, dir.path(), false).expect("cheap check");
+    assert_eq!((stats.indexed, stats.skipped), (0, 1));
+    let options = IndexOptions {
+        verify_hash: true,
+        ..Default::default()
+    };
+    let stats = index_root_with(&mut conn, dir.path(), &options).expect("verified");
+    assert_eq!((stats.indexed, stats.skipped), (1, 0));
+    let stats = index_root_with(&mut conn, dir.path(), &options).expect("verified again");
+    assert_eq!((stats.indexed, stats.skipped), (0, 1));
+}
+
+#[test]
+fn indexing_a_subdirectory_only_prunes_that_subtree() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let work = dir.path().join("work");
+    let home = dir.path().join("home");
+    for sub in [&work, &home] {
+        std::fs::create_dir(sub).expect("mkdir");
+        std::fs::write(sub.join("a.md"), "User: hi\n").expect("write a");
+        std::fs::write(sub.join("b.md"), "User: hello\n").expect("write b");
+    }
+
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+    index_root(&mut conn, dir.path(), false).expect("index all");
+
+    std::fs::remove_file(work.join("b.md")).expect("remove");
+    let stats = index_root(&mut conn, &work, false).expect("index work");
+    assert_eq!((stats.scanned, stats.removed), (1, 1));
+
+    let mut paths: Vec<String> = conn
+        .prepare("SELECT path FROM sessions ORDER BY path")
+        .expect("prepare")
+        .query_map([], |row| row.get(0))
+        .expect("query")
+        .collect::<Result<_, _>>()
+        .expect("paths");
+    let root = std::fs::canonicalize(dir.path()).expect("canonical root");
+    for path in &mut paths {
+        *path = path
+            .strip_prefix(root.to_str().expect("utf-8"))
+            .unwrap_or(path)
+            .to_string();
+    }
+    assert_eq!(paths, ["/home/a.md", "/home/b.md", "/work/a.md"]);
+}
+
+#[test]
+fn interrupted_scan_keeps_existing_sessions() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    std::fs::write(dir.path().join("a.md"), "User: hi\n").expect("write md");
+
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+    index_root(&mut conn, dir.path(), false).expect("index");
+
+    let options = IndexOptions {
+        full: true,
+        interrupt: Some(Arc::new(AtomicBool::new(true))),
+        ..Default::default()
+    };
+    let stats = index_root_with(&mut conn, dir.path(), &options).expect("interrupted index");
+    assert!(stats.interrupted);
+    assert_eq!(stats.indexed, 0);
+    assert_eq!(stats.removed, 0);
+
+    let count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM sessions", [], |row| row.get(0))
+        .expect("count");
+    assert_eq!(count, 1);
+}
+
 #[test]
 fn removes_stale_data_on_parse_failure() {
     let dir = tempfile::tempdir().expect("tempdir");
@@ -70,3 +273,553 @@ fn removes_stale_data_on_parse_failure() {
         .expect("count");
     assert_eq!(count, 0);
 }
+
+#[test]
+fn persists_repo_info_for_git_workspaces() {
+    let sessions = tempfile::tempdir().expect("sessions dir");
+    let repos = tempfile::tempdir().expect("repos dir");
+    let repo = repos.path().join("mmem-fixture-repo");
+    std::fs::create_dir(&repo).expect("repo dir");
+    let status = std::process::Command::new("git")
+        .args(["init", "--quiet"])
+        .current_dir(&repo)
+        .status()
+        .expect("git init");
+    assert!(status.success());
+
+    let workspace = repo.to_string_lossy();
+    std::fs::write(
+        sessions.path().join("in-repo.jsonl"),
+        format!(
+            "{{\"type\":\"session_meta\",\"workspace\":{workspace:?}}}\n{{\"role\":\"user\",\"content\":\"alpha\"}}\n"
+        ),
+    )
+    .expect("write repo session");
+    std::fs::write(
+        sessions.path().join("elsewhere.jsonl"),
+        "{\"role\":\"user\",\"content\":\"alpha\"}\n",
+    )
+    .expect("write other session");
+
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+    index_root(&mut conn, sessions.path(), false).expect("index");
+
+    let filters = FindFilters {
+        repo: Some("mmem-fixture-repo".to_string()),
+        limit: 10,
+        ..Default::default()
+    };
+    let sessions_hits = find_sessions(&conn, "alpha", &filters).expect("session query");
+    assert_eq!(sessions_hits.len(), 1);
+    assert!(sessions_hits[0].path.ends_with("in-repo.jsonl"));
+    assert_eq!(
+        sessions_hits[0].repo_name.as_deref(),
+        Some("mmem-fixture-repo")
+    );
+
+    let message_hits = find_messages(&conn, "alpha", &filters).expect("message query");
+    assert_eq!(message_hits.len(), 1);
+    assert!(message_hits[0].repo_root.is_some());
+}
+
+#[test]
+fn detects_renamed_sessions() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let original = dir.path().join("a.jsonl");
+    std::fs::write(&original, "{\"role\":\"user\",\"content\":\"hello\"}\n").expect("write");
+
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+    index_root(&mut conn, dir.path(), false).expect("index");
+
+    let renamed = dir.path().join("a-exported.jsonl");
+    std::fs::rename(&original, &renamed).expect("rename");
+
+    let stats = index_root(&mut conn, dir.path(), false).expect("reindex");
+    assert_eq!(stats.renamed, 1);
+    assert_eq!(stats.indexed, 0);
+    assert_eq!(stats.removed, 0);
+
+    let path: String = conn
+        .query_row("SELECT path FROM sessions", [], |row| row.get(0))
+        .expect("path");
+    assert_eq!(path, renamed.to_string_lossy());
+    let message_path: String = conn
+        .query_row("SELECT session_path FROM messages", [], |row| row.get(0))
+        .expect("message path");
+    assert_eq!(message_path, path);
+
+    let hits = find_messages(
+        &conn,
+        "hello",
+        &FindFilters {
+            limit: 5,
+            ..Default::default()
+        },
+    )
+    .expect("query");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].path, path);
+}
+
+#[test]
+fn excludes_system_prompt_from_message_search_by_default() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    std::fs::write(
+        dir.path().join("a.jsonl"),
+        concat!(
+            r#"{"type":"response_item","payload":{"type":"message","role":"system","content":"you are a zebra expert"}}"#,
+            "\n",
+            r#"{"type":"response_item","payload":{"type":"message","role":"user","content":"tell me about zebras"}}"#,
+            "\n",
+        ),
+    )
+    .expect("write jsonl");
+
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+    index_root(&mut conn, dir.path(), false).expect("index");
+
+    let filters = FindFilters {
+        role: None,
+        ..Default::default()
+    };
+    let hits = find_messages(&conn, "expert", &filters).expect("find");
+    assert!(hits.is_empty());
+    let hits = find_messages(&conn, "zebras", &filters).expect("find");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].turn_index, 1);
+
+    let prompt: Option<String> = conn
+        .query_row("SELECT system_prompt FROM sessions", [], |row| row.get(0))
+        .expect("system prompt");
+    assert_eq!(prompt.as_deref(), Some("you are a zebra expert"));
+
+    let options = IndexOptions {
+        full: true,
+        index_system: true,
+        ..Default::default()
+    };
+    index_root_with(&mut conn, dir.path(), &options).expect("reindex");
+    let hits = find_messages(&conn, "expert", &filters).expect("find");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].role.as_deref(), Some("system"));
+}
+
+#[test]
+fn stores_session_preview_when_requested() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let text = "walrus ".repeat(100);
+    std::fs::write(
+        dir.path().join("a.jsonl"),
+        format!(
+            r#"{{"type":"response_item","payload":{{"type":"message","role":"user","content":"{}"}}}}"#,
+            text.trim_end()
+        ),
+    )
+    .expect("write jsonl");
+
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+    index_root(&mut conn, dir.path(), false).expect("index");
+    let hits = find_sessions(&conn, "walrus", &FindFilters::default()).expect("find");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].preview, None);
+
+    let options = IndexOptions {
+        full: true,
+        preview_chars: Some(500),
+        ..Default::default()
+    };
+    index_root_with(&mut conn, dir.path(), &options).expect("reindex");
+    let hits = find_sessions(&conn, "walrus", &FindFilters::default()).expect("find");
+    let preview = hits[0].preview.as_deref().expect("preview");
+    assert_eq!(preview.chars().count(), 500);
+    assert!(preview.starts_with("[user] walrus walrus"));
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn indexes_zstd_compressed_sessions() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let jsonl = "{\"type\":\"response_item\",\"payload\":{\"type\":\"message\",\
+                 \"role\":\"user\",\"content\":\"archived hello\"}}\n";
+    let compressed = zstd::encode_all(jsonl.as_bytes(), 0).expect("compress");
+    std::fs::write(dir.path().join("a.jsonl.zst"), compressed).expect("write zst");
+
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let stats = index_root(&mut conn, dir.path(), false).expect("index");
+    assert_eq!(stats.indexed, 1);
+    assert_eq!(stats.parse_errors, 0);
+
+    let hits = find_messages(&conn, "archived", &FindFilters::default()).expect("find");
+    assert_eq!(hits.len(), 1);
+    assert!(hits[0].path.ends_with("a.jsonl.zst"));
+
+    let stats = index_root(&mut conn, dir.path(), false).expect("reindex");
+    assert_eq!(stats.skipped, 1);
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn indexes_gzip_compressed_sessions() {
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().expect("tempdir");
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder
+        .write_all(b"{\"role\":\"user\",\"content\":\"archived hello\"}\n")
+        .expect("compress");
+    std::fs::write(
+        dir.path().join("a.jsonl.gz"),
+        encoder.finish().expect("finish"),
+    )
+    .expect("write gz");
+    std::fs::write(dir.path().join("b.md.gz"), "not gzip").expect("write corrupt");
+
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let stats = index_root(&mut conn, dir.path(), false).expect("index");
+    assert_eq!(stats.scanned, 2);
+    assert_eq!(stats.indexed, 1);
+    assert_eq!(stats.parse_errors, 1);
+
+    let hits = find_messages(&conn, "archived", &FindFilters::default()).expect("find");
+    assert_eq!(hits.len(), 1);
+    assert!(hits[0].path.ends_with("a.jsonl.gz"));
+    // The streamed parse hashes the compressed file, as a buffered read would.
+    let hash: String = conn
+        .query_row("SELECT hash FROM sessions", [], |row| row.get(0))
+        .expect("hash");
+    let compressed = std::fs::read(dir.path().join("a.jsonl.gz")).expect("read gz");
+    assert_eq!(hash, mmem::util::content_hash(&compressed));
+
+    std::fs::remove_file(dir.path().join("a.jsonl.gz")).expect("remove");
+    let stats = index_root(&mut conn, dir.path(), false).expect("reindex");
+    assert_eq!(stats.removed, 1);
+}
+
+#[test]
+fn reports_missing_root() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let missing = dir.path().join("sessions");
+
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let err = index_root(&mut conn, &missing, false).expect_err("missing root");
+    assert!(matches!(&err, ScanError::RootNotFound { path } if path == &missing));
+    assert!(err.to_string().contains("pass --root"));
+}
+
+#[test]
+fn imports_custom_format_and_keeps_it_across_scans() {
+    let sessions = tempfile::tempdir().expect("sessions");
+    let exports = tempfile::tempdir().expect("exports");
+    let export = exports.path().join("chat.json");
+    std::fs::write(
+        &export,
+        r#"{"conversation": [{"speaker": "user", "body": "imported walrus question"}]}"#,
+    )
+    .expect("write export");
+    let spec: ParseSpec = serde_json::from_str(
+        r#"{"messages_path": "$.conversation", "role_field": "speaker", "text_field": "body"}"#,
+    )
+    .expect("spec");
+
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+    import_file(&mut conn, &export, &spec, &IndexOptions::default()).expect("import");
+
+    let hits = find_messages(&conn, "walrus", &FindFilters::default()).expect("find");
+    assert_eq!(hits.len(), 1);
+
+    let stats = index_root(&mut conn, sessions.path(), false).expect("index");
+    assert_eq!(stats.removed, 0);
+    let hits = find_messages(&conn, "walrus", &FindFilters::default()).expect("find");
+    assert_eq!(hits.len(), 1);
+}
+
+#[test]
+fn links_continuation_files_into_one_logical_session() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let first = dir.path().join("1700000000001_part1.jsonl");
+    let second = dir.path().join("1700000000002_part2.jsonl");
+    std::fs::write(
+        &first,
+        concat!(
+            "{\"role\":\"user\",\"content\":\"deploy the widget\"}\n",
+            "{\"role\":\"assistant\",\"content\":\"widget deploy started\"}\n",
+        ),
+    )
+    .expect("write first");
+    std::fs::write(
+        &second,
+        concat!(
+            "{\"type\":\"session_meta\",\"continues_from\":\"1700000000001\"}\n",
+            "{\"role\":\"user\",\"content\":\"is the widget deploy done\"}\n",
+        ),
+    )
+    .expect("write second");
+
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+    index_root(&mut conn, dir.path(), false).expect("index");
+
+    let parent: Option<String> = conn
+        .query_row(
+            "SELECT parent_path FROM sessions WHERE path = ?1",
+            [second.to_string_lossy()],
+            |row| row.get(0),
+        )
+        .expect("parent");
+    assert_eq!(parent.as_deref(), Some(first.to_string_lossy().as_ref()));
+
+    let mut filters = FindFilters {
+        limit: 10,
+        ..Default::default()
+    };
+    assert_eq!(
+        find_sessions(&conn, "widget", &filters)
+            .expect("sessions")
+            .len(),
+        2
+    );
+
+    filters.merge_continuations = true;
+    assert_eq!(
+        find_sessions(&conn, "widget", &filters)
+            .expect("merged")
+            .len(),
+        1
+    );
+
+    // A weaker standalone hit still fills the second slot even when both
+    // chain files rank above it.
+    std::fs::write(
+        dir.path().join("1700000000003_other.jsonl"),
+        "{\"role\":\"user\",\"content\":\"one widget among many other unrelated words\"}\n",
+    )
+    .expect("write other");
+    index_root(&mut conn, dir.path(), false).expect("reindex");
+    filters.limit = 2;
+    assert_eq!(
+        find_sessions(&conn, "widget", &filters)
+            .expect("refilled")
+            .len(),
+        2
+    );
+    filters.limit = 10;
+
+    filters.role = Some("user".to_string());
+    filters.around = 1;
+    let hits = find_messages(&conn, "done", &filters).expect("messages");
+    assert_eq!(hits.len(), 1);
+    let context = hits[0].context.as_ref().expect("context");
+    assert_eq!(context[0].text, "widget deploy started");
+    assert_eq!(
+        context[0].path.as_deref(),
+        Some(first.to_string_lossy().as_ref())
+    );
+}
+
+#[test]
+fn filters_sessions_by_shell_commands_run() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let call = |arguments: &str| {
+        format!(
+            "{{\"role\":\"assistant\",\"content\":[{{\"type\":\"text\",\"text\":\"running checks\"}},\
+             {{\"type\":\"toolCall\",\"name\":\"bash\",\"arguments\":{arguments}}}]}}\n"
+        )
+    };
+    std::fs::write(
+        dir.path().join("a.jsonl"),
+        call(r#"{"command":"cargo test --workspace"}"#),
+    )
+    .expect("write a");
+    std::fs::write(
+        dir.path().join("b.jsonl"),
+        call(r#""{\"cmd\":[\"npm\",\"test\"]}""#),
+    )
+    .expect("write b");
+
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+    index_root(&mut conn, dir.path(), false).expect("index");
+
+    let mut filters = FindFilters {
+        limit: 10,
+        command_contains: Some("cargo test".to_string()),
+        ..Default::default()
+    };
+    let hits = find_sessions(&conn, "checks", &filters).expect("sessions");
+    assert_eq!(hits.len(), 1);
+    assert!(hits[0].path.ends_with("a.jsonl"));
+
+    filters.command_contains = Some("npm test".to_string());
+    let hits = find_sessions(&conn, "checks", &filters).expect("sessions");
+    assert_eq!(hits.len(), 1);
+    assert!(hits[0].path.ends_with("b.jsonl"));
+}
+
+#[test]
+fn message_hits_carry_source_line() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    std::fs::write(
+        dir.path().join("a.jsonl"),
+        "{\"type\":\"session\",\"title\":\"lines\"}\n\n\
+         {\"role\":\"user\",\"content\":\"where is alpha\"}\n\
+         {\"role\":\"assistant\",\"content\":\"alpha lives here\"}\n",
+    )
+    .expect("write");
+
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+    index_root(&mut conn, dir.path(), false).expect("index");
+
+    let mut filters = FindFilters {
+        limit: 10,
+        ..Default::default()
+    };
+    let mut lines: Vec<(i64, Option<i64>)> = find_messages(&conn, "alpha", &filters)
+        .expect("find")
+        .iter()
+        .map(|hit| (hit.turn_index, hit.line))
+        .collect();
+    lines.sort();
+    assert_eq!(lines, vec![(0, Some(3)), (1, Some(4))]);
+
+    filters.per_session_limit = Some(1);
+    filters.role = Some("assistant".to_string());
+    let hits = find_messages(&conn, "alpha", &filters).expect("per session");
+    assert_eq!(hits[0].line, Some(4));
+}
+
+#[test]
+fn trigram_tokenizer_matches_short_cjk_queries() {
+    let root = tempfile::tempdir().expect("tempdir");
+    std::fs::copy(
+        "tests/fixtures/session_cjk.jsonl",
+        root.path().join("cjk.jsonl"),
+    )
+    .expect("copy fixture");
+    let db = tempfile::tempdir().expect("db dir");
+    let db_path = db.path().join("index.sqlite");
+    let filters = FindFilters {
+        limit: 10,
+        ..Default::default()
+    };
+
+    // unicode61 keeps each unspaced CJK run as one token.
+    let mut conn = Connection::open(&db_path).expect("db");
+    init_schema(&conn).expect("schema");
+    index_root(&mut conn, root.path(), false).expect("index");
+    assert!(
+        find_messages(&conn, "东京", &filters)
+            .expect("find")
+            .is_empty()
+    );
+
+    // Switching rebuilds the existing index without re-reading any files.
+    init_schema_with(&conn, Some(FtsTokenizer::Trigram)).expect("retokenize");
+    drop(conn);
+
+    let conn = open_read_only(&db_path).expect("read only");
+    let hits = find_messages(&conn, "东京", &filters).expect("two characters");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].role.as_deref(), Some("user"));
+    let hits = find_messages(&conn, "移行", &filters).expect("japanese");
+    assert_eq!(hits[0].role.as_deref(), Some("assistant"));
+    assert_eq!(
+        find_messages(&conn, "数据库迁移", &filters)
+            .expect("substring")
+            .len(),
+        1
+    );
+    assert_eq!(
+        find_sessions(&conn, "东京 移行", &filters)
+            .expect("sessions")
+            .len(),
+        1
+    );
+    assert!(
+        find_messages(&conn, "大阪", &filters)
+            .expect("absent")
+            .is_empty()
+    );
+}
+
+#[test]
+fn stores_absolute_paths_for_a_relative_root() {
+    std::fs::create_dir_all("target").expect("target dir");
+    let dir = tempfile::tempdir_in("target").expect("tempdir");
+    let cwd = std::env::current_dir().expect("cwd");
+    let relative = dir.path().strip_prefix(&cwd).expect("under cwd");
+    std::fs::write(relative.join("a.md"), "User: hi\nAssistant: hey\n").expect("write md");
+
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+    let stats = index_root(&mut conn, relative, false).expect("index");
+    assert_eq!(stats.indexed, 1);
+
+    let path: String = conn
+        .query_row("SELECT path FROM sessions", [], |row| row.get(0))
+        .expect("path");
+    assert!(std::path::Path::new(&path).is_absolute(), "{path}");
+    assert!(std::path::Path::new(&path).is_file());
+
+    // The same directory spelled absolutely is the same set of sessions.
+    let absolute = std::fs::canonicalize(dir.path()).expect("canonicalize");
+    let stats = index_root(&mut conn, &absolute, false).expect("reindex");
+    assert_eq!((stats.skipped, stats.indexed, stats.removed), (1, 0, 0));
+
+    // A relative path stored by an older index is replaced, not duplicated.
+    let legacy = relative.join("a.md").to_string_lossy().to_string();
+    conn.execute("UPDATE sessions SET path = ?1", [&legacy])
+        .expect("legacy path");
+    let stats = index_root(&mut conn, relative, false).expect("migrate");
+    assert_eq!((stats.indexed, stats.removed), (1, 1));
+    let paths: Vec<String> = conn
+        .prepare("SELECT path FROM sessions")
+        .expect("prepare")
+        .query_map([], |row| row.get(0))
+        .expect("query")
+        .collect::<Result<_, _>>()
+        .expect("paths");
+    assert_eq!(paths, vec![path]);
+}
+
+#[test]
+fn upgrading_from_an_older_schema_reparses_markdown_sessions() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    std::fs::write(
+        dir.path().join("a.md"),
+        "User: hi\nmore of it\nAssistant: hey\n",
+    )
+    .expect("write md");
+    std::fs::write(
+        dir.path().join("b.jsonl"),
+        "{\"role\":\"user\",\"content\":\"hello\"}\n",
+    )
+    .expect("write jsonl");
+
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+    index_root(&mut conn, dir.path(), false).expect("first index");
+    let stats = index_root(&mut conn, dir.path(), false).expect("second index");
+    assert_eq!(stats.skipped, 2);
+
+    // Markdown split rules changed in schema version 7.
+    conn.pragma_update(None, "user_version", 6)
+        .expect("downgrade");
+    init_schema(&conn).expect("migrate");
+    let stats = index_root(&mut conn, dir.path(), false).expect("after upgrade");
+    assert_eq!((stats.indexed, stats.skipped), (1, 1));
+    let stats = index_root(&mut conn, dir.path(), false).expect("settled");
+    assert_eq!(stats.skipped, 2);
+}
@@ -0,0 +1,49 @@
+use mmem::query::FindFilters;
+use mmem::scan::index_root;
+use mmem::search::Searcher;
+
+#[test]
+fn searcher_creates_the_schema_and_finds_indexed_sessions() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let sessions = dir.path().join("sessions");
+    std::fs::create_dir(&sessions).expect("sessions dir");
+    std::fs::write(
+        sessions.join("a.md"),
+        "User: how do I rotate the signing key\nAssistant: run the rotate task\n",
+    )
+    .expect("write session");
+
+    let db_path = dir.path().join("nested/mmem.sqlite");
+    let mut searcher = Searcher::open(&db_path).expect("open");
+    let filters = FindFilters {
+        limit: 10,
+        ..Default::default()
+    };
+    assert!(
+        searcher
+            .search_sessions("rotate", &filters)
+            .expect("empty")
+            .is_empty()
+    );
+
+    index_root(searcher.connection_mut(), &sessions, false).expect("index");
+    let sessions_hits = searcher
+        .search_sessions("signing key", &filters)
+        .expect("sessions");
+    assert_eq!(sessions_hits.len(), 1);
+    let message_hits = searcher
+        .search_messages("rotate task", &filters)
+        .expect("messages");
+    assert_eq!(message_hits.len(), 1);
+    assert_eq!(message_hits[0].turn_index, 1);
+
+    // A second handle on the same file sees the data without re-indexing.
+    let reopened = Searcher::open(&db_path).expect("reopen");
+    assert_eq!(
+        reopened
+            .search_sessions("signing", &filters)
+            .expect("find")
+            .len(),
+        1
+    );
+}
@@ -1,7 +1,41 @@
-use mmem::session::{SessionError, extract_tool_calls, load_entry_by_turn, resolve_session_path, scan_tool_calls};
+use mmem::session::{
+    SessionError, ToolScanOptions, extract_tool_calls, load_entry_by_line, load_entry_by_line_with,
+    load_entry_by_turn, resolve_session_path, scan_tool_calls, scan_tool_calls_with,
+    session_id_from_path,
+};
 use std::path::Path;
 use tempfile::tempdir;
 
+#[test]
+fn pairs_tool_calls_with_their_output_entries() {
+    let path = Path::new("tests/fixtures/session_function_output.jsonl");
+    let matches = scan_tool_calls(path, &["bash"], None).expect("scan tool calls");
+    let outputs: Vec<(Option<&str>, Option<&str>)> = matches
+        .iter()
+        .map(|item| (item.tool.id.as_deref(), item.output.as_deref()))
+        .collect();
+    assert_eq!(
+        outputs,
+        [
+            (
+                Some("call_1"),
+                Some("error[E0432]: unresolved import `serde_yaml`")
+            ),
+            (Some("call_2"), Some("Cargo.toml\nsrc")),
+        ]
+    );
+
+    // Stopping at the limit still finds the output further down.
+    let first = scan_tool_calls(path, &["bash"], Some(1)).expect("limited scan");
+    assert_eq!(first.len(), 1);
+    assert!(first[0].output.is_some());
+
+    // Result entries count as turns, matching the index.
+    let entry = load_entry_by_turn(path, 2).expect("turn 2");
+    assert_eq!(entry.role.as_deref(), Some("tool"));
+    assert_eq!(entry.line, 3);
+}
+
 #[test]
 fn loads_turn_and_extracts_tool_calls() {
     let path = Path::new("tests/fixtures/session_tools.jsonl");
@@ -18,7 +52,7 @@ fn loads_turn_and_extracts_tool_calls() {
 #[test]
 fn scans_tool_calls_with_filter() {
     let path = Path::new("tests/fixtures/session_tools.jsonl");
-    let matches = scan_tool_calls(path, Some("read"), None).expect("scan tool calls");
+    let matches = scan_tool_calls(path, &["read"], None).expect("scan tool calls");
 
     assert_eq!(matches.len(), 1);
     assert_eq!(matches[0].line, 2);
@@ -26,6 +60,107 @@ fn scans_tool_calls_with_filter() {
     assert_eq!(matches[0].tool.name, "read");
 }
 
+#[test]
+fn scans_tool_calls_matching_any_of_several_names() {
+    let dir = tempdir().expect("tempdir");
+    let path = dir.path().join("session.jsonl");
+    let call = |name: &str| {
+        format!(
+            "{{\"type\":\"message\",\"message\":{{\"role\":\"assistant\",\"content\":[{{\"type\":\"toolCall\",\"name\":\"{name}\",\"arguments\":{{}}}}]}}}}\n"
+        )
+    };
+    let contents = [call("read"), call("bash"), call("Write")].concat();
+    std::fs::write(&path, contents).expect("write session");
+
+    let matches = scan_tool_calls(&path, &["read", "write"], None).expect("scan tool calls");
+    let names: Vec<&str> = matches.iter().map(|m| m.tool.name.as_str()).collect();
+    assert_eq!(names, ["read", "Write"]);
+
+    let all = scan_tool_calls(&path, &[], None).expect("scan all");
+    assert_eq!(all.len(), 3);
+}
+
+#[test]
+fn per_tool_limit_samples_each_tool() {
+    let dir = tempdir().expect("tempdir");
+    let path = dir.path().join("session.jsonl");
+    let call = |name: &str| {
+        format!(
+            "{{\"type\":\"message\",\"message\":{{\"role\":\"assistant\",\"content\":[{{\"type\":\"toolCall\",\"name\":\"{name}\",\"arguments\":{{}}}}]}}}}\n"
+        )
+    };
+    let contents = [
+        call("read"),
+        call("read"),
+        call("Read"),
+        call("bash"),
+        call("read"),
+        call("write"),
+        call("bash"),
+        call("bash"),
+    ]
+    .concat();
+    std::fs::write(&path, contents).expect("write session");
+
+    let per_tool = |cap| ToolScanOptions {
+        per_tool_limit: Some(cap),
+        ..Default::default()
+    };
+    let sample = scan_tool_calls_with(&path, &[], &per_tool(2)).expect("scan");
+    let names: Vec<&str> = sample.iter().map(|m| m.tool.name.as_str()).collect();
+    assert_eq!(names, ["read", "read", "bash", "write", "bash"]);
+
+    let reads = scan_tool_calls_with(&path, &["read"], &per_tool(1)).expect("scan reads");
+    assert_eq!(reads.len(), 1);
+    assert_eq!(reads[0].line, 1);
+
+    // Paging applies to the capped listing.
+    let page = ToolScanOptions {
+        limit: Some(2),
+        offset: 2,
+        ..per_tool(2)
+    };
+    let names: Vec<String> = scan_tool_calls_with(&path, &[], &page)
+        .expect("page")
+        .into_iter()
+        .map(|m| m.tool.name)
+        .collect();
+    assert_eq!(names, ["bash", "write"]);
+
+    let past_end = ToolScanOptions {
+        offset: 5,
+        ..per_tool(2)
+    };
+    assert!(
+        scan_tool_calls_with(&path, &[], &past_end)
+            .expect("past end")
+            .is_empty()
+    );
+}
+
+#[test]
+fn line_entries_can_backfill_role_and_timestamp() {
+    let dir = tempdir().expect("tempdir");
+    let path = dir.path().join("session.jsonl");
+    std::fs::write(
+        &path,
+        concat!(
+            r#"{"type":"message","timestamp":"2024-01-01T00:00:05Z","message":{"role":"user","content":"ask"}}"#,
+            "\n",
+            r#"{"type":"event","name":"heartbeat"}"#,
+            "\n",
+        ),
+    )
+    .expect("write session");
+
+    let raw = load_entry_by_line(&path, 2).expect("raw line");
+    assert_eq!(raw.role, None);
+    assert_eq!(raw.timestamp, None);
+
+    let entry = load_entry_by_line_with(&path, 2, true).expect("backfilled line");
+    assert_eq!(entry.role.as_deref(), Some("user"));
+    assert_eq!(entry.timestamp.as_deref(), Some("2024-01-01T00:00:05Z"));
+}
 
 #[test]
 fn resolves_session_path_by_prefix() {
@@ -38,6 +173,60 @@ fn resolves_session_path_by_prefix() {
     assert_eq!(resolved, file);
 }
 
+#[test]
+fn resolves_session_prefix_in_nested_workspace_directories() {
+    let dir = tempdir().expect("tempdir");
+    let root = dir.path();
+    let nested = root.join("--Users-alice-project--").join("2024");
+    std::fs::create_dir_all(&nested).expect("nested dirs");
+    let file = nested.join("1766632198584_test.jsonl");
+    std::fs::write(&file, "{}\n").expect("write file");
+
+    let resolved = resolve_session_path("1766632198584", root).expect("resolve");
+    assert_eq!(resolved, file);
+
+    // The same prefix in another workspace makes it ambiguous.
+    let other = root.join("--Users-alice-other--");
+    std::fs::create_dir_all(&other).expect("other dir");
+    std::fs::write(other.join("1766632198584_copy.jsonl"), "{}\n").expect("write copy");
+    let err = resolve_session_path("1766632198584", root).expect_err("ambiguous");
+    assert!(matches!(err, SessionError::Ambiguous { .. }));
+}
+
+#[test]
+fn session_id_round_trips_through_resolve() {
+    let dir = tempdir().expect("tempdir");
+    let root = dir.path();
+    let file = root.join("1766632198584_test.jsonl");
+    std::fs::write(&file, "{}\n").expect("write file");
+
+    let id = session_id_from_path(file.to_str().expect("utf8 path")).expect("session id");
+    assert_eq!(id, "1766632198584");
+    assert_eq!(resolve_session_path(&id, root).expect("resolve"), file);
+
+    assert_eq!(
+        session_id_from_path("/tmp/notes.jsonl").as_deref(),
+        Some("notes")
+    );
+    assert_eq!(
+        session_id_from_path("/tmp/1766632198584.jsonl").as_deref(),
+        Some("1766632198584")
+    );
+}
+
+#[test]
+fn session_id_keeps_dated_file_names_whole() {
+    let dir = tempdir().expect("tempdir");
+    let root = dir.path();
+    let file = root.join("2024-01-01-foo.jsonl");
+    std::fs::write(&file, "{}\n").expect("write file");
+    std::fs::write(root.join("2024-02-14-bar.jsonl"), "{}\n").expect("write other");
+
+    let id = session_id_from_path(file.to_str().expect("utf8 path")).expect("session id");
+    assert_eq!(id, "2024-01-01-foo");
+    assert_eq!(resolve_session_path(&id, root).expect("resolve"), file);
+}
+
 #[test]
 fn resolve_session_path_reports_ambiguous_prefix() {
     let dir = tempdir().expect("tempdir");
@@ -57,3 +246,15 @@ fn resolve_session_path_reports_missing_prefix() {
     let err = resolve_session_path("nope", root).expect_err("missing");
     assert!(matches!(err, SessionError::NotFound { .. }));
 }
+
+#[test]
+fn loads_turns_from_file_with_bom() {
+    let dir = tempdir().expect("tempdir");
+    let path = dir.path().join("bom.jsonl");
+    let contents = std::fs::read_to_string("tests/fixtures/session_tools.jsonl").expect("fixture");
+    std::fs::write(&path, format!("\u{feff}{contents}")).expect("write");
+
+    let entry = load_entry_by_turn(&path, 0).expect("turn 0");
+    assert_eq!(entry.line, 2);
+    assert_eq!(extract_tool_calls(&entry.value)[0].name, "read");
+}
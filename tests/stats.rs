@@ -1,6 +1,7 @@
-use mmem::index::{init_schema, upsert_session};
-use mmem::model::SessionRecord;
-use mmem::stats::load_stats;
+use mmem::index::configure_connection;
+use mmem::index::{init_schema, remove_session, replace_messages_tx, upsert_session};
+use mmem::model::{MessageRecord, SessionRecord};
+use mmem::stats::{db_footprint, load_repo_activity, load_stats, load_tool_usage};
 use rusqlite::Connection;
 
 fn record(path: &str, last_message_at: &str) -> SessionRecord {
@@ -15,11 +16,17 @@ fn record(path: &str, last_message_at: &str) -> SessionRecord {
         workspace: Some("ws".to_string()),
         title: Some("title".to_string()),
         message_count: 2,
+        user_message_count: 0,
+        assistant_message_count: 0,
         snippet: "snippet".to_string(),
         content: "alpha".to_string(),
         repo_root: None,
         repo_name: None,
         branch: None,
+        system_prompt: None,
+        preview: None,
+        parent_path: None,
+        commands: None,
     }
 }
 
@@ -46,3 +53,90 @@ fn stats_report_counts_and_bounds() {
     );
     assert!(stats.parse_failures.is_none());
 }
+
+fn tool_message(turn_index: i64, tools: &[&str]) -> MessageRecord {
+    MessageRecord {
+        turn_index,
+        role: Some("assistant".to_string()),
+        timestamp: None,
+        text: String::new(),
+        tools: tools.iter().map(|name| name.to_string()).collect(),
+        line: None,
+    }
+}
+
+#[test]
+fn tool_usage_ranks_across_sessions() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    for path in ["/tmp/a.jsonl", "/tmp/b.jsonl"] {
+        upsert_session(&mut conn, &record(path, "2024-01-01T00:00:01Z")).expect("insert");
+    }
+
+    let tx = conn.transaction().expect("tx");
+    replace_messages_tx(
+        &tx,
+        "/tmp/a.jsonl",
+        &[
+            tool_message(0, &["read", "bash"]),
+            tool_message(1, &["read"]),
+        ],
+    )
+    .expect("messages a");
+    replace_messages_tx(&tx, "/tmp/b.jsonl", &[tool_message(0, &["edit", "read"])])
+        .expect("messages b");
+    tx.commit().expect("commit");
+
+    let usage = load_tool_usage(&conn).expect("usage");
+    let ranked: Vec<(&str, i64)> = usage.iter().map(|u| (u.tool.as_str(), u.count)).collect();
+    assert_eq!(ranked, vec![("read", 3), ("bash", 1), ("edit", 1)]);
+
+    remove_session(&mut conn, "/tmp/a.jsonl").expect("remove");
+    let usage = load_tool_usage(&conn).expect("usage");
+    let ranked: Vec<(&str, i64)> = usage.iter().map(|u| (u.tool.as_str(), u.count)).collect();
+    assert_eq!(ranked, vec![("edit", 1), ("read", 1)]);
+}
+
+#[test]
+fn measures_database_and_wal_size() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let db_path = dir.path().join("mmem.sqlite");
+    let conn = Connection::open(&db_path).expect("db");
+    configure_connection(&conn).expect("configure");
+    init_schema(&conn).expect("schema");
+
+    let footprint = db_footprint(&db_path).expect("footprint");
+    assert_eq!(footprint.db_path, db_path);
+    assert!(footprint.db_size_bytes > 0);
+    assert!(footprint.wal_size_bytes.is_some());
+
+    let json = serde_json::to_value(load_stats(&conn).expect("stats")).expect("json");
+    assert!(json.get("db_size_bytes").is_none());
+}
+
+#[test]
+fn repo_activity_groups_sessions_by_repo_name() {
+    let mut conn = Connection::open_in_memory().expect("db");
+    init_schema(&conn).expect("schema");
+
+    let in_repo = |path: &str, repo: Option<&str>, messages: i64| SessionRecord {
+        repo_name: repo.map(str::to_string),
+        message_count: messages,
+        ..record(path, "2024-01-01T00:00:01Z")
+    };
+    upsert_session(&mut conn, &in_repo("/tmp/a.jsonl", Some("mmem"), 10)).expect("a");
+    upsert_session(&mut conn, &in_repo("/tmp/b.jsonl", Some("mmem"), 4)).expect("b");
+    upsert_session(&mut conn, &in_repo("/tmp/c.jsonl", Some("website"), 30)).expect("c");
+    upsert_session(&mut conn, &in_repo("/tmp/d.jsonl", None, 2)).expect("d");
+
+    let repos = load_repo_activity(&conn).expect("repos");
+    let summary: Vec<(&str, i64, i64)> = repos
+        .iter()
+        .map(|repo| (repo.repo.as_str(), repo.sessions, repo.messages))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![("website", 1, 30), ("mmem", 2, 14), ("(unknown)", 1, 2)]
+    );
+}